@@ -0,0 +1,337 @@
+use std::str::FromStr;
+use actix_web::{web, HttpResponse, Responder};
+use kaspa_addresses::{AddressError, Prefix, Version};
+use kaspa_consensus_core::network::NetworkType;
+use kaspa_consensus_core::tx::ScriptPublicKey;
+use kaspa_rpc_core::api::rpc::RpcApi;
+use kaspa_rpc_core::RpcAddress;
+use kaspa_txscript::script_class::ScriptClass;
+use kaspa_txscript::{extract_script_pub_key_address, pay_to_address_script};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::logging::ErrorBody;
+use crate::metrics;
+
+const NETWORKS: [NetworkType; 4] = [NetworkType::Mainnet, NetworkType::Testnet, NetworkType::Devnet, NetworkType::Simnet];
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ScriptQuery {
+    // Script public key version, 0 unless given
+    version: Option<u16>,
+    // mainnet, testnet, devnet or simnet, defaults to the network of the node
+    network: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct AddressInfo {
+    address: String,
+    valid: bool,
+    prefix: Option<String>,
+    network: Option<String>,
+    // Whether the address belongs to the network of the connected node, unknown when the node is unreachable
+    matches_node_network: Option<bool>,
+    version: Option<u8>,
+    // P2PK Schnorr, P2PK ECDSA or P2SH
+    kind: Option<String>,
+    payload: Option<String>,
+    script_public_key: Option<String>,
+    script_public_key_version: Option<u16>,
+    script_class: Option<String>,
+    // Problems found while parsing, empty for a well formed address of the node's network
+    diagnostics: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ScriptAddress {
+    script_public_key: String,
+    script_public_key_version: u16,
+    script_class: String,
+    address: String,
+    network: String,
+}
+
+fn kind(version: Version) -> &'static str {
+    match version {
+        Version::PubKey => "P2PK Schnorr",
+        Version::PubKeyECDSA => "P2PK ECDSA",
+        Version::ScriptHash => "P2SH",
+    }
+}
+
+fn payload_len(version: Version) -> usize {
+    match version {
+        Version::PubKey | Version::ScriptHash => 32,
+        Version::PubKeyECDSA => 33,
+    }
+}
+
+fn network_of(prefix: Prefix) -> Option<NetworkType> {
+    NETWORKS.into_iter().find(|network| Prefix::from(*network) == prefix)
+}
+
+// Network of the connected node, None when it can't be reached
+async fn node_network() -> Option<NetworkType> {
+    let client = crate::connect_node().await.ok()?;
+    let network = metrics::rpc("get_current_network", client.get_current_network()).await.ok();
+    let _ = client.disconnect().await;
+    network
+}
+
+#[utoipa::path(
+    get,
+    path = "/addresses/{addr}",
+    tag = "addresses",
+    params(("addr" = String, Path, description = "Address with its network prefix")),
+    responses((status = 200, description = "Parsed address, or the reasons it is invalid", body = AddressInfo))
+)]
+pub async fn get_address(path: web::Path<String>) -> impl Responder {
+    let input = path.into_inner();
+    let parsed = RpcAddress::try_from(input.trim());
+    // Only a parsed address can be compared against the node's network
+    let node = match parsed {
+        Ok(_) => node_network().await,
+        Err(_) => None,
+    };
+    HttpResponse::Ok().json(describe(&input, parsed, node))
+}
+
+fn describe(input: &str, parsed: Result<RpcAddress, AddressError>, node: Option<NetworkType>) -> AddressInfo {
+    let mut diagnostics = Vec::new();
+    if input.trim() != input {
+        diagnostics.push("Address has leading or trailing whitespace".to_string());
+    }
+    if !input.contains(':') {
+        diagnostics.push("Address is missing its network prefix, e.g. xenom:".to_string());
+    }
+    if input.chars().any(|c| c.is_ascii_uppercase()) {
+        diagnostics.push("Address contains upper case characters".to_string());
+    }
+
+    let address = match parsed {
+        Ok(address) => address,
+        Err(err) => {
+            diagnostics.push(format!("Failed to parse address: {}", err));
+            return AddressInfo {
+                address: input.to_string(),
+                valid: false,
+                prefix: None,
+                network: None,
+                matches_node_network: None,
+                version: None,
+                kind: None,
+                payload: None,
+                script_public_key: None,
+                script_public_key_version: None,
+                script_class: None,
+                diagnostics,
+            };
+        }
+    };
+
+    if address.payload.len() != payload_len(address.version) {
+        diagnostics.push(format!(
+            "Payload is {} bytes, {} expects {}",
+            address.payload.len(),
+            kind(address.version),
+            payload_len(address.version)
+        ));
+    }
+    let network = network_of(address.prefix);
+    let matches_node_network = match (network, node) {
+        (Some(network), Some(node)) => Some(network == node),
+        _ => None,
+    };
+    if matches_node_network == Some(false) {
+        diagnostics.push("Address belongs to a different network than the node".to_string());
+    }
+
+    let script_public_key = pay_to_address_script(&address);
+    AddressInfo {
+        address: address.to_string(),
+        valid: true,
+        prefix: Some(address.prefix.to_string()),
+        network: network.map(|network| network.to_string()),
+        matches_node_network,
+        version: Some(address.version as u8),
+        kind: Some(kind(address.version).to_string()),
+        payload: Some(hex::encode(&address.payload)),
+        script_public_key: Some(hex::encode(script_public_key.script())),
+        script_public_key_version: Some(script_public_key.version()),
+        script_class: Some(ScriptClass::from_script(&script_public_key).to_string()),
+        diagnostics,
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/scripts/{spk}/address",
+    tag = "addresses",
+    params(("spk" = String, Path, description = "Script public key as hex"), ScriptQuery),
+    responses(
+        (status = 200, description = "Address paying to the script", body = ScriptAddress),
+        (status = 400, description = "Invalid script or network, or a script without an address", body = ErrorBody),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
+pub async fn get_script_address(path: web::Path<String>, query: web::Query<ScriptQuery>) -> impl Responder {
+    let spk = path.into_inner();
+    let script = match hex::decode(spk.trim_start_matches("0x")) {
+        Ok(script) => script,
+        Err(_) => return HttpResponse::BadRequest().json(format!("Invalid script public key: {}", spk)),
+    };
+    let script_public_key = ScriptPublicKey::from_vec(query.version.unwrap_or(0), script);
+
+    let network = match query.network.as_deref() {
+        Some(network) => match NetworkType::from_str(network) {
+            Ok(network) => network,
+            Err(_) => return HttpResponse::BadRequest().json(format!("Invalid network: {}", network)),
+        },
+        None => match node_network().await {
+            Some(network) => network,
+            None => return HttpResponse::InternalServerError().json("Failed to get the network of the node"),
+        },
+    };
+
+    match script_address(&script_public_key, network) {
+        Ok(address) => HttpResponse::Ok().json(address),
+        Err(err) => HttpResponse::BadRequest().json(err),
+    }
+}
+
+fn script_address(script_public_key: &ScriptPublicKey, network: NetworkType) -> Result<ScriptAddress, String> {
+    let address = extract_script_pub_key_address(script_public_key, Prefix::from(network))
+        .map_err(|err| format!("Script has no address: {}", err))?;
+    Ok(ScriptAddress {
+        script_public_key: hex::encode(script_public_key.script()),
+        script_public_key_version: script_public_key.version(),
+        script_class: ScriptClass::from_script(script_public_key).to_string(),
+        address: address.to_string(),
+        network: network.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VERSIONS: [(Version, &str, usize); 3] = [
+        (Version::PubKey, "P2PK Schnorr", 32),
+        (Version::PubKeyECDSA, "P2PK ECDSA", 33),
+        (Version::ScriptHash, "P2SH", 32),
+    ];
+
+    fn address(network: NetworkType, version: Version) -> RpcAddress {
+        RpcAddress::new(Prefix::from(network), version, &vec![7; payload_len(version)])
+    }
+
+    fn parse(input: &str, node: Option<NetworkType>) -> AddressInfo {
+        describe(input, RpcAddress::try_from(input.trim()), node)
+    }
+
+    fn has(info: &AddressInfo, needle: &str) -> bool {
+        info.diagnostics.iter().any(|diagnostic| diagnostic.to_lowercase().contains(needle))
+    }
+
+    #[test]
+    fn describes_every_kind_on_mainnet_and_testnet() {
+        for network in [NetworkType::Mainnet, NetworkType::Testnet] {
+            for (version, name, len) in VERSIONS {
+                assert_eq!((kind(version), payload_len(version)), (name, len));
+                let input = address(network, version).to_string();
+                let info = parse(&input, Some(network));
+                assert!(info.valid, "{}", input);
+                assert_eq!(info.address, input);
+                assert_eq!(info.prefix, Some(Prefix::from(network).to_string()));
+                assert_eq!(info.network, Some(network.to_string()));
+                assert_eq!(info.matches_node_network, Some(true));
+                assert_eq!(info.version, Some(version as u8));
+                assert_eq!(info.kind.as_deref(), Some(name));
+                assert_eq!(info.payload, Some(hex::encode(vec![7; len])));
+                assert!(info.diagnostics.is_empty(), "{}: {:?}", input, info.diagnostics);
+            }
+        }
+    }
+
+    #[test]
+    fn reports_a_different_or_unknown_node_network() {
+        let input = address(NetworkType::Mainnet, Version::PubKey).to_string();
+        let info = parse(&input, Some(NetworkType::Testnet));
+        assert!(info.valid);
+        assert_eq!(info.matches_node_network, Some(false));
+        assert!(has(&info, "different network"));
+
+        let info = parse(&input, None);
+        assert_eq!(info.matches_node_network, None);
+        assert!(info.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn diagnoses_malformed_addresses() {
+        let mainnet = address(NetworkType::Mainnet, Version::PubKey).to_string();
+        let testnet = address(NetworkType::Testnet, Version::PubKey).to_string();
+        let (_, encoded) = mainnet.split_once(':').unwrap();
+        let (testnet_prefix, _) = testnet.split_once(':').unwrap();
+        // Flip the last checksum character to another character of the bech32 alphabet
+        let last = if mainnet.ends_with('q') { 'p' } else { 'q' };
+        let bad_checksum = format!("{}{}", &mainnet[..mainnet.len() - 1], last);
+
+        let cases = [
+            (format!("bitcoincash:{}", encoded), "prefix"),
+            // The checksum covers the prefix, so moving a payload to another network breaks it
+            (format!("{}:{}", testnet_prefix, encoded), "checksum"),
+            (bad_checksum, "checksum"),
+            (encoded.to_string(), "missing its network prefix"),
+            (mainnet.to_uppercase(), "upper case"),
+        ];
+        for (input, needle) in cases {
+            let info = parse(&input, Some(NetworkType::Mainnet));
+            assert!(!info.valid, "{}", input);
+            assert_eq!(info.address, input);
+            assert_eq!((info.kind, info.network, info.matches_node_network), (None, None, None));
+            assert!(has(&info, "failed to parse address"), "{}: {:?}", input, info.diagnostics);
+            assert!(has(&info, needle), "{}: {:?}", input, info.diagnostics);
+        }
+
+        let info = describe(&mainnet, Err(AddressError::InvalidVersion(9)), None);
+        assert!(!info.valid);
+        assert!(has(&info, "version"), "{:?}", info.diagnostics);
+
+        let info = parse(&format!(" {}", mainnet), Some(NetworkType::Mainnet));
+        assert!(info.valid);
+        assert!(has(&info, "whitespace"));
+    }
+
+    #[test]
+    fn flags_payloads_of_the_wrong_length_for_the_version() {
+        let short = RpcAddress::new(Prefix::Mainnet, Version::PubKeyECDSA, &[7; 32]);
+        let info = describe(&short.to_string(), Ok(short), None);
+        assert!(info.valid);
+        assert_eq!(info.diagnostics, vec!["Payload is 32 bytes, P2PK ECDSA expects 33".to_string()]);
+    }
+
+    #[test]
+    fn scripts_round_trip_to_addresses() {
+        for network in [NetworkType::Mainnet, NetworkType::Testnet] {
+            for (version, _, _) in VERSIONS {
+                let expected = address(network, version);
+                let info = parse(&expected.to_string(), Some(network));
+                let script = hex::decode(info.script_public_key.unwrap()).unwrap();
+                let script_public_key = ScriptPublicKey::from_vec(info.script_public_key_version.unwrap(), script);
+
+                let found = script_address(&script_public_key, network).unwrap();
+                assert_eq!(found.address, expected.to_string());
+                assert_eq!(found.network, network.to_string());
+                assert_eq!(Some(found.script_class), info.script_class);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_scripts_without_an_address() {
+        // OP_RETURN data carrier
+        let script_public_key = ScriptPublicKey::from_vec(0, vec![0x6a, 0x01, 0x00]);
+        let err = script_address(&script_public_key, NetworkType::Mainnet).unwrap_err();
+        assert!(err.starts_with("Script has no address"), "{}", err);
+    }
+}
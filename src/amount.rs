@@ -0,0 +1,117 @@
+use kaspa_consensus_core::constants::SOMPI_PER_KASPA;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+// Which representations an amount is serialized with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Unit {
+    Sompi,
+    Xen,
+    #[default]
+    Both,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UnitQuery {
+    // sompi, xen or both (default)
+    unit: Option<Unit>,
+}
+
+impl UnitQuery {
+    pub fn unit(&self) -> Unit {
+        self.unit.unwrap_or_default()
+    }
+}
+
+// An amount as exact sompi and as decimal XEN, both strings so no precision is lost to floats
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Amount {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sompi: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub xen: Option<String>,
+}
+
+impl Amount {
+    pub fn new(sompi: u64, unit: Unit) -> Amount {
+        Amount {
+            sompi: (unit != Unit::Xen).then(|| sompi.to_string()),
+            xen: (unit != Unit::Sompi).then(|| format_xen(sompi)),
+        }
+    }
+}
+
+// Decimal XEN without trailing zeros, e.g. 150000000 sompi is "1.5"
+pub fn format_xen(sompi: u64) -> String {
+    let whole = sompi / SOMPI_PER_KASPA;
+    let fraction = sompi % SOMPI_PER_KASPA;
+    if fraction == 0 {
+        return whole.to_string();
+    }
+    let digits = SOMPI_PER_KASPA.to_string().len() - 1;
+    let fraction = format!("{:0width$}", fraction, width = digits);
+    format!("{}.{}", whole, fraction.trim_end_matches('0'))
+}
+
+// Exact sompi of a decimal XEN string, None when it has more precision than a sompi
+pub fn parse_xen(value: &str) -> Option<u64> {
+    let digits = SOMPI_PER_KASPA.to_string().len() - 1;
+    let (whole, fraction) = value.split_once('.').unwrap_or((value, ""));
+    if fraction.len() > digits || !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) || whole.is_empty() {
+        return None;
+    }
+    let whole: u64 = whole.parse().ok()?;
+    let fraction: u64 = if fraction.is_empty() { 0 } else { format!("{:0<width$}", fraction, width = digits).parse().ok()? };
+    whole.checked_mul(SOMPI_PER_KASPA)?.checked_add(fraction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_without_trailing_zeros() {
+        assert_eq!(format_xen(0), "0");
+        assert_eq!(format_xen(1), "0.00000001");
+        assert_eq!(format_xen(150_000_000), "1.5");
+        assert_eq!(format_xen(2_000_000_000), "20");
+        assert_eq!(format_xen(u64::MAX), "184467440737.09551615");
+    }
+
+    #[test]
+    fn parses_exact_sompi() {
+        assert_eq!(parse_xen("0"), Some(0));
+        assert_eq!(parse_xen("1.5"), Some(150_000_000));
+        assert_eq!(parse_xen("0.00000001"), Some(1));
+        assert_eq!(parse_xen("184467440737.09551615"), Some(u64::MAX));
+    }
+
+    #[test]
+    fn rejects_invalid_or_too_precise_amounts() {
+        assert_eq!(parse_xen(""), None);
+        assert_eq!(parse_xen(".5"), None);
+        assert_eq!(parse_xen("-1"), None);
+        assert_eq!(parse_xen("1e8"), None);
+        assert_eq!(parse_xen("0.000000001"), None);
+        assert_eq!(parse_xen("184467440737.09551616"), None);
+    }
+
+    #[test]
+    fn round_trips() {
+        for sompi in [0, 1, 10, 99_999_999, 100_000_000, 123_456_789_012, u64::MAX] {
+            assert_eq!(parse_xen(&format_xen(sompi)), Some(sompi));
+        }
+    }
+
+    #[test]
+    fn unit_selects_the_representations() {
+        let both = Amount::new(150_000_000, Unit::default());
+        assert_eq!((both.sompi.as_deref(), both.xen.as_deref()), (Some("150000000"), Some("1.5")));
+        let sompi = Amount::new(150_000_000, Unit::Sompi);
+        assert_eq!((sompi.sompi.as_deref(), sompi.xen), (Some("150000000"), None));
+        let xen = Amount::new(150_000_000, Unit::Xen);
+        assert_eq!((xen.sompi, xen.xen.as_deref()), (None, Some("1.5")));
+    }
+}
@@ -0,0 +1,435 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use actix_web::dev::ServiceRequest;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use tracing::info;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use crate::config;
+use crate::db::Db;
+use crate::logging::ErrorBody;
+use crate::ratelimit;
+
+pub const SCOPE_BATCH: &str = "batch";
+pub const SCOPE_EXPORT: &str = "export";
+pub const SCOPE_WEBHOOKS: &str = "webhooks";
+pub const SCOPE_PAYMENTS: &str = "payments";
+const KNOWN_SCOPES: &[&str] = &[SCOPE_BATCH, SCOPE_EXPORT, SCOPE_WEBHOOKS, SCOPE_PAYMENTS];
+
+// Routes only available to keys holding the given scope, everything else stays anonymous
+fn required_scope(route: &str) -> Option<&'static str> {
+    match route {
+        "/batch/blocks" | "/batch/balances" => Some(SCOPE_BATCH),
+        "/addresses/{addr}/transactions.csv" | "/addresses/{addr}/transactions.jsonl" | "/blocks/export" => Some(SCOPE_EXPORT),
+        "/webhooks" | "/webhooks/{id}" | "/webhooks/{id}/ping" | "/webhooks/{id}/deliveries" => Some(SCOPE_WEBHOOKS),
+        // Reading a payment request stays open to the customer's checkout page
+        "/payments" => Some(SCOPE_PAYMENTS),
+        _ => None,
+    }
+}
+
+// Authenticated caller, stored in the request extensions for the rate limiter
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<String>,
+    // Multiplier applied to the anonymous rate limits
+    pub rate_factor: f64,
+    // Requests allowed per UTC day, unlimited when missing
+    pub daily_quota: Option<u64>,
+    pub created_at: i64,
+    pub revoked_at: Option<i64>,
+}
+
+impl ApiKey {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|granted| granted == scope)
+    }
+}
+
+fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+// Active keys by secret hash, plus per key usage of the current day
+#[derive(Default)]
+pub struct ApiKeys {
+    keys: Mutex<HashMap<String, ApiKey>>,
+    // Day and request count per key id. Kept in memory like the rate limiter buckets, so a restart
+    // or a second instance starts the day's count over
+    usage: Mutex<HashMap<String, (i64, u64)>>,
+}
+
+impl ApiKeys {
+    // Load stored keys and the keys given in the configuration
+    pub async fn load(db: &Db) -> anyhow::Result<ApiKeys> {
+        let keys = ApiKeys::default();
+        keys.reload(db).await?;
+        Ok(keys)
+    }
+
+    async fn reload(&self, db: &Db) -> anyhow::Result<()> {
+        let stored = db
+            .call(|conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT key_hash, id, name, scopes, rate_factor, daily_quota, created_at FROM api_keys
+                     WHERE revoked_at IS NULL",
+                )?;
+                let rows = stmt.query_map([], |row| {
+                    let scopes: String = row.get(3)?;
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        ApiKey {
+                            id: row.get(1)?,
+                            name: row.get(2)?,
+                            scopes: scopes.split(',').filter(|scope| !scope.is_empty()).map(str::to_string).collect(),
+                            rate_factor: row.get(4)?,
+                            daily_quota: row.get::<_, Option<i64>>(5)?.map(|quota| quota as u64),
+                            created_at: row.get(6)?,
+                            revoked_at: None,
+                        },
+                    ))
+                })?;
+                rows.collect::<rusqlite::Result<Vec<_>>>()
+            })
+            .await?;
+
+        let mut keys: HashMap<String, ApiKey> = stored.into_iter().collect();
+        for (secret, key) in &config::get().api_keys {
+            keys.insert(hash_key(secret), key.clone());
+        }
+        *self.keys.lock().unwrap() = keys;
+        Ok(())
+    }
+
+    fn find(&self, secret: &str) -> Option<ApiKey> {
+        self.keys.lock().unwrap().get(&hash_key(secret)).cloned()
+    }
+
+    // Count one request against the daily quota, false once it is used up
+    fn consume_quota(&self, key: &ApiKey) -> bool {
+        let Some(quota) = key.daily_quota else { return true };
+        let today = Utc::now().timestamp() / (24 * 60 * 60);
+        let mut usage = self.usage.lock().unwrap();
+        let entry = usage.entry(key.id.clone()).or_insert((today, 0));
+        if entry.0 != today {
+            *entry = (today, 0);
+        }
+        if entry.1 >= quota {
+            return false;
+        }
+        entry.1 += 1;
+        true
+    }
+}
+
+// Resolve the API key of a request, returning the rejection when it is invalid or lacks a scope
+pub fn authenticate(keys: &ApiKeys, req: &ServiceRequest) -> Option<HttpResponse> {
+    let route = req.match_pattern().unwrap_or_else(|| req.path().to_string());
+    // Admin routes use their own token
+    if route.starts_with("/admin/") {
+        return None;
+    }
+    let scope = required_scope(&route);
+
+    let key = match ratelimit::api_key(req) {
+        Some(secret) => match keys.find(&secret) {
+            Some(key) => key,
+            None => return Some(HttpResponse::Unauthorized().json("Invalid or revoked API key")),
+        },
+        None => {
+            return scope.map(|scope| HttpResponse::Unauthorized().json(format!("An API key with the {} scope is required", scope)));
+        }
+    };
+
+    if let Some(scope) = scope {
+        if !key.has_scope(scope) {
+            return Some(HttpResponse::Forbidden().json(format!("API key lacks the {} scope", scope)));
+        }
+    }
+
+    req.extensions_mut().insert(key);
+    None
+}
+
+// Count the request against the daily quota of its key, run after the rate limiter so rejected
+// requests don't use it up. Routes the rate limiter leaves unlimited don't count either.
+pub fn check_quota(keys: &ApiKeys, req: &ServiceRequest) -> Option<HttpResponse> {
+    let route = req.match_pattern().unwrap_or_else(|| req.path().to_string());
+    ratelimit::RouteClass::of(&route)?;
+    let extensions = req.extensions();
+    let key = extensions.get::<ApiKey>()?;
+    if keys.consume_quota(key) {
+        None
+    } else {
+        Some(HttpResponse::TooManyRequests().json("Daily API key quota exhausted"))
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateKeyRequest {
+    name: String,
+    #[serde(default)]
+    scopes: Vec<String>,
+    rate_factor: Option<f64>,
+    daily_quota: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct CreatedKey {
+    // The secret is only ever returned here, the database keeps a hash
+    key: String,
+    #[serde(flatten)]
+    api_key: ApiKey,
+}
+
+// Compares digests in constant time, so neither the token's content nor its length leaks through timing
+fn token_matches(given: &str, token: &str) -> bool {
+    Sha256::digest(given.as_bytes()).as_slice().ct_eq(Sha256::digest(token.as_bytes()).as_slice()).into()
+}
+
+fn is_admin(req: &HttpRequest) -> bool {
+    let Some(token) = config::get().admin_token.as_deref() else { return false };
+    req.headers()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map_or(false, |given| token_matches(given, token))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/keys",
+    tag = "admin",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "All stored keys, without their secrets", body = Vec<ApiKey>),
+        (status = 401, description = "Missing or wrong admin token", body = ErrorBody),
+    )
+)]
+pub async fn list_keys(req: HttpRequest, db: web::Data<Db>) -> impl Responder {
+    if !is_admin(&req) {
+        return HttpResponse::Unauthorized().json("Admin token required");
+    }
+    let keys = db
+        .call(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, name, scopes, rate_factor, daily_quota, created_at, revoked_at FROM api_keys ORDER BY created_at",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                let scopes: String = row.get(2)?;
+                Ok(ApiKey {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    scopes: scopes.split(',').filter(|scope| !scope.is_empty()).map(str::to_string).collect(),
+                    rate_factor: row.get(3)?,
+                    daily_quota: row.get::<_, Option<i64>>(4)?.map(|quota| quota as u64),
+                    created_at: row.get(5)?,
+                    revoked_at: row.get(6)?,
+                })
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await;
+
+    match keys {
+        Ok(keys) => HttpResponse::Ok().json(keys),
+        Err(err) => HttpResponse::InternalServerError().json(format!("Failed to list API keys: {:?}", err)),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/keys",
+    tag = "admin",
+    request_body = CreateKeyRequest,
+    security(("admin_token" = [])),
+    responses(
+        (status = 201, description = "The new key, including its secret", body = CreatedKey),
+        (status = 400, description = "Unknown scope or invalid rate factor", body = ErrorBody),
+        (status = 401, description = "Missing or wrong admin token", body = ErrorBody),
+    )
+)]
+pub async fn create_key(
+    req: HttpRequest,
+    body: web::Json<CreateKeyRequest>,
+    db: web::Data<Db>,
+    keys: web::Data<ApiKeys>,
+) -> impl Responder {
+    if !is_admin(&req) {
+        return HttpResponse::Unauthorized().json("Admin token required");
+    }
+    let body = body.into_inner();
+    if let Some(scope) = body.scopes.iter().find(|scope| !KNOWN_SCOPES.contains(&scope.as_str())) {
+        return HttpResponse::BadRequest().json(format!("Unknown scope {}, available: {}", scope, KNOWN_SCOPES.join(", ")));
+    }
+    let rate_factor = body.rate_factor.unwrap_or(config::get().api_key_rate_factor);
+    if !(rate_factor > 0.0 && rate_factor.is_finite()) {
+        return HttpResponse::BadRequest().json("rate_factor must be a positive number");
+    }
+
+    let secret = format!("xk_{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let api_key = ApiKey {
+        id: Uuid::new_v4().to_string(),
+        name: body.name,
+        scopes: body.scopes,
+        rate_factor,
+        daily_quota: body.daily_quota,
+        created_at: Utc::now().timestamp(),
+        revoked_at: None,
+    };
+
+    let stored = api_key.clone();
+    let key_hash = hash_key(&secret);
+    let inserted = db
+        .call(move |conn| {
+            conn.execute(
+                "INSERT INTO api_keys (key_hash, id, name, scopes, rate_factor, daily_quota, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    key_hash,
+                    stored.id,
+                    stored.name,
+                    stored.scopes.join(","),
+                    stored.rate_factor,
+                    stored.daily_quota.map(|quota| quota as i64),
+                    stored.created_at
+                ],
+            )
+        })
+        .await;
+    if let Err(err) = inserted {
+        return HttpResponse::InternalServerError().json(format!("Failed to store API key: {:?}", err));
+    }
+    if let Err(err) = keys.reload(&db).await {
+        return HttpResponse::InternalServerError().json(format!("Failed to reload API keys: {:?}", err));
+    }
+
+    info!(id = %api_key.id, name = %api_key.name, "Created API key");
+    HttpResponse::Created().json(CreatedKey { key: secret, api_key })
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/keys/{id}",
+    tag = "admin",
+    params(("id" = String, Path, description = "Key id")),
+    security(("admin_token" = [])),
+    responses(
+        (status = 204, description = "Key revoked"),
+        (status = 401, description = "Missing or wrong admin token", body = ErrorBody),
+        (status = 404, description = "No active key with this id", body = ErrorBody),
+    )
+)]
+pub async fn revoke_key(
+    req: HttpRequest,
+    path: web::Path<String>,
+    db: web::Data<Db>,
+    keys: web::Data<ApiKeys>,
+) -> impl Responder {
+    if !is_admin(&req) {
+        return HttpResponse::Unauthorized().json("Admin token required");
+    }
+    let id = path.into_inner();
+    let revoked_id = id.clone();
+    let revoked = db
+        .call(move |conn| {
+            conn.execute(
+                "UPDATE api_keys SET revoked_at = ?1 WHERE id = ?2 AND revoked_at IS NULL",
+                params![Utc::now().timestamp(), revoked_id],
+            )
+        })
+        .await;
+
+    match revoked {
+        Ok(0) => HttpResponse::NotFound().json(format!("No active API key with id {}", id)),
+        Ok(_) => {
+            if let Err(err) = keys.reload(&db).await {
+                return HttpResponse::InternalServerError().json(format!("Failed to reload API keys: {:?}", err));
+            }
+            info!(id = %id, "Revoked API key");
+            HttpResponse::NoContent().finish()
+        }
+        Err(err) => HttpResponse::InternalServerError().json(format!("Failed to revoke API key: {:?}", err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(daily_quota: Option<u64>) -> ApiKey {
+        ApiKey {
+            id: "key".to_string(),
+            name: "test".to_string(),
+            scopes: vec![SCOPE_EXPORT.to_string()],
+            rate_factor: 1.0,
+            daily_quota,
+            created_at: 0,
+            revoked_at: None,
+        }
+    }
+
+    #[test]
+    fn scopes_only_the_protected_routes() {
+        assert_eq!(required_scope("/batch/balances"), Some(SCOPE_BATCH));
+        assert_eq!(required_scope("/addresses/{addr}/transactions.csv"), Some(SCOPE_EXPORT));
+        assert_eq!(required_scope("/blocks/export"), Some(SCOPE_EXPORT));
+        assert_eq!(required_scope("/webhooks/{id}/deliveries"), Some(SCOPE_WEBHOOKS));
+        assert_eq!(required_scope("/payments"), Some(SCOPE_PAYMENTS));
+        assert_eq!(required_scope("/payments/{id}"), None);
+        assert_eq!(required_scope("/blocks/{hash}"), None);
+        assert!(KNOWN_SCOPES.iter().all(|scope| [SCOPE_BATCH, SCOPE_EXPORT, SCOPE_WEBHOOKS, SCOPE_PAYMENTS].contains(scope)));
+    }
+
+    #[test]
+    fn checks_granted_scopes() {
+        assert!(key(None).has_scope(SCOPE_EXPORT));
+        assert!(!key(None).has_scope(SCOPE_BATCH));
+    }
+
+    #[test]
+    fn stops_at_the_daily_quota() {
+        let keys = ApiKeys::default();
+        let limited = key(Some(2));
+        assert!(keys.consume_quota(&limited));
+        assert!(keys.consume_quota(&limited));
+        assert!(!keys.consume_quota(&limited));
+        assert!(keys.consume_quota(&key(None)));
+    }
+
+    #[test]
+    fn spends_the_quota_only_on_limited_routes() {
+        let keys = ApiKeys::default();
+        let request = |path: &str| {
+            let req = actix_web::test::TestRequest::with_uri(path).to_srv_request();
+            req.extensions_mut().insert(key(Some(1)));
+            req
+        };
+        for path in ["/health", "/health/live", "/health/ready", "/metrics", "/health"] {
+            assert!(check_quota(&keys, &request(path)).is_none(), "{}", path);
+        }
+        assert!(check_quota(&keys, &request("/blocks")).is_none());
+        let rejected = check_quota(&keys, &request("/info/blockdag")).unwrap();
+        assert_eq!(rejected.status(), 429);
+        assert!(check_quota(&keys, &request("/metrics")).is_none());
+
+        // Anonymous requests have no quota
+        let anonymous = actix_web::test::TestRequest::with_uri("/blocks").to_srv_request();
+        assert!(check_quota(&keys, &anonymous).is_none());
+    }
+
+    #[test]
+    fn compares_admin_tokens() {
+        assert!(token_matches("secret", "secret"));
+        assert!(!token_matches("secreT", "secret"));
+        assert!(!token_matches("secret-and-more", "secret"));
+        assert!(!token_matches("", "secret"));
+    }
+}
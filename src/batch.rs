@@ -0,0 +1,143 @@
+use actix_web::{web, HttpResponse, Responder};
+use kaspa_rpc_core::api::rpc::RpcApi;
+use kaspa_rpc_core::{RpcAddress, RpcBlock};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::amount::{Amount, UnitQuery};
+use crate::logging::ErrorBody;
+use crate::parse_hash;
+use crate::metrics;
+
+// Upper bound of items in a single batch request
+const MAX_BATCH: usize = 100;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BlocksRequest {
+    hashes: Vec<String>,
+    #[serde(default)]
+    include_transactions: bool,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BalancesRequest {
+    addresses: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct BlocksResponse {
+    #[schema(value_type = Vec<Object>)]
+    blocks: Vec<RpcBlock>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct Balance {
+    address: String,
+    balance: Option<Amount>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct BalancesResponse {
+    balances: Vec<Balance>,
+}
+
+fn check_size(len: usize) -> Result<(), HttpResponse> {
+    if len == 0 || len > MAX_BATCH {
+        return Err(HttpResponse::BadRequest().json(format!("A batch holds between 1 and {} items", MAX_BATCH)));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/batch/blocks",
+    tag = "batch",
+    request_body = BlocksRequest,
+    security(("api_key" = [])),
+    responses(
+        (status = 200, description = "Blocks in request order", body = BlocksResponse),
+        (status = 400, description = "Invalid hash or batch size", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = ErrorBody),
+        (status = 403, description = "API key lacks the batch scope", body = ErrorBody),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
+pub async fn get_blocks(body: web::Json<BlocksRequest>) -> impl Responder {
+    if let Err(err) = check_size(body.hashes.len()) {
+        return err;
+    }
+    let mut hashes = Vec::with_capacity(body.hashes.len());
+    for hash in &body.hashes {
+        match parse_hash(hash) {
+            Ok(hash) => hashes.push(hash),
+            Err(err) => return err,
+        }
+    }
+
+    let client = match crate::get_client().await {
+        Ok(client) => client,
+        Err(err) => return err,
+    };
+
+    let mut blocks = Vec::with_capacity(hashes.len());
+    for hash in hashes {
+        match metrics::rpc("get_block", client.get_block(hash, body.include_transactions)).await {
+            Ok(block) => blocks.push(block),
+            Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to get block {}: {:?}", hash, err)),
+        }
+    }
+    if let Err(disconnect_err) = client.disconnect().await {
+        return HttpResponse::InternalServerError().json(format!("Failed to disconnect: {}", disconnect_err));
+    }
+
+    HttpResponse::Ok().json(BlocksResponse { blocks })
+}
+
+#[utoipa::path(
+    post,
+    path = "/batch/balances",
+    tag = "batch",
+    request_body = BalancesRequest,
+    params(UnitQuery),
+    security(("api_key" = [])),
+    responses(
+        (status = 200, description = "Balance per address", body = BalancesResponse),
+        (status = 400, description = "Invalid address or batch size", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = ErrorBody),
+        (status = 403, description = "API key lacks the batch scope", body = ErrorBody),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
+pub async fn get_balances(body: web::Json<BalancesRequest>, query: web::Query<UnitQuery>) -> impl Responder {
+    if let Err(err) = check_size(body.addresses.len()) {
+        return err;
+    }
+    let mut addresses = Vec::with_capacity(body.addresses.len());
+    for address in &body.addresses {
+        match RpcAddress::try_from(address.as_str()) {
+            Ok(address) => addresses.push(address),
+            Err(_) => return HttpResponse::BadRequest().json(format!("Invalid address: {}", address)),
+        }
+    }
+
+    let client = match crate::get_client().await {
+        Ok(client) => client,
+        Err(err) => return err,
+    };
+
+    let entries = match metrics::rpc("get_balances_by_addresses", client.get_balances_by_addresses(addresses)).await {
+        Ok(entries) => entries,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to get balances: {:?}", err)),
+    };
+    if let Err(disconnect_err) = client.disconnect().await {
+        return HttpResponse::InternalServerError().json(format!("Failed to disconnect: {}", disconnect_err));
+    }
+
+    let balances = entries
+        .into_iter()
+        .map(|entry| Balance {
+            address: entry.address.to_string(),
+            balance: entry.balance.map(|balance| Amount::new(balance, query.unit())),
+        })
+        .collect();
+    HttpResponse::Ok().json(BalancesResponse { balances })
+}
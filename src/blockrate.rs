@@ -0,0 +1,255 @@
+use actix_web::{web, HttpResponse, Responder};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::config;
+use crate::db::Db;
+use crate::index::{self, BlockRange};
+use crate::logging::ErrorBody;
+
+const DEFAULT_WINDOWS: &str = "1m,1h,24h";
+// Window used for the headline blocks per second figure
+const MEASURE_WINDOW_SECS: u64 = 60 * 60;
+const MAX_WINDOW_SECS: u64 = 7 * 24 * 60 * 60;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BlockRateQuery {
+    // Comma separated windows such as 1m,1h,24h
+    windows: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct IntervalStats {
+    median_ms: u64,
+    p90_ms: u64,
+    p99_ms: u64,
+    max_ms: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct WindowRate {
+    window: String,
+    blocks: u64,
+    blocks_per_second: f64,
+    daa_score_per_second: f64,
+    intervals: IntervalStats,
+}
+
+#[derive(Serialize, ToSchema)]
+struct BlockRateResponse {
+    blocks_per_second: Option<f64>,
+    target_blocks_per_second: f64,
+    // Timestamp of the newest indexed header, the end of every window
+    measured_at: u64,
+    windows: Vec<WindowRate>,
+}
+
+// Parse a window such as 30s, 1m, 1h or 7d into seconds
+fn parse_window(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let unit = value.chars().last()?;
+    let amount: u64 = value[..value.len() - unit.len_utf8()].parse().ok()?;
+    let seconds = match unit {
+        's' => amount,
+        'm' => amount.checked_mul(60)?,
+        'h' => amount.checked_mul(60 * 60)?,
+        'd' => amount.checked_mul(24 * 60 * 60)?,
+        _ => return None,
+    };
+    (seconds > 0 && seconds <= MAX_WINDOW_SECS).then_some(seconds)
+}
+
+// What a window needs of the indexed headers in a time range, aggregated by SQLite so a long window
+// never loads every header
+struct Headers {
+    count: u64,
+    first_time: u64,
+    last_time: u64,
+    min_daa: u64,
+    max_daa: u64,
+    intervals: IntervalStats,
+}
+
+// Position of the percentile in a sorted list of the given length
+fn percentile_rank(len: u64, percent: u64) -> u64 {
+    len.saturating_sub(1) * percent / 100
+}
+
+async fn headers_between(db: &Db, from: u64, to: u64) -> anyhow::Result<Headers> {
+    db.call(move |conn| {
+        let (from, to) = (from as i64, to.min(i64::MAX as u64) as i64);
+        let (count, first_time, last_time, min_daa, max_daa) = conn.query_row(
+            "SELECT COUNT(*), MIN(timestamp), MAX(timestamp), MIN(daa_score), MAX(daa_score)
+             FROM blocks WHERE timestamp BETWEEN ?1 AND ?2",
+            params![from, to],
+            |row| {
+                let value = |index: usize| row.get::<_, Option<i64>>(index).map(|value| value.unwrap_or(0) as u64);
+                Ok((value(0)?, value(1)?, value(2)?, value(3)?, value(4)?))
+            },
+        )?;
+
+        // Gaps between consecutive headers, only the ones at the percentile ranks are read back
+        let gaps = count.saturating_sub(1);
+        let ranks = [percentile_rank(gaps, 50), percentile_rank(gaps, 90), percentile_rank(gaps, 99), percentile_rank(gaps, 100)];
+        let mut values = [0; 4];
+        if gaps > 0 {
+            let mut stmt = conn.prepare(
+                "WITH gaps AS (
+                     SELECT timestamp - LAG(timestamp) OVER (ORDER BY timestamp) AS gap
+                     FROM blocks WHERE timestamp BETWEEN ?1 AND ?2
+                 ),
+                 ranked AS (
+                     SELECT gap, ROW_NUMBER() OVER (ORDER BY gap) - 1 AS rank FROM gaps WHERE gap IS NOT NULL
+                 )
+                 SELECT rank, gap FROM ranked WHERE rank IN (?3, ?4, ?5, ?6)",
+            )?;
+            let rows = stmt.query_map(
+                params![from, to, ranks[0] as i64, ranks[1] as i64, ranks[2] as i64, ranks[3] as i64],
+                |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64)),
+            )?;
+            for row in rows {
+                let (rank, gap) = row?;
+                for (value, wanted) in values.iter_mut().zip(ranks) {
+                    if wanted == rank {
+                        *value = gap;
+                    }
+                }
+            }
+        }
+
+        Ok(Headers {
+            count,
+            first_time,
+            last_time,
+            min_daa,
+            max_daa,
+            intervals: IntervalStats { median_ms: values[0], p90_ms: values[1], p99_ms: values[2], max_ms: values[3] },
+        })
+    })
+        .await
+}
+
+fn measure(window: String, window_secs: u64, headers: Headers) -> WindowRate {
+    let blocks_per_second = headers.count as f64 / window_secs as f64;
+    let daa_score_per_second = if headers.last_time > headers.first_time {
+        (headers.max_daa - headers.min_daa) as f64 / ((headers.last_time - headers.first_time) as f64 / 1000.0)
+    } else {
+        0.0
+    };
+
+    WindowRate {
+        window,
+        blocks: headers.count,
+        blocks_per_second,
+        daa_score_per_second,
+        intervals: headers.intervals,
+    }
+}
+
+// Blocks per second observed over the last hour of indexed headers
+pub async fn get_blocks_per_second(db: &Db) -> anyhow::Result<Option<f64>> {
+    let Some(tip) = index::tip(db).await? else { return Ok(None) };
+    let from = tip.timestamp.saturating_sub(MEASURE_WINDOW_SECS * 1000);
+    let count = index::count_in_range(db, BlockRange::Time { from, to: tip.timestamp }).await?;
+    Ok(Some(count as f64 / MEASURE_WINDOW_SECS as f64))
+}
+
+#[utoipa::path(
+    get,
+    path = "/info/blockrate",
+    tag = "info",
+    params(BlockRateQuery),
+    responses(
+        (status = 200, description = "Observed block rate per window", body = BlockRateResponse),
+        (status = 400, description = "Invalid window", body = ErrorBody),
+        (status = 503, description = "Block index is empty", body = ErrorBody),
+    )
+)]
+pub async fn get_block_rate(query: web::Query<BlockRateQuery>, db: web::Data<Db>) -> impl Responder {
+    let mut windows = Vec::new();
+    for window in query.windows.as_deref().unwrap_or(DEFAULT_WINDOWS).split(',') {
+        match parse_window(window) {
+            Some(seconds) => windows.push((window.trim().to_string(), seconds)),
+            None => return HttpResponse::BadRequest().json(format!("Invalid window: {}", window)),
+        }
+    }
+
+    let tip = match index::tip(&db).await {
+        Ok(Some(tip)) => tip,
+        Ok(None) => return HttpResponse::ServiceUnavailable().json("Block index is empty"),
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to query block index: {:?}", err)),
+    };
+
+    // All windows end at the newest indexed header, so indexer lag doesn't skew the rates
+    let mut rates = Vec::new();
+    for (window, seconds) in windows {
+        let from = tip.timestamp.saturating_sub(seconds * 1000);
+        let headers = match headers_between(&db, from, tip.timestamp).await {
+            Ok(headers) => headers,
+            Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to query block index: {:?}", err)),
+        };
+        rates.push(measure(window, seconds, headers));
+    }
+
+    let blocks_per_second = match get_blocks_per_second(&db).await {
+        Ok(rate) => rate,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to query block index: {:?}", err)),
+    };
+
+    HttpResponse::Ok().json(BlockRateResponse {
+        blocks_per_second,
+        target_blocks_per_second: config::get().target_blocks_per_second,
+        measured_at: tip.timestamp,
+        windows: rates,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_windows() {
+        assert_eq!(parse_window("30s"), Some(30));
+        assert_eq!(parse_window(" 1h"), Some(3600));
+        assert_eq!(parse_window("7d"), Some(MAX_WINDOW_SECS));
+        assert_eq!(parse_window("8d"), None);
+        assert_eq!(parse_window("0m"), None);
+        assert_eq!(parse_window("1w"), None);
+        assert_eq!(parse_window(""), None);
+    }
+
+    #[tokio::test]
+    async fn aggregates_headers_in_the_database() {
+        let db = Db::open(":memory:").unwrap();
+        db.call(|conn| {
+            // One block a second, except a 10s gap before the last one
+            for (index, timestamp) in [1000, 2000, 3000, 4000, 14000].into_iter().enumerate() {
+                conn.execute(
+                    "INSERT INTO blocks (hash, daa_score, blue_score, timestamp, bits) VALUES (?1, ?2, ?2, ?3, 0)",
+                    params![index.to_string(), 100 + index as i64 * 2, timestamp],
+                )?;
+            }
+            Ok(())
+        })
+            .await
+            .unwrap();
+
+        let headers = headers_between(&db, 0, u64::MAX).await.unwrap();
+        assert_eq!((headers.count, headers.first_time, headers.last_time), (5, 1000, 14000));
+        assert_eq!((headers.min_daa, headers.max_daa), (100, 108));
+        assert_eq!(headers.intervals.median_ms, 1000);
+        assert_eq!(headers.intervals.p90_ms, 1000);
+        assert_eq!(headers.intervals.max_ms, 10000);
+
+        let rate = measure("13s".to_string(), 13, headers);
+        assert_eq!(rate.blocks, 5);
+        assert!((rate.blocks_per_second - 5.0 / 13.0).abs() < 1e-9);
+        assert!((rate.daa_score_per_second - 8.0 / 13.0).abs() < 1e-9);
+
+        let empty = headers_between(&db, 20000, 30000).await.unwrap();
+        assert_eq!(empty.count, 0);
+        assert_eq!(measure("10s".to_string(), 10, empty).daa_score_per_second, 0.0);
+    }
+}
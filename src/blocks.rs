@@ -0,0 +1,275 @@
+use std::collections::HashSet;
+use actix_web::{web, HttpResponse, Responder};
+use chrono::DateTime;
+use kaspa_rpc_core::api::rpc::RpcApi;
+use kaspa_rpc_core::RpcHash;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::db::Db;
+use crate::index::{self, BlockRange, IndexedBlock};
+use crate::logging::ErrorBody;
+use crate::metrics;
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
+// Upper bound of get_blocks round trips when the range reaches past the index
+const MAX_LIVE_PAGES: usize = 10;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BlockListQuery {
+    #[serde(rename = "lowDaa")]
+    low_daa: Option<u64>,
+    #[serde(rename = "highDaa")]
+    high_daa: Option<u64>,
+    #[serde(rename = "lowBlue")]
+    low_blue: Option<u64>,
+    #[serde(rename = "highBlue")]
+    high_blue: Option<u64>,
+    from: Option<String>,
+    to: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+// camelCase like the query parameters
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct BlockListResponse {
+    blocks: Vec<IndexedBlock>,
+    limit: usize,
+    offset: usize,
+    next_offset: Option<usize>,
+}
+
+// Accepts either unix milliseconds or an RFC 3339 date
+pub fn parse_time(value: &str) -> Option<u64> {
+    if let Ok(millis) = value.parse::<u64>() {
+        return Some(millis);
+    }
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .and_then(|date| u64::try_from(date.timestamp_millis()).ok())
+}
+
+fn parse_range(query: &BlockListQuery) -> Result<BlockRange, String> {
+    let daa = (query.low_daa, query.high_daa);
+    let blue = (query.low_blue, query.high_blue);
+    match (daa, blue, query.from.as_deref(), query.to.as_deref()) {
+        ((Some(low), high), (None, None), None, None) => {
+            let high = high.unwrap_or(u64::MAX);
+            if low > high {
+                return Err("lowDaa must not be greater than highDaa".to_string());
+            }
+            Ok(BlockRange::Daa { low, high })
+        }
+        ((None, None), (Some(low), high), None, None) => {
+            let high = high.unwrap_or(u64::MAX);
+            if low > high {
+                return Err("lowBlue must not be greater than highBlue".to_string());
+            }
+            Ok(BlockRange::Blue { low, high })
+        }
+        ((None, None), (None, None), Some(from), to) => {
+            let from = parse_time(from).ok_or("Invalid from time")?;
+            let to = match to {
+                Some(to) => parse_time(to).ok_or("Invalid to time")?,
+                None => u64::MAX,
+            };
+            if from > to {
+                return Err("from must not be after to".to_string());
+            }
+            Ok(BlockRange::Time { from, to })
+        }
+        _ => Err("Specify one of lowDaa (and optionally highDaa), lowBlue (and optionally highBlue) or from (and optionally to)".to_string()),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/blocks",
+    tag = "blocks",
+    params(BlockListQuery),
+    responses(
+        (status = 200, description = "Block headers in the DAA score, blue score or time range", body = BlockListResponse),
+        (status = 400, description = "Invalid range", body = ErrorBody),
+        (status = 500, description = "Index or node request failed", body = ErrorBody),
+    )
+)]
+pub async fn list_blocks(query: web::Query<BlockListQuery>, db: web::Data<Db>) -> impl Responder {
+    let range = match parse_range(&query) {
+        Ok(range) => range,
+        Err(err) => return HttpResponse::BadRequest().json(err),
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let offset = query.offset.unwrap_or(0);
+
+    // Historical part of the range comes from the local index
+    let mut blocks = match index::blocks_in_range(&db, range, limit + 1, offset).await {
+        Ok(blocks) => blocks,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to query block index: {:?}", err)),
+    };
+
+    let tip = match index::tip(&db).await {
+        Ok(tip) => tip,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to query block index: {:?}", err)),
+    };
+
+    // Blocks newer than the index are fetched live from the node
+    if blocks.len() <= limit && tip.as_ref().map_or(true, |tip| range.extends_past(tip)) {
+        let indexed = match index::count_in_range(&db, range).await {
+            Ok(count) => count,
+            Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to query block index: {:?}", err)),
+        };
+        let live = match fetch_live_blocks(&db, range, tip.as_ref(), limit + 1 - blocks.len() + offset.saturating_sub(indexed)).await {
+            Ok(live) => live,
+            Err(err) => return err,
+        };
+        blocks.extend(live.into_iter().skip(offset.saturating_sub(indexed)));
+    }
+
+    let next_offset = if blocks.len() > limit {
+        blocks.truncate(limit);
+        Some(offset + limit)
+    } else {
+        None
+    };
+
+    HttpResponse::Ok().json(BlockListResponse { blocks, limit, offset, next_offset })
+}
+
+// Blocks of the range the index doesn't have yet. Blocks sharing the tip's DAA score may still be
+// missing from the index, so they are fetched too unless their hash is already known.
+fn new_blocks(
+    fetched: impl Iterator<Item = IndexedBlock>,
+    range: BlockRange,
+    tip_daa_score: Option<u64>,
+    known: &mut HashSet<String>,
+) -> Vec<IndexedBlock> {
+    fetched
+        .filter(|block| range.contains(block) && tip_daa_score.map_or(true, |daa| block.daa_score >= daa))
+        .filter(|block| known.insert(block.hash.clone()))
+        .collect()
+}
+
+async fn fetch_live_blocks(
+    db: &Db,
+    range: BlockRange,
+    tip: Option<&IndexedBlock>,
+    wanted: usize,
+) -> Result<Vec<IndexedBlock>, HttpResponse> {
+    let tip_daa_score = tip.map(|tip| tip.daa_score);
+    let mut known = match tip_daa_score {
+        Some(daa_score) => index::hashes_from(db, daa_score)
+            .await
+            .map_err(|err| HttpResponse::InternalServerError().json(format!("Failed to query block index: {:?}", err)))?,
+        None => HashSet::new(),
+    };
+    let tip_hash: Option<RpcHash> = tip
+        .map(|tip| tip.hash.parse())
+        .transpose()
+        .map_err(|_| HttpResponse::InternalServerError().json("Corrupt block index"))?;
+    let client = crate::get_client().await?;
+
+    let mut low_hash = match tip_hash {
+        Some(tip_hash) => tip_hash,
+        None => match metrics::rpc("get_block_dag_info", client.get_block_dag_info()).await {
+            Ok(info) => info.pruning_point_hash,
+            Err(err) => {
+                let _ = client.disconnect().await;
+                return Err(HttpResponse::InternalServerError().json(format!("Failed to get block DAG info: {:?}", err)));
+            }
+        },
+    };
+
+    let mut blocks: Vec<IndexedBlock> = Vec::new();
+    for _ in 0..MAX_LIVE_PAGES {
+        let response = match metrics::rpc("get_blocks", client.get_blocks(Some(low_hash), true, false)).await {
+            Ok(response) => response,
+            Err(err) => {
+                let _ = client.disconnect().await;
+                return Err(HttpResponse::InternalServerError().json(format!("Failed to get blocks: {:?}", err)));
+            }
+        };
+
+        let next_low_hash = index::next_low_hash(&response.blocks, low_hash);
+        blocks.extend(new_blocks(response.blocks.iter().map(IndexedBlock::from), range, tip_daa_score, &mut known));
+
+        if blocks.len() >= wanted || next_low_hash == low_hash {
+            break;
+        }
+        low_hash = next_low_hash;
+    }
+
+    if let Err(disconnect_err) = client.disconnect().await {
+        return Err(HttpResponse::InternalServerError().json(format!("Failed to disconnect: {}", disconnect_err)));
+    }
+
+    blocks.sort_by(|a, b| a.daa_score.cmp(&b.daa_score).then_with(|| a.hash.cmp(&b.hash)));
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(pairs: &[(&str, &str)]) -> BlockListQuery {
+        let query = pairs.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<_>>().join("&");
+        web::Query::<BlockListQuery>::from_query(&query).unwrap().into_inner()
+    }
+
+    #[test]
+    fn parses_each_kind_of_range() {
+        assert!(matches!(parse_range(&query(&[("lowDaa", "5"), ("highDaa", "9")])), Ok(BlockRange::Daa { low: 5, high: 9 })));
+        assert!(matches!(parse_range(&query(&[("lowBlue", "7")])), Ok(BlockRange::Blue { low: 7, high: u64::MAX })));
+        assert!(matches!(
+            parse_range(&query(&[("from", "1000"), ("to", "2000")])),
+            Ok(BlockRange::Time { from: 1000, to: 2000 })
+        ));
+        assert!(matches!(
+            parse_range(&query(&[("from", "1970-01-01T00:00:01Z")])),
+            Ok(BlockRange::Time { from: 1000, to: u64::MAX })
+        ));
+    }
+
+    fn block(hash: &str, daa_score: u64) -> IndexedBlock {
+        IndexedBlock { hash: hash.to_string(), daa_score, blue_score: daa_score, timestamp: 0, bits: 0 }
+    }
+
+    #[test]
+    fn live_blocks_include_the_tip_score_unless_indexed() {
+        let range = BlockRange::Daa { low: 0, high: u64::MAX };
+        let mut known = HashSet::from(["tip".to_string()]);
+        let fetched = vec![block("old", 9), block("tip", 10), block("sibling", 10), block("new", 11)];
+        let hashes: Vec<String> = new_blocks(fetched.into_iter(), range, Some(10), &mut known).into_iter().map(|block| block.hash).collect();
+        assert_eq!(hashes, ["sibling", "new"]);
+    }
+
+    #[test]
+    fn live_blocks_are_deduplicated_across_pages() {
+        let range = BlockRange::Daa { low: 5, high: 20 };
+        let mut known = HashSet::new();
+        let first = new_blocks(vec![block("a", 4), block("b", 6), block("c", 7)].into_iter(), range, None, &mut known);
+        let second = new_blocks(vec![block("c", 7), block("d", 8), block("d", 8)].into_iter(), range, None, &mut known);
+        assert_eq!(first.iter().map(|block| block.hash.as_str()).collect::<Vec<_>>(), ["b", "c"]);
+        assert_eq!(second.iter().map(|block| block.hash.as_str()).collect::<Vec<_>>(), ["d"]);
+    }
+
+    #[test]
+    fn list_response_is_camel_case() {
+        let response = BlockListResponse { blocks: vec![block("a", 1)], limit: 1, offset: 0, next_offset: Some(1) };
+        let value = serde_json::to_value(response).unwrap();
+        assert_eq!(value["nextOffset"], 1);
+        assert_eq!(value["blocks"][0]["daaScore"], 1);
+        assert_eq!(value["blocks"][0]["blueScore"], 1);
+    }
+
+    #[test]
+    fn rejects_mixed_and_inverted_ranges() {
+        assert!(parse_range(&query(&[])).is_err());
+        assert!(parse_range(&query(&[("lowDaa", "1"), ("lowBlue", "1")])).is_err());
+        assert!(parse_range(&query(&[("lowBlue", "1"), ("from", "1")])).is_err());
+        assert!(parse_range(&query(&[("lowBlue", "9"), ("highBlue", "8")])).is_err());
+        assert!(parse_range(&query(&[("from", "yesterday")])).is_err());
+    }
+}
@@ -0,0 +1,380 @@
+use std::collections::{HashSet, VecDeque};
+use std::future::Future;
+use actix_web::{web, HttpResponse, Responder};
+use kaspa_rpc_core::api::rpc::RpcApi;
+use kaspa_rpc_core::{RpcAcceptedTransactionIds, RpcBlock, RpcHash};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::logging::ErrorBody;
+use crate::parse_hash;
+use crate::metrics;
+
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+// Upper bound of blocks visited while looking for the accepting chain block
+const MAX_ACCEPTANCE_SEARCH: usize = 200;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ChainQuery {
+    from: String,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct AcceptedTransactions {
+    accepting_block_hash: String,
+    accepted_transaction_ids: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ChainResponse {
+    removed_chain_block_hashes: Vec<String>,
+    added_chain_block_hashes: Vec<String>,
+    accepted_transaction_ids: Vec<AcceptedTransactions>,
+    limit: usize,
+    offset: usize,
+    next_offset: Option<usize>,
+    // Last chain block of this page, usable as `from` to continue without re-reading the page
+    next_from: Option<String>,
+}
+
+// What the accepting block search needs of a descendant
+struct Descendant {
+    is_chain_block: bool,
+    blue_score: u64,
+    merge_set: Vec<RpcHash>,
+    children: Vec<RpcHash>,
+}
+
+impl Descendant {
+    // None without verbose data, such a block can't be followed
+    fn of(block: RpcBlock) -> Option<Descendant> {
+        let data = block.verbose_data?;
+        Some(Descendant {
+            is_chain_block: data.is_chain_block,
+            blue_score: block.header.blue_score,
+            merge_set: data.merge_set_blues_hashes.into_iter().chain(data.merge_set_reds_hashes).collect(),
+            children: data.children_hashes,
+        })
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct IsChainResponse {
+    hash: String,
+    is_chain: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+struct AcceptingBlockResponse {
+    hash: String,
+    is_chain: bool,
+    accepting_block_hash: Option<String>,
+    accepting_block_blue_score: Option<u64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/chain",
+    tag = "chain",
+    params(ChainQuery),
+    responses(
+        (status = 200, description = "Selected chain changes and accepted transactions since `from`", body = ChainResponse),
+        (status = 400, description = "Invalid hash", body = ErrorBody),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
+pub async fn get_chain(query: web::Query<ChainQuery>) -> impl Responder {
+    let from = match parse_hash(&query.from) {
+        Ok(hash) => hash,
+        Err(err) => return err,
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let offset = query.offset.unwrap_or(0);
+
+    let client = match crate::get_client().await {
+        Ok(client) => client,
+        Err(err) => return err,
+    };
+
+    // The node answers with the whole chain since `from` in one response, pages are cut from it.
+    // Continuing with `next_from` instead of `next_offset` keeps each of those responses short.
+    let chain = metrics::rpc("get_virtual_chain_from_block", client.get_virtual_chain_from_block(from, true)).await;
+    let _ = client.disconnect().await;
+    match chain {
+        Ok(chain) => HttpResponse::Ok().json(chain_page(
+            &chain.removed_chain_block_hashes,
+            &chain.added_chain_block_hashes,
+            &chain.accepted_transaction_ids,
+            offset,
+            limit,
+        )),
+        Err(err) => HttpResponse::InternalServerError().json(format!("Failed to get virtual chain: {:?}", err)),
+    }
+}
+
+fn chain_page(
+    removed: &[RpcHash],
+    added: &[RpcHash],
+    accepted: &[RpcAcceptedTransactionIds],
+    offset: usize,
+    limit: usize,
+) -> ChainResponse {
+    let page: Vec<RpcHash> = added.iter().skip(offset).take(limit).cloned().collect();
+    let accepted_transaction_ids = accepted
+        .iter()
+        .filter(|accepted| page.contains(&accepted.accepting_block_hash))
+        .map(|accepted| AcceptedTransactions {
+            accepting_block_hash: accepted.accepting_block_hash.to_string(),
+            accepted_transaction_ids: accepted.accepted_transaction_ids.iter().map(|id| id.to_string()).collect(),
+        })
+        .collect();
+
+    // Removed blocks relate to the `from` block, so they are only reported with the first page
+    let removed_chain_block_hashes = if offset == 0 {
+        removed.iter().map(|hash| hash.to_string()).collect()
+    } else {
+        Vec::new()
+    };
+
+    let end = offset.saturating_add(limit);
+    let next_offset = (end < added.len()).then_some(end);
+    let next_from = next_offset.and(page.last()).map(|hash| hash.to_string());
+
+    ChainResponse {
+        removed_chain_block_hashes,
+        added_chain_block_hashes: page.iter().map(|hash| hash.to_string()).collect(),
+        accepted_transaction_ids,
+        limit,
+        offset,
+        next_offset,
+        next_from,
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/blocks/{hash}/is-chain",
+    tag = "chain",
+    params(("hash" = String, Path, description = "Block hash")),
+    responses(
+        (status = 200, description = "Whether the block is on the selected chain", body = IsChainResponse),
+        (status = 400, description = "Invalid hash", body = ErrorBody),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
+pub async fn get_is_chain(path: web::Path<String>) -> impl Responder {
+    let hash = match parse_hash(&path.into_inner()) {
+        Ok(hash) => hash,
+        Err(err) => return err,
+    };
+    let client = match crate::get_client().await {
+        Ok(client) => client,
+        Err(err) => return err,
+    };
+
+    let block = metrics::rpc("get_block", client.get_block(hash, false)).await;
+    let _ = client.disconnect().await;
+    let block = match block {
+        Ok(block) => block,
+        Err(err) => return HttpResponse::NotFound().json(format!("Failed to get block: {:?}", err)),
+    };
+
+    let is_chain = block.verbose_data.as_ref().map_or(false, |data| data.is_chain_block);
+    HttpResponse::Ok().json(IsChainResponse { hash: hash.to_string(), is_chain })
+}
+
+#[utoipa::path(
+    get,
+    path = "/blocks/{hash}/accepting-block",
+    tag = "chain",
+    params(("hash" = String, Path, description = "Block hash")),
+    responses(
+        (status = 200, description = "Chain block that merged the block", body = AcceptingBlockResponse),
+        (status = 400, description = "Invalid hash", body = ErrorBody),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
+pub async fn get_accepting_block(path: web::Path<String>) -> impl Responder {
+    let hash = match parse_hash(&path.into_inner()) {
+        Ok(hash) => hash,
+        Err(err) => return err,
+    };
+    let client = match crate::get_client().await {
+        Ok(client) => client,
+        Err(err) => return err,
+    };
+
+    let result = async {
+        let block = metrics::rpc("get_block", client.get_block(hash, false))
+            .await
+            .map_err(|err| HttpResponse::NotFound().json(format!("Failed to get block: {:?}", err)))?;
+        let verbose = block
+            .verbose_data
+            .ok_or_else(|| HttpResponse::InternalServerError().json("Block has no verbose data"))?;
+        let client = &client;
+        let accepting = find_accepting_block(hash, verbose.children_hashes, |candidate| async move {
+            metrics::rpc("get_block", client.get_block(candidate, false)).await.map(Descendant::of)
+        })
+            .await
+            .map_err(|err| HttpResponse::InternalServerError().json(format!("Failed to get block: {:?}", err)))?;
+        Ok::<_, HttpResponse>((verbose.is_chain_block, accepting))
+    }
+        .await;
+    let _ = client.disconnect().await;
+    let (is_chain, accepting) = match result {
+        Ok(found) => found,
+        Err(err) => return err,
+    };
+
+    HttpResponse::Ok().json(AcceptingBlockResponse {
+        hash: hash.to_string(),
+        is_chain,
+        accepting_block_hash: accepting.map(|(hash, _)| hash.to_string()),
+        accepting_block_blue_score: accepting.map(|(_, blue_score)| blue_score),
+    })
+}
+
+// The accepting block is the chain block whose merge set contains the block. Descendants are
+// searched breadth first from the children, visiting at most MAX_ACCEPTANCE_SEARCH blocks.
+async fn find_accepting_block<F, Fut, E>(hash: RpcHash, children: Vec<RpcHash>, mut fetch: F) -> Result<Option<(RpcHash, u64)>, E>
+where
+    F: FnMut(RpcHash) -> Fut,
+    Fut: Future<Output = Result<Option<Descendant>, E>>,
+{
+    let mut queue: VecDeque<RpcHash> = children.into_iter().collect();
+    let mut visited: HashSet<RpcHash> = queue.iter().cloned().collect();
+    while let Some(candidate_hash) = queue.pop_front() {
+        if visited.len() > MAX_ACCEPTANCE_SEARCH {
+            break;
+        }
+        let Some(candidate) = fetch(candidate_hash).await? else { continue };
+        if candidate.is_chain_block && candidate.merge_set.contains(&hash) {
+            return Ok(Some((candidate_hash, candidate.blue_score)));
+        }
+        for child in candidate.children {
+            if visited.insert(child) {
+                queue.push_back(child);
+            }
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    fn hash(n: u64) -> RpcHash {
+        format!("{:064x}", n).parse().unwrap()
+    }
+
+    fn descendant(is_chain_block: bool, blue_score: u64, merge_set: &[u64], children: &[u64]) -> Descendant {
+        Descendant {
+            is_chain_block,
+            blue_score,
+            merge_set: merge_set.iter().map(|n| hash(*n)).collect(),
+            children: children.iter().map(|n| hash(*n)).collect(),
+        }
+    }
+
+    fn chain(count: u64) -> (Vec<RpcHash>, Vec<RpcAcceptedTransactionIds>) {
+        let added: Vec<RpcHash> = (1..=count).map(hash).collect();
+        let accepted = (1..=count)
+            .map(|n| RpcAcceptedTransactionIds { accepting_block_hash: hash(n), accepted_transaction_ids: vec![hash(100 + n)] })
+            .collect();
+        (added, accepted)
+    }
+
+    #[test]
+    fn first_page_reports_removed_blocks_and_continuation() {
+        let (added, accepted) = chain(5);
+        let page = chain_page(&[hash(9)], &added, &accepted, 0, 2);
+        assert_eq!(page.added_chain_block_hashes, [hash(1).to_string(), hash(2).to_string()]);
+        assert_eq!(page.removed_chain_block_hashes, [hash(9).to_string()]);
+        let accepting: Vec<_> = page.accepted_transaction_ids.iter().map(|accepted| accepted.accepting_block_hash.clone()).collect();
+        assert_eq!(accepting, [hash(1).to_string(), hash(2).to_string()]);
+        assert_eq!(page.accepted_transaction_ids[1].accepted_transaction_ids, [hash(102).to_string()]);
+        assert_eq!(page.next_offset, Some(2));
+        assert_eq!(page.next_from, Some(hash(2).to_string()));
+    }
+
+    #[test]
+    fn later_pages_leave_out_removed_blocks() {
+        let (added, accepted) = chain(5);
+        let page = chain_page(&[hash(9)], &added, &accepted, 2, 2);
+        assert_eq!(page.added_chain_block_hashes, [hash(3).to_string(), hash(4).to_string()]);
+        assert!(page.removed_chain_block_hashes.is_empty());
+        assert_eq!(page.next_offset, Some(4));
+
+        let last = chain_page(&[hash(9)], &added, &accepted, 4, 2);
+        assert_eq!(last.added_chain_block_hashes, [hash(5).to_string()]);
+        assert_eq!((last.next_offset, last.next_from), (None, None));
+    }
+
+    #[test]
+    fn offsets_past_the_end_give_an_empty_page() {
+        let (added, accepted) = chain(3);
+        for offset in [3, 10, usize::MAX] {
+            let page = chain_page(&[], &added, &accepted, offset, MAX_PAGE_SIZE);
+            assert!(page.added_chain_block_hashes.is_empty());
+            assert!(page.accepted_transaction_ids.is_empty());
+            assert_eq!(page.next_offset, None);
+        }
+    }
+
+    #[tokio::test]
+    async fn finds_the_chain_block_merging_the_block() {
+        // 1 merges the block but is off the chain, 2 is on the chain without merging it, 3 accepts it
+        let mut blocks = HashMap::from([
+            (hash(1), descendant(false, 10, &[0], &[3])),
+            (hash(2), descendant(true, 11, &[5], &[3])),
+            (hash(3), descendant(true, 30, &[0, 1], &[4])),
+            (hash(4), descendant(true, 40, &[0], &[])),
+        ]);
+        let found = find_accepting_block(hash(0), vec![hash(1), hash(2)], |candidate| {
+            std::future::ready(Ok::<_, ()>(blocks.remove(&candidate)))
+        })
+            .await;
+        assert_eq!(found, Ok(Some((hash(3), 30))));
+        assert!(blocks.contains_key(&hash(4)));
+    }
+
+    #[tokio::test]
+    async fn skips_blocks_without_verbose_data() {
+        let mut blocks = HashMap::from([(hash(2), descendant(true, 20, &[0], &[]))]);
+        let found = find_accepting_block(hash(0), vec![hash(1), hash(2)], |candidate| {
+            std::future::ready(Ok::<_, ()>(blocks.remove(&candidate)))
+        })
+            .await;
+        assert_eq!(found, Ok(Some((hash(2), 20))));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_search_limit() {
+        let mut fetched = 0;
+        let found = find_accepting_block(hash(0), vec![hash(1)], |candidate| {
+            fetched += 1;
+            // An endless line of blocks that never merge the searched one
+            let next = fetched + 1;
+            std::future::ready(Ok::<_, ()>(Some(Descendant {
+                is_chain_block: true,
+                blue_score: 0,
+                merge_set: vec![candidate],
+                children: vec![hash(next)],
+            })))
+        })
+            .await;
+        assert_eq!(found, Ok(None));
+        assert_eq!(fetched, MAX_ACCEPTANCE_SEARCH as u64);
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_first_failed_fetch() {
+        let found = find_accepting_block(hash(0), vec![hash(1)], |_| std::future::ready(Err::<Option<Descendant>, _>("node down"))).await;
+        assert_eq!(found, Err("node down"));
+    }
+}
@@ -0,0 +1,123 @@
+use std::env;
+use std::net::IpAddr;
+use std::sync::OnceLock;
+use tokio::time::Duration;
+use crate::auth::ApiKey;
+use crate::ratelimit::{Budget, Budgets};
+
+// Runtime settings, read once from the environment
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub node_url: String,
+    pub db_path: String,
+    pub index_interval: Duration,
+    pub stats_interval: Duration,
+    pub utxo_snapshot_interval: Duration,
+    pub target_blocks_per_second: f64,
+    // Readiness fails once the virtual DAA score hasn't advanced for this long
+    pub ready_max_daa_stall: Duration,
+    pub log_level: String,
+    pub log_json: bool,
+    pub rate_limits: Budgets,
+    // Default multiplier of the anonymous budgets for new API keys
+    pub api_key_rate_factor: f64,
+    // Keys defined in the environment next to the stored ones, by secret
+    pub api_keys: Vec<(String, ApiKey)>,
+    // Bearer token for the /admin routes, which are disabled without it
+    pub admin_token: Option<String>,
+    // Networks whose X-Forwarded-For header is trusted, as address and prefix length
+    pub trusted_proxies: Vec<(IpAddr, u8)>,
+    // Lets webhooks post to loopback, link-local and private addresses, e.g. for local testing
+    pub webhooks_allow_private: bool,
+}
+
+impl Config {
+    fn from_env() -> Config {
+        let api_key_rate_factor = env_parse("XENOM_RATE_API_KEY_FACTOR", 5.0);
+        Config {
+            node_url: env_or("XENOM_NODE_URL", "ws://eu.losmuchachos.digital:19910"),
+            db_path: env_or("XENOM_API_DB", "xenom_api.db"),
+            index_interval: Duration::from_secs(env_parse("XENOM_INDEX_INTERVAL_SECS", 5)),
+            stats_interval: Duration::from_secs(env_parse("XENOM_STATS_INTERVAL_SECS", 60)),
+            utxo_snapshot_interval: Duration::from_secs(env_parse("XENOM_UTXO_SNAPSHOT_INTERVAL_SECS", 600)),
+            // The subsidy schedule advances one DAA score per second
+            target_blocks_per_second: env_parse("XENOM_TARGET_BPS", 1.0),
+            ready_max_daa_stall: Duration::from_secs(env_parse("XENOM_READY_MAX_DAA_STALL_SECS", 60)),
+            log_level: env_or("XENOM_LOG", "info"),
+            log_json: env_or("XENOM_LOG_FORMAT", "text") == "json",
+            rate_limits: Budgets {
+                cheap: env_budget("XENOM_RATE_CHEAP", Budget { burst: 60.0, per_second: 20.0 }),
+                standard: env_budget("XENOM_RATE_STANDARD", Budget { burst: 30.0, per_second: 5.0 }),
+                expensive: env_budget("XENOM_RATE_EXPENSIVE", Budget { burst: 10.0, per_second: 1.0 }),
+            },
+            api_key_rate_factor,
+            api_keys: env_or("XENOM_API_KEYS", "")
+                .split(',')
+                .filter(|entry| !entry.trim().is_empty())
+                .filter_map(|entry| parse_api_key(entry, api_key_rate_factor))
+                .collect(),
+            admin_token: env::var("XENOM_ADMIN_TOKEN").ok().filter(|token| !token.is_empty()),
+            trusted_proxies: env_or("XENOM_TRUSTED_PROXIES", "")
+                .split(',')
+                .filter(|entry| !entry.trim().is_empty())
+                .filter_map(parse_network)
+                .collect(),
+            webhooks_allow_private: env_parse("XENOM_WEBHOOKS_ALLOW_PRIVATE", false),
+        }
+    }
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::from_env)
+}
+
+fn env_or(key: &str, default: &str) -> String {
+    env::var(key).unwrap_or_else(|_| default.to_string())
+}
+
+fn env_parse<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env::var(key).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+// Budget given as burst:per_second, e.g. 30:5
+fn env_budget(key: &str, default: Budget) -> Budget {
+    env::var(key)
+        .ok()
+        .and_then(|value| {
+            let (burst, per_second) = value.split_once(':')?;
+            Some(Budget { burst: burst.trim().parse().ok()?, per_second: per_second.trim().parse().ok()? })
+        })
+        .unwrap_or(default)
+}
+
+// Address with an optional prefix length, e.g. 10.0.0.0/8 or 127.0.0.1
+fn parse_network(value: &str) -> Option<(IpAddr, u8)> {
+    let value = value.trim();
+    match value.split_once('/') {
+        Some((address, prefix)) => Some((address.parse().ok()?, prefix.parse().ok()?)),
+        None => {
+            let address: IpAddr = value.parse().ok()?;
+            Some((address, if address.is_ipv4() { 32 } else { 128 }))
+        }
+    }
+}
+
+// Key given as name:secret[:scope|scope[:rate_factor[:daily_quota]]], e.g. explorer:s3cret:batch|export:10
+fn parse_api_key(value: &str, default_rate_factor: f64) -> Option<(String, ApiKey)> {
+    let mut fields = value.trim().split(':');
+    let name = fields.next()?.to_string();
+    let secret = fields.next().filter(|secret| !secret.is_empty())?.to_string();
+    let scopes = fields.next().unwrap_or("").split('|').filter(|scope| !scope.is_empty()).map(str::to_string).collect();
+    let rate_factor = match fields.next() {
+        Some(factor) => factor.parse().ok()?,
+        None => default_rate_factor,
+    };
+    let daily_quota = match fields.next() {
+        Some(quota) => Some(quota.parse().ok()?),
+        None => None,
+    };
+    let key = ApiKey { id: format!("config:{}", name), name, scopes, rate_factor, daily_quota, created_at: 0, revoked_at: None };
+    Some((secret, key))
+}
@@ -0,0 +1,406 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use actix_web::{web, HttpResponse, Responder};
+use kaspa_rpc_core::api::rpc::RpcApi;
+use kaspa_rpc_core::{RpcBlock, RpcHash};
+use kaspa_wrpc_client::KaspaRpcClient;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::logging::ErrorBody;
+use crate::parse_hash;
+use crate::metrics;
+
+const DEFAULT_DEPTH: usize = 3;
+const MAX_DEPTH: usize = 20;
+const DEFAULT_TIP_COUNT: usize = 50;
+// Upper bound of nodes in a single graph response
+const MAX_NODES: usize = 500;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GraphQuery {
+    around: String,
+    depth: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TipGraphQuery {
+    count: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+enum Color {
+    Blue,
+    Red,
+    // Not merged by any chain block inside the returned graph
+    Unknown,
+}
+
+#[derive(Serialize, ToSchema, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum EdgeKind {
+    SelectedParent,
+    Parent,
+    Merge,
+}
+
+#[derive(Serialize, ToSchema)]
+struct Node {
+    hash: String,
+    blue_score: u64,
+    daa_score: u64,
+    timestamp: u64,
+    is_chain: bool,
+    color: Color,
+}
+
+#[derive(Serialize, ToSchema)]
+struct Edge {
+    from: String,
+    to: String,
+    kind: EdgeKind,
+}
+
+#[derive(Serialize, ToSchema)]
+struct GraphResponse {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+    truncated: bool,
+}
+
+// What the graph needs of a block
+struct GraphBlock {
+    blue_score: u64,
+    daa_score: u64,
+    timestamp: u64,
+    parents: Vec<RpcHash>,
+    children: Vec<RpcHash>,
+    is_chain_block: bool,
+    // None without verbose data
+    selected_parent: Option<RpcHash>,
+    merge_set_blues: Vec<RpcHash>,
+    merge_set_reds: Vec<RpcHash>,
+}
+
+impl GraphBlock {
+    fn of(block: RpcBlock) -> GraphBlock {
+        let parents = block.header.parents_by_level.first().cloned().unwrap_or_default();
+        let mut graph_block = GraphBlock {
+            blue_score: block.header.blue_score,
+            daa_score: block.header.daa_score,
+            timestamp: block.header.timestamp,
+            parents,
+            children: Vec::new(),
+            is_chain_block: false,
+            selected_parent: None,
+            merge_set_blues: Vec::new(),
+            merge_set_reds: Vec::new(),
+        };
+        if let Some(data) = block.verbose_data {
+            graph_block.children = data.children_hashes;
+            graph_block.is_chain_block = data.is_chain_block;
+            graph_block.selected_parent = Some(data.selected_parent_hash);
+            graph_block.merge_set_blues = data.merge_set_blues_hashes;
+            graph_block.merge_set_reds = data.merge_set_reds_hashes;
+        }
+        graph_block
+    }
+}
+
+// Breadth first walk from the start blocks, following parents and optionally children
+async fn walk<F, Fut, E>(
+    start: Vec<RpcHash>,
+    max_depth: usize,
+    max_nodes: usize,
+    follow_children: bool,
+    mut fetch: F,
+) -> Result<(HashMap<RpcHash, GraphBlock>, bool), E>
+where
+    F: FnMut(RpcHash) -> Fut,
+    Fut: Future<Output = Result<GraphBlock, E>>,
+{
+    let mut blocks: HashMap<RpcHash, GraphBlock> = HashMap::new();
+    let mut seen: HashSet<RpcHash> = start.iter().cloned().collect();
+    let mut queue: VecDeque<(RpcHash, usize)> = start.into_iter().map(|hash| (hash, 0)).collect();
+    let mut truncated = false;
+
+    while let Some((hash, depth)) = queue.pop_front() {
+        if blocks.len() >= max_nodes {
+            truncated = true;
+            break;
+        }
+        let block = fetch(hash).await?;
+        if depth < max_depth {
+            let mut next = block.parents.clone();
+            if follow_children {
+                next.extend(block.children.iter().cloned());
+            }
+            for neighbour in next {
+                if seen.insert(neighbour) {
+                    queue.push_back((neighbour, depth + 1));
+                }
+            }
+        }
+        blocks.insert(hash, block);
+    }
+
+    Ok((blocks, truncated))
+}
+
+async fn fetch_graph(
+    client: &KaspaRpcClient,
+    start: Vec<RpcHash>,
+    max_depth: usize,
+    max_nodes: usize,
+    follow_children: bool,
+) -> Result<(HashMap<RpcHash, GraphBlock>, bool), HttpResponse> {
+    walk(start, max_depth, max_nodes, follow_children, |hash| async move {
+        metrics::rpc("get_block", client.get_block(hash, false)).await.map(GraphBlock::of)
+    })
+        .await
+        .map_err(|err| HttpResponse::InternalServerError().json(format!("Failed to get block: {:?}", err)))
+}
+
+fn build_graph(blocks: HashMap<RpcHash, GraphBlock>, truncated: bool) -> GraphResponse {
+    // Colors are only known for blocks merged by a chain block that is part of the graph
+    let mut colors: HashMap<RpcHash, Color> = HashMap::new();
+    let mut edges = Vec::new();
+    for (hash, block) in &blocks {
+        if block.is_chain_block {
+            colors.insert(*hash, Color::Blue);
+            for blue in &block.merge_set_blues {
+                colors.entry(*blue).or_insert(Color::Blue);
+            }
+            for red in &block.merge_set_reds {
+                colors.insert(*red, Color::Red);
+            }
+            for merged in block.merge_set_blues.iter().chain(&block.merge_set_reds) {
+                if Some(*merged) != block.selected_parent && blocks.contains_key(merged) {
+                    edges.push(Edge { from: hash.to_string(), to: merged.to_string(), kind: EdgeKind::Merge });
+                }
+            }
+        }
+        for parent in &block.parents {
+            if blocks.contains_key(parent) {
+                let is_selected = block.selected_parent == Some(*parent);
+                let kind = if is_selected { EdgeKind::SelectedParent } else { EdgeKind::Parent };
+                edges.push(Edge { from: hash.to_string(), to: parent.to_string(), kind });
+            }
+        }
+    }
+
+    let mut nodes: Vec<Node> = blocks
+        .iter()
+        .map(|(hash, block)| Node {
+            hash: hash.to_string(),
+            blue_score: block.blue_score,
+            daa_score: block.daa_score,
+            timestamp: block.timestamp,
+            is_chain: block.is_chain_block,
+            color: colors.get(hash).copied().unwrap_or(Color::Unknown),
+        })
+        .collect();
+    nodes.sort_by(|a, b| a.blue_score.cmp(&b.blue_score).then_with(|| a.hash.cmp(&b.hash)));
+
+    GraphResponse { nodes, edges, truncated }
+}
+
+#[utoipa::path(
+    get,
+    path = "/dag/graph",
+    tag = "dag",
+    params(GraphQuery),
+    responses(
+        (status = 200, description = "Blocks and edges around a block", body = GraphResponse),
+        (status = 400, description = "Invalid hash", body = ErrorBody),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
+pub async fn get_graph(query: web::Query<GraphQuery>) -> impl Responder {
+    let around = match parse_hash(&query.around) {
+        Ok(hash) => hash,
+        Err(err) => return err,
+    };
+    let depth = query.depth.unwrap_or(DEFAULT_DEPTH).min(MAX_DEPTH);
+
+    let client = match crate::get_client().await {
+        Ok(client) => client,
+        Err(err) => return err,
+    };
+
+    let (blocks, truncated) = match fetch_graph(&client, vec![around], depth, MAX_NODES, true).await {
+        Ok(result) => result,
+        Err(err) => return err,
+    };
+    if let Err(disconnect_err) = client.disconnect().await {
+        return HttpResponse::InternalServerError().json(format!("Failed to disconnect: {}", disconnect_err));
+    }
+
+    HttpResponse::Ok().json(build_graph(blocks, truncated))
+}
+
+#[utoipa::path(
+    get,
+    path = "/dag/tip-graph",
+    tag = "dag",
+    params(TipGraphQuery),
+    responses(
+        (status = 200, description = "Most recent blocks and edges below the tips", body = GraphResponse),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
+pub async fn get_tip_graph(query: web::Query<TipGraphQuery>) -> impl Responder {
+    let count = query.count.unwrap_or(DEFAULT_TIP_COUNT).clamp(1, MAX_NODES);
+
+    let client = match crate::get_client().await {
+        Ok(client) => client,
+        Err(err) => return err,
+    };
+
+    let block_dag_info = match metrics::rpc("get_block_dag_info", client.get_block_dag_info()).await {
+        Ok(info) => info,
+        Err(err) => {
+            return HttpResponse::InternalServerError().json(format!("Failed to get block DAG info: {:?}", err));
+        }
+    };
+
+    // Walk back from the tips through parents only until enough blocks are collected
+    let (blocks, truncated) = match fetch_graph(&client, block_dag_info.tip_hashes, usize::MAX, count, false).await {
+        Ok(result) => result,
+        Err(err) => return err,
+    };
+    if let Err(disconnect_err) = client.disconnect().await {
+        return HttpResponse::InternalServerError().json(format!("Failed to disconnect: {}", disconnect_err));
+    }
+
+    HttpResponse::Ok().json(build_graph(blocks, truncated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(n: u64) -> RpcHash {
+        format!("{:064x}", n).parse().unwrap()
+    }
+
+    fn hashes(ns: &[u64]) -> Vec<RpcHash> {
+        ns.iter().map(|n| hash(*n)).collect()
+    }
+
+    fn block(blue_score: u64, parents: &[u64], selected_parent: Option<u64>) -> GraphBlock {
+        GraphBlock {
+            blue_score,
+            daa_score: blue_score,
+            timestamp: blue_score * 1000,
+            parents: hashes(parents),
+            children: Vec::new(),
+            is_chain_block: false,
+            selected_parent: selected_parent.map(hash),
+            merge_set_blues: Vec::new(),
+            merge_set_reds: Vec::new(),
+        }
+    }
+
+    fn chain_block(blue_score: u64, parents: &[u64], selected_parent: u64, blues: &[u64], reds: &[u64]) -> GraphBlock {
+        GraphBlock {
+            is_chain_block: true,
+            merge_set_blues: hashes(blues),
+            merge_set_reds: hashes(reds),
+            ..block(blue_score, parents, Some(selected_parent))
+        }
+    }
+
+    // Chain 1 <- 2 <- 5 where 5 also merges the blue 3 and the red 4, and 6 is not merged yet
+    fn fixture() -> HashMap<RpcHash, GraphBlock> {
+        HashMap::from([
+            (hash(1), GraphBlock { is_chain_block: true, ..block(1, &[], None) }),
+            (hash(2), chain_block(2, &[1], 1, &[1], &[])),
+            (hash(3), block(2, &[1], Some(1))),
+            (hash(4), block(2, &[1], Some(1))),
+            // The red 9 is outside the graph
+            (hash(5), chain_block(3, &[2, 3, 4], 2, &[2, 3], &[4, 9])),
+            (hash(6), block(4, &[5], Some(5))),
+        ])
+    }
+
+    #[test]
+    fn colors_merged_blocks_of_chain_blocks() {
+        let graph = build_graph(fixture(), false);
+        let colors: Vec<(String, bool, Color)> =
+            graph.nodes.iter().map(|node| (node.hash.clone(), node.is_chain, node.color)).collect();
+        assert_eq!(
+            colors,
+            vec![
+                (hash(1).to_string(), true, Color::Blue),
+                (hash(2).to_string(), true, Color::Blue),
+                (hash(3).to_string(), false, Color::Blue),
+                (hash(4).to_string(), false, Color::Red),
+                (hash(5).to_string(), true, Color::Blue),
+                (hash(6).to_string(), false, Color::Unknown),
+            ]
+        );
+        assert!(!graph.truncated);
+    }
+
+    #[test]
+    fn tells_merge_edges_from_parent_edges() {
+        let graph = build_graph(fixture(), true);
+        let mut edges: Vec<(String, String, EdgeKind)> =
+            graph.edges.into_iter().map(|edge| (edge.from, edge.to, edge.kind)).collect();
+        edges.sort_by_key(|(from, to, kind)| (from.clone(), to.clone(), format!("{:?}", kind)));
+        let edge = |from: u64, to: u64, kind| (hash(from).to_string(), hash(to).to_string(), kind);
+        assert_eq!(
+            edges,
+            vec![
+                edge(2, 1, EdgeKind::SelectedParent),
+                edge(3, 1, EdgeKind::SelectedParent),
+                edge(4, 1, EdgeKind::SelectedParent),
+                // The selected parent is in the merge set but only gets its parent edge
+                edge(5, 2, EdgeKind::SelectedParent),
+                edge(5, 3, EdgeKind::Merge),
+                edge(5, 3, EdgeKind::Parent),
+                edge(5, 4, EdgeKind::Merge),
+                edge(5, 4, EdgeKind::Parent),
+                edge(6, 5, EdgeKind::SelectedParent),
+            ]
+        );
+        assert!(graph.truncated);
+    }
+
+    // A line of blocks where n has the parent n - 1 and the child n + 1
+    async fn walk_line(start: u64, max_depth: usize, max_nodes: usize, follow_children: bool) -> (Vec<u64>, bool) {
+        let (blocks, truncated) = walk(vec![hash(start)], max_depth, max_nodes, follow_children, |id| async move {
+            let n = u64::from_str_radix(&id.to_string(), 16).unwrap();
+            Ok::<_, ()>(GraphBlock { children: vec![hash(n + 1)], ..block(n, &[n - 1], Some(n - 1)) })
+        })
+            .await
+            .unwrap();
+        let mut scores: Vec<u64> = blocks.values().map(|block| block.blue_score).collect();
+        scores.sort();
+        (scores, truncated)
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_depth() {
+        assert_eq!(walk_line(10, 0, MAX_NODES, true).await, (vec![10], false));
+        assert_eq!(walk_line(10, 1, MAX_NODES, true).await, (vec![9, 10, 11], false));
+        assert_eq!(walk_line(10, 2, MAX_NODES, true).await, (vec![8, 9, 10, 11, 12], false));
+        assert_eq!(walk_line(10, 2, MAX_NODES, false).await, (vec![8, 9, 10], false));
+    }
+
+    #[tokio::test]
+    async fn truncates_at_the_node_limit() {
+        assert_eq!(walk_line(10, usize::MAX, 3, false).await, (vec![8, 9, 10], true));
+        // Exactly filling the limit with nothing left to visit is not truncated
+        assert_eq!(walk_line(10, 1, 3, true).await, (vec![9, 10, 11], false));
+    }
+
+    #[tokio::test]
+    async fn stops_on_a_failed_fetch() {
+        let result = walk(vec![hash(1)], 1, MAX_NODES, false, |_| async { Err::<GraphBlock, _>("down") }).await;
+        assert_eq!(result.err(), Some("down"));
+    }
+}
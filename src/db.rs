@@ -0,0 +1,249 @@
+use std::sync::{Arc, Mutex};
+use anyhow::Context;
+use rusqlite::Connection;
+
+// Tables are created on startup if missing
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS blocks (
+    hash TEXT PRIMARY KEY,
+    daa_score INTEGER NOT NULL,
+    blue_score INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    bits INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS blocks_daa_score ON blocks (daa_score);
+CREATE INDEX IF NOT EXISTS blocks_timestamp ON blocks (timestamp);
+CREATE INDEX IF NOT EXISTS blocks_blue_score ON blocks (blue_score);
+
+CREATE TABLE IF NOT EXISTS index_state (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS samples (
+    metric TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    value REAL NOT NULL,
+    PRIMARY KEY (metric, timestamp)
+);
+
+-- Amount series in sompi, kept apart from the REAL samples so they stay exact
+CREATE TABLE IF NOT EXISTS amount_samples (
+    metric TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    sompi INTEGER NOT NULL,
+    PRIMARY KEY (metric, timestamp)
+);
+
+-- Only a hash of the secret is kept, the key itself is shown once on creation
+CREATE TABLE IF NOT EXISTS api_keys (
+    key_hash TEXT PRIMARY KEY,
+    id TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    scopes TEXT NOT NULL,
+    rate_factor REAL NOT NULL,
+    daily_quota INTEGER,
+    created_at INTEGER NOT NULL,
+    revoked_at INTEGER
+);
+
+-- Every address seen in an indexed output, the set the UTXO snapshot is taken for
+CREATE TABLE IF NOT EXISTS addresses (
+    address TEXT PRIMARY KEY,
+    first_seen_daa INTEGER NOT NULL,
+    last_seen_daa INTEGER NOT NULL
+);
+
+-- Latest UTXO snapshot of the known addresses, replaced as a whole on every refresh
+CREATE TABLE IF NOT EXISTS utxos (
+    transaction_id TEXT NOT NULL,
+    output_index INTEGER NOT NULL,
+    address TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    block_daa_score INTEGER NOT NULL,
+    is_coinbase INTEGER NOT NULL,
+    PRIMARY KEY (transaction_id, output_index)
+);
+CREATE INDEX IF NOT EXISTS utxos_address ON utxos (address);
+
+-- Snapshot being taken, written one address batch at a time and moved into utxos once complete
+CREATE TABLE IF NOT EXISTS utxos_next (
+    transaction_id TEXT NOT NULL,
+    output_index INTEGER NOT NULL,
+    address TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    block_daa_score INTEGER NOT NULL,
+    is_coinbase INTEGER NOT NULL,
+    PRIMARY KEY (transaction_id, output_index)
+);
+
+CREATE TABLE IF NOT EXISTS balances (
+    address TEXT PRIMARY KEY,
+    balance INTEGER NOT NULL,
+    utxo_count INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS balances_balance ON balances (balance);
+
+-- Transactions accepted by the virtual chain, with the first indexed block including them
+CREATE TABLE IF NOT EXISTS transactions (
+    transaction_id TEXT PRIMARY KEY,
+    block_hash TEXT NOT NULL,
+    daa_score INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL
+);
+
+-- Outputs of indexed transactions, used to resolve the address and amount of spent outpoints
+CREATE TABLE IF NOT EXISTS tx_outputs (
+    transaction_id TEXT NOT NULL,
+    output_index INTEGER NOT NULL,
+    address TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    PRIMARY KEY (transaction_id, output_index)
+);
+
+-- Net balance change of an address by one transaction, in sompi
+CREATE TABLE IF NOT EXISTS address_deltas (
+    address TEXT NOT NULL,
+    transaction_id TEXT NOT NULL,
+    daa_score INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    delta INTEGER NOT NULL,
+    PRIMARY KEY (address, transaction_id)
+);
+CREATE INDEX IF NOT EXISTS address_deltas_timestamp ON address_deltas (address, timestamp);
+
+-- One row per UTXO snapshot, amounts in sompi and ages in DAA score
+CREATE TABLE IF NOT EXISTS utxo_summaries (
+    timestamp INTEGER PRIMARY KEY,
+    virtual_daa_score INTEGER NOT NULL,
+    count INTEGER NOT NULL,
+    value INTEGER NOT NULL,
+    coinbase_count INTEGER NOT NULL,
+    coinbase_value INTEGER NOT NULL,
+    dust_count INTEGER NOT NULL,
+    dust_value INTEGER NOT NULL,
+    mean_age REAL NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS utxo_age_buckets (
+    timestamp INTEGER NOT NULL,
+    min_age INTEGER NOT NULL,
+    count INTEGER NOT NULL,
+    value INTEGER NOT NULL,
+    PRIMARY KEY (timestamp, min_age)
+);
+
+-- The secret signs the deliveries, so unlike API keys it is kept as is
+CREATE TABLE IF NOT EXISTS webhooks (
+    id TEXT PRIMARY KEY,
+    owner TEXT NOT NULL,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    min_amount INTEGER NOT NULL,
+    confirmations INTEGER NOT NULL,
+    created_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS webhooks_owner ON webhooks (owner);
+
+CREATE TABLE IF NOT EXISTS webhook_addresses (
+    webhook_id TEXT NOT NULL,
+    address TEXT NOT NULL,
+    PRIMARY KEY (webhook_id, address)
+);
+CREATE INDEX IF NOT EXISTS webhook_addresses_address ON webhook_addresses (address);
+
+-- Outbox of events, each retried until delivered or given up
+CREATE TABLE IF NOT EXISTS webhook_events (
+    id TEXT PRIMARY KEY,
+    webhook_id TEXT NOT NULL,
+    event TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    attempts INTEGER NOT NULL,
+    next_attempt_at INTEGER NOT NULL,
+    delivered_at INTEGER,
+    failed_at INTEGER
+);
+CREATE INDEX IF NOT EXISTS webhook_events_due ON webhook_events (next_attempt_at) WHERE delivered_at IS NULL AND failed_at IS NULL;
+CREATE INDEX IF NOT EXISTS webhook_events_webhook ON webhook_events (webhook_id);
+
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    event_id TEXT NOT NULL,
+    attempt INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    status INTEGER,
+    error TEXT,
+    duration_ms INTEGER NOT NULL,
+    PRIMARY KEY (event_id, attempt)
+);
+
+-- Added UTXOs waiting for the virtual to reach their webhook's confirmation depth
+CREATE TABLE IF NOT EXISTS webhook_confirmations (
+    webhook_id TEXT NOT NULL,
+    transaction_id TEXT NOT NULL,
+    output_index INTEGER NOT NULL,
+    address TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    block_daa_score INTEGER NOT NULL,
+    is_coinbase INTEGER NOT NULL,
+    target_daa_score INTEGER NOT NULL,
+    PRIMARY KEY (webhook_id, transaction_id, output_index)
+);
+CREATE INDEX IF NOT EXISTS webhook_confirmations_target ON webhook_confirmations (target_daa_score);
+
+-- Amounts in sompi, times in unix seconds
+CREATE TABLE IF NOT EXISTS payments (
+    id TEXT PRIMARY KEY,
+    owner TEXT NOT NULL,
+    address TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    confirmations INTEGER NOT NULL,
+    label TEXT,
+    start_daa_score INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL,
+    paid_at INTEGER,
+    confirmed_at INTEGER
+);
+CREATE INDEX IF NOT EXISTS payments_address ON payments (address);
+
+-- UTXOs counted towards a payment request, kept after they are spent
+CREATE TABLE IF NOT EXISTS payment_utxos (
+    payment_id TEXT NOT NULL,
+    transaction_id TEXT NOT NULL,
+    output_index INTEGER NOT NULL,
+    amount INTEGER NOT NULL,
+    block_daa_score INTEGER NOT NULL,
+    PRIMARY KEY (payment_id, transaction_id, output_index)
+);
+";
+
+// Local SQLite storage shared by the background tasks and the handlers
+#[derive(Clone)]
+pub struct Db {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Db {
+    pub fn open(path: &str) -> anyhow::Result<Db> {
+        let conn = Connection::open(path).with_context(|| format!("Failed to open database {}", path))?;
+        conn.execute_batch(SCHEMA).context("Failed to create database schema")?;
+        Ok(Db { conn: Arc::new(Mutex::new(conn)) })
+    }
+
+    // Run a query on the blocking thread pool so handlers don't stall the executor
+    pub async fn call<F, T>(&self, f: F) -> anyhow::Result<T>
+    where
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let conn = self.conn.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().unwrap();
+            f(&mut conn)
+        })
+            .await
+            .context("Database task panicked")?;
+        result.context("Database query failed")
+    }
+}
@@ -0,0 +1,386 @@
+use actix_web::{web, HttpResponse, Responder};
+use chrono::Utc;
+use kaspa_rpc_core::api::rpc::RpcApi;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::logging::ErrorBody;
+use crate::blocks::parse_time;
+use crate::db::Db;
+use crate::metrics;
+use crate::stats;
+
+const DEFAULT_RANGE_MS: u64 = 24 * 60 * 60 * 1000;
+const DEFAULT_INTERVAL_SECS: u64 = 60 * 60;
+
+// 256-bit target in big-endian byte order
+pub type Target = [u8; 32];
+
+// Easiest allowed target (2^255 - 1, which rounds to 2^255 as f64), difficulty is relative to it
+fn max_target() -> f64 {
+    2f64.powi(255)
+}
+
+// Expand compact bits into the full target, None if the target doesn't fit into 256 bits
+pub fn bits_to_target(bits: u32) -> Option<Target> {
+    let exponent = (bits >> 24) as usize;
+    let mantissa = bits & 0x007f_ffff;
+    let mut target = [0u8; 32];
+    if exponent <= 3 {
+        let value = mantissa >> (8 * (3 - exponent));
+        target[29..].copy_from_slice(&value.to_be_bytes()[1..]);
+        return Some(target);
+    }
+    let mantissa_bytes = &mantissa.to_be_bytes()[1..];
+    for (i, byte) in mantissa_bytes.iter().enumerate() {
+        // Position of this byte counted from the least significant end
+        let position = exponent - 1 - i;
+        if position >= 32 {
+            if *byte != 0 {
+                return None;
+            }
+            continue;
+        }
+        target[31 - position] = *byte;
+    }
+    Some(target)
+}
+
+// Compress a target into compact bits, dropping precision below the top three bytes
+pub fn target_to_bits(target: &Target) -> u32 {
+    let Some(first) = target.iter().position(|byte| *byte != 0) else { return 0 };
+    let mut size = (32 - first) as u32;
+    let mut mantissa = target[first..].iter().take(3).fold(0u32, |acc, byte| (acc << 8) | *byte as u32);
+    if size < 3 {
+        mantissa <<= 8 * (3 - size);
+    }
+    // The top mantissa bit is a sign bit in the compact format
+    if mantissa & 0x0080_0000 != 0 {
+        mantissa >>= 8;
+        size += 1;
+    }
+    (size << 24) | mantissa
+}
+
+pub fn target_to_f64(target: &Target) -> f64 {
+    target.iter().fold(0.0, |acc, byte| acc * 256.0 + *byte as f64)
+}
+
+pub fn target_to_hex(target: &Target) -> String {
+    target.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn bits_to_difficulty(bits: u32) -> Option<f64> {
+    let target = target_to_f64(&bits_to_target(bits)?);
+    (target > 0.0).then(|| max_target() / target)
+}
+
+// Compact bits of the target matching a difficulty, None for difficulties below 1 or not finite
+pub fn difficulty_to_bits(difficulty: f64) -> Option<u32> {
+    if !difficulty.is_finite() || difficulty < 1.0 {
+        return None;
+    }
+    let mut value = (max_target() / difficulty).floor();
+    if value < 1.0 {
+        return None;
+    }
+    // The float is an integer now, so its base 256 digits are exact
+    let mut target = [0u8; 32];
+    for byte in target.iter_mut().rev() {
+        *byte = (value % 256.0) as u8;
+        value = (value / 256.0).floor();
+    }
+    Some(target_to_bits(&target))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DifficultyQuery {
+    from: Option<String>,
+    to: Option<String>,
+    // Bucket width in seconds
+    interval: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct DifficultyBucket {
+    timestamp: u64,
+    avg: f64,
+    min: f64,
+    max: f64,
+    blocks: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct DifficultyResponse {
+    difficulty: f64,
+    sink: String,
+    bits: u32,
+    bits_hex: String,
+    target: String,
+    interval: u64,
+    history: Vec<DifficultyBucket>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ConvertQuery {
+    // Compact bits as hex, with or without 0x prefix
+    bits: Option<String>,
+    // Full target as 64 hex characters
+    target: Option<String>,
+    difficulty: Option<f64>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ConvertResponse {
+    bits: u32,
+    bits_hex: String,
+    target: String,
+    difficulty: Option<f64>,
+}
+
+fn parse_target(value: &str) -> Option<Target> {
+    let value = value.strip_prefix("0x").unwrap_or(value);
+    // from_str_radix would also take a sign, so only plain hex digits go through
+    if value.is_empty() || value.len() > 64 || !value.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    let padded = format!("{:0>64}", value);
+    let mut target = [0u8; 32];
+    for (i, byte) in target.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&padded[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(target)
+}
+
+// Convert between compact bits, full target and difficulty, whichever one is given
+#[utoipa::path(
+    get,
+    path = "/info/difficulty/convert",
+    tag = "info",
+    params(ConvertQuery),
+    responses(
+        (status = 200, description = "The value in every representation", body = ConvertResponse),
+        (status = 400, description = "Invalid or ambiguous input", body = ErrorBody),
+    )
+)]
+pub async fn convert(query: web::Query<ConvertQuery>) -> impl Responder {
+    let bits = match (query.bits.as_deref(), query.target.as_deref(), query.difficulty) {
+        (Some(bits), None, None) => u32::from_str_radix(bits.trim_start_matches("0x"), 16).ok(),
+        (None, Some(target), None) => parse_target(target).map(|target| target_to_bits(&target)),
+        (None, None, Some(difficulty)) => difficulty_to_bits(difficulty),
+        _ => return HttpResponse::BadRequest().json("Specify exactly one of bits, target or difficulty"),
+    };
+    let Some(bits) = bits else { return HttpResponse::BadRequest().json("Invalid bits, target or difficulty") };
+    let Some(target) = bits_to_target(bits) else {
+        return HttpResponse::BadRequest().json(format!("Target of bits {:08x} exceeds 256 bits", bits));
+    };
+
+    HttpResponse::Ok().json(ConvertResponse {
+        bits,
+        bits_hex: format!("{:08x}", bits),
+        target: target_to_hex(&target),
+        difficulty: bits_to_difficulty(bits),
+    })
+}
+
+// Difficulty per time bucket from the bits of indexed headers
+async fn difficulty_history(db: &Db, from: u64, to: u64, bucket_ms: i64) -> anyhow::Result<Vec<DifficultyBucket>> {
+    let rows = db
+        .call(move |conn| {
+            // Bits only change with retargeting, so grouping by them keeps the result small
+            let mut stmt = conn.prepare(
+                "SELECT (timestamp / ?1) * ?1 AS bucket, bits, COUNT(*) FROM blocks
+                 WHERE timestamp BETWEEN ?2 AND ?3 GROUP BY bucket, bits ORDER BY bucket",
+            )?;
+            let rows = stmt.query_map(params![bucket_ms, from as i64, to as i64], |row| {
+                Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u32, row.get::<_, i64>(2)? as u64))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await?;
+
+    let mut history: Vec<DifficultyBucket> = Vec::new();
+    for (timestamp, bits, blocks) in rows {
+        let Some(difficulty) = bits_to_difficulty(bits) else { continue };
+        match history.last_mut() {
+            Some(bucket) if bucket.timestamp == timestamp => {
+                bucket.avg = (bucket.avg * bucket.blocks as f64 + difficulty * blocks as f64) / (bucket.blocks + blocks) as f64;
+                bucket.min = bucket.min.min(difficulty);
+                bucket.max = bucket.max.max(difficulty);
+                bucket.blocks += blocks;
+            }
+            _ => history.push(DifficultyBucket { timestamp, avg: difficulty, min: difficulty, max: difficulty, blocks }),
+        }
+    }
+    Ok(history)
+}
+
+#[utoipa::path(
+    get,
+    path = "/info/difficulty",
+    tag = "info",
+    params(DifficultyQuery),
+    responses(
+        (status = 200, description = "Current difficulty and its history from the index", body = DifficultyResponse),
+        (status = 400, description = "Invalid range", body = ErrorBody),
+        (status = 500, description = "Index or node request failed", body = ErrorBody),
+    )
+)]
+pub async fn get_difficulty(query: web::Query<DifficultyQuery>, db: web::Data<Db>) -> impl Responder {
+    let to = match query.to.as_deref() {
+        // SQLite integers are signed, later times are clamped to i64::MAX
+        Some(to) => match parse_time(to) {
+            Some(to) => to.min(i64::MAX as u64),
+            None => return HttpResponse::BadRequest().json("Invalid to time"),
+        },
+        None => Utc::now().timestamp_millis() as u64,
+    };
+    let from = match query.from.as_deref() {
+        Some(from) => match parse_time(from) {
+            Some(from) => from,
+            None => return HttpResponse::BadRequest().json("Invalid from time"),
+        },
+        None => to.saturating_sub(DEFAULT_RANGE_MS),
+    };
+    if from > to {
+        return HttpResponse::BadRequest().json("from must not be after to");
+    }
+    let (interval, bucket_ms) = match stats::bucket_width(from, to, query.interval.unwrap_or(DEFAULT_INTERVAL_SECS)) {
+        Some(width) => width,
+        None => return HttpResponse::BadRequest().json("interval is too large"),
+    };
+
+    let client = match crate::get_client().await {
+        Ok(client) => client,
+        Err(err) => return err,
+    };
+
+    let block_dag_info = match metrics::rpc("get_block_dag_info", client.get_block_dag_info()).await {
+        Ok(info) => info,
+        Err(err) => {
+            return HttpResponse::InternalServerError().json(format!("Failed to get block DAG info: {:?}", err));
+        }
+    };
+    let sink = match metrics::rpc("get_block", client.get_block(block_dag_info.sink, false)).await {
+        Ok(block) => block,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to get block: {:?}", err)),
+    };
+    if let Err(disconnect_err) = client.disconnect().await {
+        return HttpResponse::InternalServerError().json(format!("Failed to disconnect: {}", disconnect_err));
+    }
+
+    let bits = sink.header.bits;
+    let target = match bits_to_target(bits) {
+        Some(target) => target,
+        None => return HttpResponse::InternalServerError().json(format!("Invalid compact target bits: {:#010x}", bits)),
+    };
+
+    let history = match difficulty_history(&db, from, to, bucket_ms).await {
+        Ok(history) => history,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to query block index: {:?}", err)),
+    };
+
+    HttpResponse::Ok().json(DifficultyResponse {
+        difficulty: block_dag_info.difficulty,
+        sink: block_dag_info.sink.to_string(),
+        bits,
+        bits_hex: format!("{:08x}", bits),
+        target: target_to_hex(&target),
+        interval,
+        history,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(hex: &str) -> Target {
+        parse_target(hex).unwrap()
+    }
+
+    #[test]
+    fn expands_known_mainnet_bits() {
+        // Bitcoin mainnet genesis and block 100000, which use the same compact format
+        assert_eq!(
+            target_to_hex(&bits_to_target(0x1d00ffff).unwrap()),
+            "00000000ffff0000000000000000000000000000000000000000000000000000"
+        );
+        assert_eq!(
+            target_to_hex(&bits_to_target(0x1b04864c).unwrap()),
+            "000000000004864c000000000000000000000000000000000000000000000000"
+        );
+        assert_eq!(target_to_bits(&target("00000000ffff0000000000000000000000000000000000000000000000000000")), 0x1d00ffff);
+        assert_eq!(target_to_bits(&target("000000000004864c000000000000000000000000000000000000000000000000")), 0x1b04864c);
+    }
+
+    #[test]
+    fn handles_exponents_up_to_three() {
+        // Bits, expanded target and the canonical bits of that target
+        let cases = [
+            (0x01003456, "00", 0x00000000),
+            (0x01123456, "12", 0x01120000),
+            (0x02123456, "1234", 0x02123400),
+            (0x03123456, "123456", 0x03123456),
+            (0x04123456, "12345600", 0x04123456),
+            (0x05009234, "92340000", 0x05009234),
+        ];
+        for (bits, expanded, canonical) in cases {
+            let expanded = target(expanded);
+            assert_eq!(bits_to_target(bits), Some(expanded), "bits {:08x}", bits);
+            assert_eq!(target_to_bits(&expanded), canonical, "bits {:08x}", bits);
+        }
+    }
+
+    #[test]
+    fn normalises_mantissas_with_the_sign_bit_set() {
+        // 0x80 as a mantissa would read as negative, so it moves one byte down
+        assert_eq!(target_to_bits(&target("80")), 0x02008000);
+        assert_eq!(target_to_bits(&target("800000")), 0x04008000);
+        assert_eq!(target_to_bits(&target("8000000000000000000000000000000000000000000000000000000000000000")), 0x21008000);
+        assert_eq!(bits_to_target(0x02008000), Some(target("80")));
+        // A set sign bit in given bits is ignored rather than making the target negative
+        assert_eq!(bits_to_target(0x04923456), Some(target("12345600")));
+    }
+
+    #[test]
+    fn parses_only_hex_targets() {
+        assert_eq!(parse_target("0x1234"), Some(target("1234")));
+        assert_eq!(parse_target("ABCdef"), Some(target("abcdef")));
+        assert_eq!(parse_target("abc"), Some(target("0abc")));
+        assert!(parse_target(&"f".repeat(64)).is_some());
+        for invalid in ["", "0x", "+1", "-1", "1+2", "0x+f", "0x0x12", " 12", "12 ", "g0", "é1", &"f".repeat(65)] {
+            assert_eq!(parse_target(invalid), None, "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn rejects_targets_beyond_256_bits() {
+        assert_eq!(bits_to_target(0x21010000), None);
+        assert_eq!(bits_to_target(0xff123456), None);
+        assert!(bits_to_target(0x21008000).is_some());
+    }
+
+    #[test]
+    fn round_trips_bits_through_target_and_difficulty() {
+        for bits in [0x1d00ffff, 0x1b04864c, 0x1a0a2f5e, 0x1e7fffff, 0x207fffff, 0x03123456, 0x04123456, 0x02008000] {
+            assert_eq!(target_to_bits(&bits_to_target(bits).unwrap()), bits, "bits {:08x}", bits);
+            assert_eq!(difficulty_to_bits(bits_to_difficulty(bits).unwrap()), Some(bits), "bits {:08x}", bits);
+        }
+    }
+
+    #[test]
+    fn converts_difficulties() {
+        // Difficulty is relative to 2^255, so the easiest target is difficulty 1
+        assert_eq!(difficulty_to_bits(1.0), Some(0x21008000));
+        assert_eq!(bits_to_difficulty(0x21008000), Some(1.0));
+        assert_eq!(difficulty_to_bits(2.0), Some(0x20400000));
+        assert_eq!(bits_to_difficulty(0x1e7fffff).map(f64::round), Some(65536.0));
+        assert_eq!(bits_to_difficulty(0x01003456), None);
+        assert_eq!(difficulty_to_bits(0.5), None);
+        assert_eq!(difficulty_to_bits(f64::NAN), None);
+        assert_eq!(difficulty_to_bits(f64::INFINITY), None);
+    }
+}
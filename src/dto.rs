@@ -0,0 +1,445 @@
+use kaspa_rpc_core::{
+    GetBlockDagInfoResponse, GetInfoResponse, GetServerInfoResponse, RpcBlock, RpcTransaction, RpcTransactionInput,
+    RpcTransactionOutput,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+use crate::amount::{Amount, Unit};
+use crate::CoinSupply;
+
+// Public response shapes of the /v1 routes, converted from the node RPC types so upstream
+// changes to those types can't leak into the API
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockHeaderDto {
+    pub hash: String,
+    pub version: u16,
+    // Direct parents first, followed by the parents of each higher level
+    pub parents_by_level: Vec<Vec<String>>,
+    pub hash_merkle_root: String,
+    pub accepted_id_merkle_root: String,
+    pub utxo_commitment: String,
+    // Unix milliseconds
+    pub timestamp: u64,
+    pub bits: u32,
+    pub nonce: String,
+    pub daa_score: u64,
+    pub blue_score: u64,
+    // Hex encoded accumulated blue work
+    pub blue_work: String,
+    pub pruning_point: String,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockDto {
+    pub header: BlockHeaderDto,
+    pub difficulty: Option<f64>,
+    pub selected_parent_hash: Option<String>,
+    pub is_chain_block: Option<bool>,
+    pub children_hashes: Vec<String>,
+    pub merge_set_blues_hashes: Vec<String>,
+    pub merge_set_reds_hashes: Vec<String>,
+    pub transaction_ids: Vec<String>,
+    // Only filled when transactions were requested
+    pub transactions: Vec<TransactionDto>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OutpointDto {
+    pub transaction_id: String,
+    pub index: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionInputDto {
+    pub previous_outpoint: OutpointDto,
+    pub signature_script: String,
+    pub sequence: String,
+    pub sig_op_count: u8,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionOutputDto {
+    pub amount: Amount,
+    pub script_public_key: String,
+    pub script_public_key_version: u16,
+    pub script_public_key_type: Option<String>,
+    pub address: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionDto {
+    pub transaction_id: Option<String>,
+    pub version: u16,
+    pub inputs: Vec<TransactionInputDto>,
+    pub outputs: Vec<TransactionOutputDto>,
+    pub lock_time: String,
+    pub subnetwork_id: String,
+    pub gas: String,
+    pub payload: String,
+    pub mass: String,
+    pub block_hash: Option<String>,
+    pub block_time: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockDagInfoDto {
+    pub network: String,
+    pub block_count: u64,
+    pub header_count: u64,
+    pub tip_hashes: Vec<String>,
+    pub difficulty: f64,
+    // Unix milliseconds of the median of past blocks
+    pub past_median_time: u64,
+    pub virtual_parent_hashes: Vec<String>,
+    pub pruning_point_hash: String,
+    pub virtual_daa_score: u64,
+    pub sink: String,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeInfoDto {
+    pub server_version: String,
+    pub network: String,
+    pub p2p_id: String,
+    pub mempool_size: u64,
+    pub is_utxo_indexed: bool,
+    pub is_synced: bool,
+    pub virtual_daa_score: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SupplyDto {
+    pub circulating: Amount,
+    pub max: Amount,
+}
+
+fn hashes<T: ToString>(hashes: &[T]) -> Vec<String> {
+    hashes.iter().map(ToString::to_string).collect()
+}
+
+impl From<&RpcTransactionInput> for TransactionInputDto {
+    fn from(input: &RpcTransactionInput) -> Self {
+        TransactionInputDto {
+            previous_outpoint: OutpointDto {
+                transaction_id: input.previous_outpoint.transaction_id.to_string(),
+                index: input.previous_outpoint.index,
+            },
+            signature_script: hex::encode(&input.signature_script),
+            sequence: input.sequence.to_string(),
+            sig_op_count: input.sig_op_count,
+        }
+    }
+}
+
+impl TransactionOutputDto {
+    pub fn new(output: &RpcTransactionOutput, unit: Unit) -> Self {
+        let verbose = output.verbose_data.as_ref();
+        TransactionOutputDto {
+            amount: Amount::new(output.value, unit),
+            script_public_key: hex::encode(output.script_public_key.script()),
+            script_public_key_version: output.script_public_key.version(),
+            script_public_key_type: verbose.map(|data| data.script_public_key_type.to_string()),
+            address: verbose.map(|data| data.script_public_key_address.to_string()),
+        }
+    }
+}
+
+impl TransactionDto {
+    pub fn new(tx: &RpcTransaction, unit: Unit) -> Self {
+        let verbose = tx.verbose_data.as_ref();
+        TransactionDto {
+            transaction_id: verbose.map(|data| data.transaction_id.to_string()),
+            version: tx.version,
+            inputs: tx.inputs.iter().map(TransactionInputDto::from).collect(),
+            outputs: tx.outputs.iter().map(|output| TransactionOutputDto::new(output, unit)).collect(),
+            lock_time: tx.lock_time.to_string(),
+            subnetwork_id: tx.subnetwork_id.to_string(),
+            gas: tx.gas.to_string(),
+            payload: hex::encode(&tx.payload),
+            mass: tx.mass.to_string(),
+            block_hash: verbose.map(|data| data.block_hash.to_string()),
+            block_time: verbose.map(|data| data.block_time),
+        }
+    }
+}
+
+impl BlockDto {
+    pub fn new(block: &RpcBlock, unit: Unit) -> Self {
+        let header = &block.header;
+        let verbose = block.verbose_data.as_ref();
+        BlockDto {
+            header: BlockHeaderDto {
+                hash: header.hash.to_string(),
+                version: header.version,
+                parents_by_level: header.parents_by_level.iter().map(|level| hashes(level)).collect(),
+                hash_merkle_root: header.hash_merkle_root.to_string(),
+                accepted_id_merkle_root: header.accepted_id_merkle_root.to_string(),
+                utxo_commitment: header.utxo_commitment.to_string(),
+                timestamp: header.timestamp,
+                bits: header.bits,
+                nonce: header.nonce.to_string(),
+                daa_score: header.daa_score,
+                blue_score: header.blue_score,
+                blue_work: format!("{:x}", header.blue_work),
+                pruning_point: header.pruning_point.to_string(),
+            },
+            difficulty: verbose.map(|data| data.difficulty),
+            selected_parent_hash: verbose.map(|data| data.selected_parent_hash.to_string()),
+            is_chain_block: verbose.map(|data| data.is_chain_block),
+            children_hashes: verbose.map(|data| hashes(&data.children_hashes)).unwrap_or_default(),
+            merge_set_blues_hashes: verbose.map(|data| hashes(&data.merge_set_blues_hashes)).unwrap_or_default(),
+            merge_set_reds_hashes: verbose.map(|data| hashes(&data.merge_set_reds_hashes)).unwrap_or_default(),
+            transaction_ids: verbose.map(|data| hashes(&data.transaction_ids)).unwrap_or_default(),
+            transactions: block.transactions.iter().map(|tx| TransactionDto::new(tx, unit)).collect(),
+        }
+    }
+}
+
+impl From<&GetBlockDagInfoResponse> for BlockDagInfoDto {
+    fn from(info: &GetBlockDagInfoResponse) -> Self {
+        BlockDagInfoDto {
+            network: info.network.to_string(),
+            block_count: info.block_count,
+            header_count: info.header_count,
+            tip_hashes: hashes(&info.tip_hashes),
+            difficulty: info.difficulty,
+            past_median_time: info.past_median_time,
+            virtual_parent_hashes: hashes(&info.virtual_parent_hashes),
+            pruning_point_hash: info.pruning_point_hash.to_string(),
+            virtual_daa_score: info.virtual_daa_score,
+            sink: info.sink.to_string(),
+        }
+    }
+}
+
+impl From<(&GetInfoResponse, &GetServerInfoResponse)> for NodeInfoDto {
+    fn from((info, server): (&GetInfoResponse, &GetServerInfoResponse)) -> Self {
+        NodeInfoDto {
+            server_version: info.server_version.clone(),
+            network: server.network_id.to_string(),
+            p2p_id: info.p2p_id.clone(),
+            mempool_size: info.mempool_size,
+            is_utxo_indexed: info.is_utxo_indexed,
+            is_synced: info.is_synced,
+            virtual_daa_score: server.virtual_daa_score,
+        }
+    }
+}
+
+impl SupplyDto {
+    pub fn new(supply: &CoinSupply, unit: Unit) -> Self {
+        SupplyDto {
+            circulating: Amount::new(supply.circulating_sompi, unit),
+            max: Amount::new(supply.max_sompi, unit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn header() -> BlockHeaderDto {
+        BlockHeaderDto {
+            hash: "h".to_string(),
+            version: 1,
+            parents_by_level: vec![vec!["p".to_string()]],
+            hash_merkle_root: "m".to_string(),
+            accepted_id_merkle_root: "a".to_string(),
+            utxo_commitment: "u".to_string(),
+            timestamp: 1_000,
+            bits: 7,
+            nonce: "9".to_string(),
+            daa_score: 10,
+            blue_score: 11,
+            blue_work: "ff".to_string(),
+            pruning_point: "pp".to_string(),
+        }
+    }
+
+    fn transaction() -> TransactionDto {
+        TransactionDto {
+            transaction_id: Some("t".to_string()),
+            version: 0,
+            inputs: vec![TransactionInputDto {
+                previous_outpoint: OutpointDto { transaction_id: "prev".to_string(), index: 1 },
+                signature_script: "00".to_string(),
+                sequence: "2".to_string(),
+                sig_op_count: 1,
+            }],
+            outputs: vec![TransactionOutputDto {
+                amount: Amount::new(150_000_000, Unit::Both),
+                script_public_key: "20ab".to_string(),
+                script_public_key_version: 0,
+                script_public_key_type: Some("pubkey".to_string()),
+                address: Some("xenom:q".to_string()),
+            }],
+            lock_time: "0".to_string(),
+            subnetwork_id: "s".to_string(),
+            gas: "0".to_string(),
+            payload: "".to_string(),
+            mass: "100".to_string(),
+            block_hash: None,
+            block_time: None,
+        }
+    }
+
+    #[test]
+    fn block_header_fields_are_camel_case() {
+        assert_eq!(
+            serde_json::to_value(header()).unwrap(),
+            json!({
+                "hash": "h",
+                "version": 1,
+                "parentsByLevel": [["p"]],
+                "hashMerkleRoot": "m",
+                "acceptedIdMerkleRoot": "a",
+                "utxoCommitment": "u",
+                "timestamp": 1_000,
+                "bits": 7,
+                "nonce": "9",
+                "daaScore": 10,
+                "blueScore": 11,
+                "blueWork": "ff",
+                "pruningPoint": "pp",
+            })
+        );
+    }
+
+    #[test]
+    fn block_fields_are_camel_case() {
+        let block = BlockDto {
+            header: header(),
+            difficulty: Some(1.5),
+            selected_parent_hash: None,
+            is_chain_block: Some(true),
+            children_hashes: vec!["c".to_string()],
+            merge_set_blues_hashes: vec![],
+            merge_set_reds_hashes: vec![],
+            transaction_ids: vec!["t".to_string()],
+            transactions: vec![],
+        };
+        let value = serde_json::to_value(block).unwrap();
+        let mut keys: Vec<&str> = value.as_object().unwrap().keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        assert_eq!(
+            keys,
+            [
+                "childrenHashes",
+                "difficulty",
+                "header",
+                "isChainBlock",
+                "mergeSetBluesHashes",
+                "mergeSetRedsHashes",
+                "selectedParentHash",
+                "transactionIds",
+                "transactions",
+            ]
+        );
+        assert_eq!(value["selectedParentHash"], json!(null));
+        assert_eq!(value["header"]["daaScore"], 10);
+    }
+
+    #[test]
+    fn transaction_fields_are_camel_case() {
+        assert_eq!(
+            serde_json::to_value(transaction()).unwrap(),
+            json!({
+                "transactionId": "t",
+                "version": 0,
+                "inputs": [{
+                    "previousOutpoint": { "transactionId": "prev", "index": 1 },
+                    "signatureScript": "00",
+                    "sequence": "2",
+                    "sigOpCount": 1,
+                }],
+                "outputs": [{
+                    "amount": { "sompi": "150000000", "xen": "1.5" },
+                    "scriptPublicKey": "20ab",
+                    "scriptPublicKeyVersion": 0,
+                    "scriptPublicKeyType": "pubkey",
+                    "address": "xenom:q",
+                }],
+                "lockTime": "0",
+                "subnetworkId": "s",
+                "gas": "0",
+                "payload": "",
+                "mass": "100",
+                "blockHash": null,
+                "blockTime": null,
+            })
+        );
+    }
+
+    #[test]
+    fn block_dag_info_fields_are_camel_case() {
+        let info = BlockDagInfoDto {
+            network: "xenom-mainnet".to_string(),
+            block_count: 1,
+            header_count: 2,
+            tip_hashes: vec!["tip".to_string()],
+            difficulty: 3.0,
+            past_median_time: 4,
+            virtual_parent_hashes: vec!["v".to_string()],
+            pruning_point_hash: "pp".to_string(),
+            virtual_daa_score: 5,
+            sink: "s".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(info).unwrap(),
+            json!({
+                "network": "xenom-mainnet",
+                "blockCount": 1,
+                "headerCount": 2,
+                "tipHashes": ["tip"],
+                "difficulty": 3.0,
+                "pastMedianTime": 4,
+                "virtualParentHashes": ["v"],
+                "pruningPointHash": "pp",
+                "virtualDaaScore": 5,
+                "sink": "s",
+            })
+        );
+    }
+
+    #[test]
+    fn node_info_fields_are_camel_case() {
+        let info = NodeInfoDto {
+            server_version: "0.15.2".to_string(),
+            network: "xenom-mainnet".to_string(),
+            p2p_id: "id".to_string(),
+            mempool_size: 3,
+            is_utxo_indexed: true,
+            is_synced: false,
+            virtual_daa_score: 5,
+        };
+        assert_eq!(
+            serde_json::to_value(info).unwrap(),
+            json!({
+                "serverVersion": "0.15.2",
+                "network": "xenom-mainnet",
+                "p2pId": "id",
+                "mempoolSize": 3,
+                "isUtxoIndexed": true,
+                "isSynced": false,
+                "virtualDaaScore": 5,
+            })
+        );
+    }
+
+    #[test]
+    fn supply_amounts_follow_the_unit() {
+        let supply = SupplyDto { circulating: Amount::new(100_000_000, Unit::Xen), max: Amount::new(200_000_000, Unit::Xen) };
+        assert_eq!(serde_json::to_value(supply).unwrap(), json!({ "circulating": { "xen": "1" }, "max": { "xen": "2" } }));
+    }
+}
@@ -0,0 +1,434 @@
+use std::future::Future;
+use actix_web::web::Bytes;
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, SecondsFormat};
+use futures_util::{stream, Stream, StreamExt};
+use kaspa_rpc_core::RpcAddress;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use utoipa::{IntoParams, ToSchema};
+use crate::amount::format_xen;
+use crate::blocks::parse_time;
+use crate::db::Db;
+use crate::logging::ErrorBody;
+
+// Rows read from the database per streamed chunk
+const PAGE_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Csv,
+    Jsonl,
+}
+
+impl Format {
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv; charset=utf-8",
+            Format::Jsonl => "application/x-ndjson",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Jsonl => "jsonl",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TransactionExportQuery {
+    from: Option<String>,
+    to: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BlockExportQuery {
+    #[serde(rename = "fromDaa")]
+    from_daa: u64,
+    #[serde(rename = "toDaa")]
+    to_daa: Option<u64>,
+    // csv (default) or jsonl
+    format: Option<Format>,
+}
+
+// A row of an export, read page by page after the cursor of the last row streamed
+trait ExportRow: Serialize {
+    type Cursor;
+    const CSV_HEADER: &'static str;
+
+    fn cursor(&self) -> Self::Cursor;
+    fn csv(&self) -> String;
+}
+
+// Net balance change of the address by one transaction
+#[derive(Serialize)]
+struct TransactionRow {
+    // UTC, RFC 3339
+    timestamp: String,
+    transaction_id: String,
+    daa_score: u64,
+    // Signed, negative when the address paid more than it received
+    amount_sompi: String,
+    amount_xen: String,
+    #[serde(skip)]
+    timestamp_ms: u64,
+}
+
+impl ExportRow for TransactionRow {
+    type Cursor = (u64, String);
+    const CSV_HEADER: &'static str = "timestamp,transaction_id,daa_score,amount_sompi,amount_xen";
+
+    fn cursor(&self) -> Self::Cursor {
+        (self.timestamp_ms, self.transaction_id.clone())
+    }
+
+    fn csv(&self) -> String {
+        format!("{},{},{},{},{}", self.timestamp, csv_field(&self.transaction_id), self.daa_score, self.amount_sompi, self.amount_xen)
+    }
+}
+
+#[derive(Serialize)]
+struct BlockRow {
+    hash: String,
+    daa_score: u64,
+    blue_score: u64,
+    // UTC, RFC 3339
+    timestamp: String,
+    bits: u32,
+}
+
+impl ExportRow for BlockRow {
+    type Cursor = (u64, String);
+    const CSV_HEADER: &'static str = "hash,daa_score,blue_score,timestamp,bits";
+
+    fn cursor(&self) -> Self::Cursor {
+        (self.daa_score, self.hash.clone())
+    }
+
+    fn csv(&self) -> String {
+        format!("{},{},{},{},{}", csv_field(&self.hash), self.daa_score, self.blue_score, self.timestamp, self.bits)
+    }
+}
+
+// Quotes a text field holding a separator, quote or line break, doubling its quotes (RFC 4180)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// Range bounds beyond SQLite integers match like the largest one
+fn sqlite_int(value: u64) -> i64 {
+    i64::try_from(value).unwrap_or(i64::MAX)
+}
+
+fn utc(timestamp_ms: u64) -> String {
+    DateTime::from_timestamp_millis(timestamp_ms as i64)
+        .map(|date| date.to_rfc3339_opts(SecondsFormat::Millis, true))
+        .unwrap_or_default()
+}
+
+fn signed_xen(sompi: i64) -> String {
+    let xen = format_xen(sompi.unsigned_abs());
+    if sompi < 0 {
+        format!("-{}", xen)
+    } else {
+        xen
+    }
+}
+
+// Streams the rows one page per chunk, so a large export never sits in memory as a whole
+fn rows<R, F, Fut>(format: Format, fetch: F) -> impl Stream<Item = Result<Bytes, std::io::Error>>
+where
+    R: ExportRow,
+    F: Fn(Option<R::Cursor>) -> Fut,
+    Fut: Future<Output = anyhow::Result<Vec<R>>>,
+{
+    let header = (format == Format::Csv).then(|| Ok(Bytes::from(format!("{}\n", R::CSV_HEADER))));
+    // The outer None marks the end of the export, the inner one the first page
+    let pages = stream::unfold((fetch, Some(None)), move |(fetch, cursor)| async move {
+        let cursor = cursor?;
+        let rows = match fetch(cursor).await {
+            Ok(rows) => rows,
+            Err(err) => {
+                // The status is already sent, cutting the stream short is all that is left
+                error!(error = ?err, "Export failed");
+                return Some((Err(std::io::Error::other("Export failed")), (fetch, None)));
+            }
+        };
+        let last = rows.last()?;
+        let next = (rows.len() == PAGE_SIZE).then(|| Some(last.cursor()));
+        let mut chunk = String::new();
+        for row in &rows {
+            match format {
+                Format::Csv => chunk.push_str(&row.csv()),
+                Format::Jsonl => chunk.push_str(&serde_json::to_string(row).unwrap_or_default()),
+            }
+            chunk.push('\n');
+        }
+        Some((Ok(Bytes::from(chunk)), (fetch, next)))
+    });
+    stream::iter(header).chain(pages)
+}
+
+fn attachment<S>(format: Format, name: &str, body: S) -> HttpResponse
+where
+    S: Stream<Item = Result<Bytes, std::io::Error>> + 'static,
+{
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}.{}\"", name, format.extension())))
+        .streaming(body)
+}
+
+async fn transaction_page(
+    db: Db,
+    address: String,
+    from: u64,
+    to: u64,
+    cursor: Option<(u64, String)>,
+) -> anyhow::Result<Vec<TransactionRow>> {
+    db.call(move |conn| {
+        let (after_timestamp, after_id) = cursor.map_or((-1, String::new()), |(timestamp, id)| (timestamp as i64, id));
+        let mut stmt = conn.prepare(
+            "SELECT timestamp, transaction_id, daa_score, delta FROM address_deltas
+             WHERE address = ?1 AND timestamp BETWEEN ?2 AND ?3 AND (timestamp, transaction_id) > (?4, ?5)
+             ORDER BY timestamp, transaction_id LIMIT ?6",
+        )?;
+        let rows = stmt.query_map(
+            params![address, sqlite_int(from), sqlite_int(to), after_timestamp, after_id, PAGE_SIZE as i64],
+            |row| {
+                let timestamp_ms = row.get::<_, i64>(0)? as u64;
+                let delta: i64 = row.get(3)?;
+                Ok(TransactionRow {
+                    timestamp: utc(timestamp_ms),
+                    transaction_id: row.get(1)?,
+                    daa_score: row.get::<_, i64>(2)? as u64,
+                    amount_sompi: delta.to_string(),
+                    amount_xen: signed_xen(delta),
+                    timestamp_ms,
+                })
+            },
+        )?;
+        rows.collect()
+    })
+        .await
+}
+
+async fn block_page(db: Db, from_daa: u64, to_daa: u64, cursor: Option<(u64, String)>) -> anyhow::Result<Vec<BlockRow>> {
+    db.call(move |conn| {
+        let (after_daa, after_hash) = cursor.map_or((-1, String::new()), |(daa_score, hash)| (daa_score as i64, hash));
+        let mut stmt = conn.prepare(
+            "SELECT hash, daa_score, blue_score, timestamp, bits FROM blocks
+             WHERE daa_score BETWEEN ?1 AND ?2 AND (daa_score, hash) > (?3, ?4)
+             ORDER BY daa_score, hash LIMIT ?5",
+        )?;
+        let rows = stmt.query_map(
+            params![sqlite_int(from_daa), sqlite_int(to_daa), after_daa, after_hash, PAGE_SIZE as i64],
+            |row| {
+                Ok(BlockRow {
+                    hash: row.get(0)?,
+                    daa_score: row.get::<_, i64>(1)? as u64,
+                    blue_score: row.get::<_, i64>(2)? as u64,
+                    timestamp: utc(row.get::<_, i64>(3)? as u64),
+                    bits: row.get::<_, i64>(4)? as u32,
+                })
+            },
+        )?;
+        rows.collect()
+    })
+        .await
+}
+
+fn export_transactions(format: Format, addr: String, query: &TransactionExportQuery, db: Db) -> HttpResponse {
+    let address = match RpcAddress::try_from(addr.as_str()) {
+        Ok(address) => address.to_string(),
+        Err(err) => return HttpResponse::BadRequest().json(format!("Invalid address: {}", err)),
+    };
+    let from = match query.from.as_deref() {
+        Some(from) => match parse_time(from) {
+            Some(from) => from,
+            None => return HttpResponse::BadRequest().json("Invalid from time"),
+        },
+        None => 0,
+    };
+    let to = match query.to.as_deref() {
+        Some(to) => match parse_time(to) {
+            Some(to) => to.min(i64::MAX as u64),
+            None => return HttpResponse::BadRequest().json("Invalid to time"),
+        },
+        None => i64::MAX as u64,
+    };
+    if from > to {
+        return HttpResponse::BadRequest().json("from must not be after to");
+    }
+
+    let name = format!("{}-transactions", address.replace(':', "-"));
+    let body = rows(format, move |cursor| transaction_page(db.clone(), address.clone(), from, to, cursor));
+    attachment(format, &name, body)
+}
+
+#[utoipa::path(
+    get,
+    path = "/addresses/{addr}/transactions.csv",
+    tag = "export",
+    params(("addr" = String, Path, description = "Address with its network prefix"), TransactionExportQuery),
+    responses(
+        (status = 200, description = "Indexed transactions of the address with their net amount, one per line", content_type = "text/csv", body = String),
+        (status = 400, description = "Invalid address or range", body = ErrorBody),
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_transactions_csv(path: web::Path<String>, query: web::Query<TransactionExportQuery>, db: web::Data<Db>) -> impl Responder {
+    export_transactions(Format::Csv, path.into_inner(), &query, db.get_ref().clone())
+}
+
+#[utoipa::path(
+    get,
+    path = "/addresses/{addr}/transactions.jsonl",
+    tag = "export",
+    params(("addr" = String, Path, description = "Address with its network prefix"), TransactionExportQuery),
+    responses(
+        (status = 200, description = "Indexed transactions of the address with their net amount, one JSON object per line", content_type = "application/x-ndjson", body = String),
+        (status = 400, description = "Invalid address or range", body = ErrorBody),
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_transactions_jsonl(path: web::Path<String>, query: web::Query<TransactionExportQuery>, db: web::Data<Db>) -> impl Responder {
+    export_transactions(Format::Jsonl, path.into_inner(), &query, db.get_ref().clone())
+}
+
+#[utoipa::path(
+    get,
+    path = "/blocks/export",
+    tag = "export",
+    params(BlockExportQuery),
+    responses(
+        (status = 200, description = "Indexed blocks in the DAA score range, as CSV or JSON lines", content_type = "text/csv", body = String),
+        (status = 400, description = "Invalid range", body = ErrorBody),
+    ),
+    security(("api_key" = []))
+)]
+pub async fn export_blocks(query: web::Query<BlockExportQuery>, db: web::Data<Db>) -> impl Responder {
+    let format = query.format.unwrap_or_default();
+    let from_daa = query.from_daa;
+    let to_daa = query.to_daa.unwrap_or(u64::MAX);
+    if from_daa > to_daa {
+        return HttpResponse::BadRequest().json("fromDaa must not be greater than toDaa");
+    }
+
+    let db = db.get_ref().clone();
+    let name = match query.to_daa {
+        Some(to_daa) => format!("blocks-{}-{}", from_daa, to_daa),
+        None => format!("blocks-{}", from_daa),
+    };
+    let body = rows(format, move |cursor| block_page(db.clone(), from_daa, to_daa, cursor));
+    attachment(format, &name, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn insert_blocks(db: &Db, count: u64) {
+        db.call(move |conn| {
+            let tx = conn.transaction()?;
+            for daa_score in 0..count {
+                tx.execute(
+                    "INSERT INTO blocks (hash, daa_score, blue_score, timestamp, bits) VALUES (?1, ?2, ?2, ?3, 7)",
+                    params![format!("{:064x}", daa_score), daa_score as i64, 1_000 * daa_score as i64],
+                )?;
+            }
+            tx.commit()
+        })
+            .await
+            .unwrap();
+    }
+
+    async fn export(db: &Db, format: Format, from_daa: u64, to_daa: u64) -> Vec<String> {
+        let db = db.clone();
+        rows(format, move |cursor| block_page(db.clone(), from_daa, to_daa, cursor))
+            .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn pages_through_every_row_once() {
+        let db = Db::open(":memory:").unwrap();
+        insert_blocks(&db, PAGE_SIZE as u64 * 2 + 1).await;
+
+        let chunks = export(&db, Format::Csv, 0, u64::MAX).await;
+        // Header, two full pages and the last row
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[0], format!("{}\n", BlockRow::CSV_HEADER));
+        let lines: Vec<&str> = chunks[1..].iter().flat_map(|chunk| chunk.lines()).collect();
+        assert_eq!(lines.len(), PAGE_SIZE * 2 + 1);
+        let scores: Vec<u64> = lines.iter().map(|line| line.split(',').nth(1).unwrap().parse().unwrap()).collect();
+        assert_eq!(scores, (0..PAGE_SIZE as u64 * 2 + 1).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn ends_after_an_exactly_full_page() {
+        let db = Db::open(":memory:").unwrap();
+        insert_blocks(&db, PAGE_SIZE as u64).await;
+
+        let chunks = export(&db, Format::Jsonl, 0, u64::MAX).await;
+        assert_eq!(chunks.len(), 1);
+        let first: serde_json::Value = serde_json::from_str(chunks[0].lines().next().unwrap()).unwrap();
+        assert_eq!(first["daa_score"], 0);
+        assert_eq!(first["timestamp"], "1970-01-01T00:00:00.000Z");
+        assert_eq!(first["bits"], 7);
+    }
+
+    #[tokio::test]
+    async fn clamps_ranges_beyond_sqlite_integers() {
+        let db = Db::open(":memory:").unwrap();
+        insert_blocks(&db, 3).await;
+
+        let chunks = export(&db, Format::Csv, 1, u64::MAX).await;
+        assert_eq!(chunks[1].lines().count(), 2);
+        let chunks = export(&db, Format::Csv, u64::MAX, u64::MAX).await;
+        assert_eq!(chunks.len(), 1);
+    }
+
+    #[test]
+    fn quotes_fields_that_need_it() {
+        assert_eq!(csv_field("abc"), "abc");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn signs_negative_deltas() {
+        assert_eq!(signed_xen(150_000_000), "1.5");
+        assert_eq!(signed_xen(-150_000_000), "-1.5");
+        assert_eq!(signed_xen(-1), "-0.00000001");
+        assert_eq!(signed_xen(0), "0");
+        assert_eq!(signed_xen(i64::MIN), format!("-{}", format_xen(i64::MIN.unsigned_abs())));
+    }
+
+    #[test]
+    fn writes_transaction_rows_as_csv() {
+        let row = TransactionRow {
+            timestamp: utc(1_500),
+            transaction_id: "ab".repeat(32),
+            daa_score: 42,
+            amount_sompi: (-250_000_000i64).to_string(),
+            amount_xen: signed_xen(-250_000_000),
+            timestamp_ms: 1_500,
+        };
+        assert_eq!(row.csv(), format!("1970-01-01T00:00:01.500Z,{},42,-250000000,-2.5", "ab".repeat(32)));
+        assert_eq!(TransactionRow::CSV_HEADER.split(',').count(), row.csv().split(',').count());
+    }
+}
@@ -0,0 +1,264 @@
+use std::sync::Mutex;
+use actix_web::{web, HttpResponse, Responder};
+use chrono::Utc;
+use kaspa_rpc_core::api::rpc::RpcApi;
+use serde::Serialize;
+use tokio::time::{Duration, Instant};
+use utoipa::ToSchema;
+use crate::config;
+use crate::db::Db;
+use crate::index;
+use crate::metrics;
+use crate::utxoset;
+
+// Last virtual DAA score seen by a readiness check and when it last changed
+#[derive(Default)]
+pub struct HealthState {
+    last_daa_score: Mutex<Option<(u64, Instant)>>,
+}
+
+impl HealthState {
+    // Record the current score and return how long it has been since it last advanced, unknown
+    // until a previous check gives something to compare with
+    fn observe(&self, daa_score: u64) -> Option<u64> {
+        let mut last = self.last_daa_score.lock().unwrap();
+        match *last {
+            Some((score, changed)) if score >= daa_score => Some(changed.elapsed().as_secs()),
+            Some(_) => {
+                *last = Some((daa_score, Instant::now()));
+                Some(0)
+            }
+            None => {
+                *last = Some((daa_score, Instant::now()));
+                None
+            }
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct UpstreamStatus {
+    reachable: bool,
+    is_synced: bool,
+    virtual_daa_score: Option<u64>,
+    secs_since_daa_advanced: Option<u64>,
+    error: Option<String>,
+}
+
+impl UpstreamStatus {
+    fn is_ready(&self, max_daa_stall: Duration) -> bool {
+        self.reachable
+            && self.is_synced
+            && self.secs_since_daa_advanced.map_or(false, |secs| secs <= max_daa_stall.as_secs())
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct IndexerStatus {
+    tip_daa_score: Option<u64>,
+    tip_timestamp: Option<u64>,
+    // Distance between the node's virtual DAA score and the newest indexed block
+    daa_score_lag: Option<u64>,
+    time_lag_ms: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct CollectorStatus {
+    last_sample_timestamp: Option<u64>,
+    lag_ms: Option<u64>,
+}
+
+// Age of the periodically refreshed UTXO snapshot behind the rich list and distribution
+#[derive(Serialize, ToSchema)]
+struct SnapshotStatus {
+    timestamp: Option<u64>,
+    lag_ms: Option<u64>,
+    // Distance between the node's virtual DAA score and the one the snapshot was taken at
+    daa_score_lag: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct HealthReport {
+    status: &'static str,
+    upstream: UpstreamStatus,
+    indexer: IndexerStatus,
+    collector: CollectorStatus,
+    utxo_snapshot: SnapshotStatus,
+}
+
+#[derive(Serialize, ToSchema)]
+struct Status {
+    status: &'static str,
+}
+
+async fn check_upstream(state: &HealthState) -> UpstreamStatus {
+    let client = match crate::connect_node().await {
+        Ok(client) => client,
+        Err(err) => {
+            return UpstreamStatus {
+                reachable: false,
+                is_synced: false,
+                virtual_daa_score: None,
+                secs_since_daa_advanced: None,
+                error: Some(format!("{:?}", err)),
+            }
+        }
+    };
+    let server_info = metrics::rpc("get_server_info", client.get_server_info()).await;
+    let _ = client.disconnect().await;
+
+    match server_info {
+        Ok(info) => UpstreamStatus {
+            reachable: true,
+            is_synced: info.is_synced,
+            virtual_daa_score: Some(info.virtual_daa_score),
+            secs_since_daa_advanced: state.observe(info.virtual_daa_score),
+            error: None,
+        },
+        Err(err) => UpstreamStatus {
+            reachable: false,
+            is_synced: false,
+            virtual_daa_score: None,
+            secs_since_daa_advanced: None,
+            error: Some(format!("Failed to get server info: {:?}", err)),
+        },
+    }
+}
+
+async fn last_sample_timestamp(db: &Db) -> anyhow::Result<Option<u64>> {
+    db.call(|conn| {
+        conn.query_row("SELECT MAX(timestamp) FROM samples", [], |row| row.get::<_, Option<i64>>(0))
+            .map(|timestamp| timestamp.map(|timestamp| timestamp as u64))
+    })
+        .await
+}
+
+fn status_response(ready: bool, body: impl Serialize) -> HttpResponse {
+    if ready {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
+
+// The process is up and serving requests
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "health",
+    responses((status = 200, description = "Process is up", body = Status))
+)]
+pub async fn live() -> impl Responder {
+    HttpResponse::Ok().json(Status { status: "ok" })
+}
+
+// The upstream node is reachable, synced and its DAA score keeps advancing
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "health",
+    responses(
+        (status = 200, description = "Ready to serve traffic", body = Status),
+        (status = 503, description = "Node unreachable, not synced or stalled", body = Status),
+    )
+)]
+pub async fn ready(state: web::Data<HealthState>) -> impl Responder {
+    let upstream = check_upstream(&state).await;
+    let ready = upstream.is_ready(config::get().ready_max_daa_stall);
+    status_response(ready, Status { status: if ready { "ok" } else { "unavailable" } })
+}
+
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    responses(
+        (status = 200, description = "Detailed health report", body = HealthReport),
+        (status = 503, description = "Detailed health report of an unready service", body = HealthReport),
+    )
+)]
+pub async fn report(state: web::Data<HealthState>, db: web::Data<Db>) -> impl Responder {
+    let upstream = check_upstream(&state).await;
+    let now = Utc::now().timestamp_millis() as u64;
+
+    let tip = index::tip(&db).await.ok().flatten();
+    let indexer = IndexerStatus {
+        tip_daa_score: tip.as_ref().map(|tip| tip.daa_score),
+        tip_timestamp: tip.as_ref().map(|tip| tip.timestamp),
+        daa_score_lag: tip
+            .as_ref()
+            .zip(upstream.virtual_daa_score)
+            .map(|(tip, virtual_daa_score)| virtual_daa_score.saturating_sub(tip.daa_score)),
+        time_lag_ms: tip.as_ref().map(|tip| now.saturating_sub(tip.timestamp)),
+    };
+
+    let last_sample = last_sample_timestamp(&db).await.ok().flatten();
+    let collector = CollectorStatus {
+        last_sample_timestamp: last_sample,
+        lag_ms: last_sample.map(|timestamp| now.saturating_sub(timestamp)),
+    };
+
+    let snapshot = utxoset::snapshot_info(&db).await.ok().flatten();
+    let utxo_snapshot = SnapshotStatus {
+        timestamp: snapshot.as_ref().map(|snapshot| snapshot.timestamp),
+        lag_ms: snapshot.as_ref().map(|snapshot| now.saturating_sub(snapshot.timestamp)),
+        daa_score_lag: snapshot
+            .as_ref()
+            .zip(upstream.virtual_daa_score)
+            .map(|(snapshot, virtual_daa_score)| virtual_daa_score.saturating_sub(snapshot.virtual_daa_score)),
+    };
+
+    let ready = upstream.is_ready(config::get().ready_max_daa_stall);
+    status_response(ready, HealthReport {
+        status: if ready { "ok" } else { "unavailable" },
+        upstream,
+        indexer,
+        collector,
+        utxo_snapshot,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_STALL: Duration = Duration::from_secs(60);
+
+    fn upstream(reachable: bool, is_synced: bool, secs_since_daa_advanced: Option<u64>) -> UpstreamStatus {
+        UpstreamStatus { reachable, is_synced, virtual_daa_score: Some(100), secs_since_daa_advanced, error: None }
+    }
+
+    #[test]
+    fn first_observation_is_unknown() {
+        let state = HealthState::default();
+        assert_eq!(state.observe(100), None);
+        assert_eq!(state.observe(100), Some(0));
+        assert_eq!(state.observe(101), Some(0));
+    }
+
+    #[test]
+    fn reports_how_long_the_score_has_stalled() {
+        let state = HealthState::default();
+        *state.last_daa_score.lock().unwrap() = Some((100, Instant::now() - Duration::from_secs(90)));
+        assert_eq!(state.observe(100), Some(90));
+        // A lower score, e.g. from another node behind a balancer, isn't progress either
+        assert_eq!(state.observe(99), Some(90));
+        assert_eq!(state.observe(101), Some(0));
+    }
+
+    #[test]
+    fn ready_only_when_reachable_synced_and_advancing() {
+        assert!(upstream(true, true, Some(0)).is_ready(MAX_STALL));
+        assert!(upstream(true, true, Some(60)).is_ready(MAX_STALL));
+        assert!(!upstream(true, true, Some(61)).is_ready(MAX_STALL));
+        assert!(!upstream(true, true, None).is_ready(MAX_STALL));
+        assert!(!upstream(true, false, Some(0)).is_ready(MAX_STALL));
+        assert!(!upstream(false, true, Some(0)).is_ready(MAX_STALL));
+    }
+
+    #[test]
+    fn unready_answers_service_unavailable() {
+        assert_eq!(status_response(true, Status { status: "ok" }).status(), 200);
+        assert_eq!(status_response(false, Status { status: "unavailable" }).status(), 503);
+    }
+}
@@ -0,0 +1,205 @@
+use actix_web::{web, HttpResponse, Responder};
+use chrono::Utc;
+use kaspa_rpc_core::api::rpc::RpcApi;
+use kaspa_rpc_core::RpcAddress;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::amount::{Amount, Unit};
+use crate::blocks::parse_time;
+use crate::db::Db;
+use crate::logging::ErrorBody;
+use crate::metrics;
+
+const DEFAULT_RANGE_MS: u64 = 30 * 24 * 60 * 60 * 1000;
+const DEFAULT_INTERVAL_SECS: u64 = 24 * 60 * 60;
+// Upper bound of points returned for one history
+const MAX_POINTS: u64 = 2000;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistoryQuery {
+    from: Option<String>,
+    to: Option<String>,
+    // Point spacing in seconds
+    interval: Option<u64>,
+    unit: Option<Unit>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct BalancePoint {
+    // End of the interval, unix milliseconds
+    timestamp: u64,
+    balance: Amount,
+    // Transactions touching the address within the interval
+    transactions: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct HistoryResponse {
+    address: String,
+    from: u64,
+    to: u64,
+    interval: u64,
+    // Oldest indexed block, balance changes before it are not known
+    indexed_since: Option<u64>,
+    points: Vec<BalancePoint>,
+}
+
+// Point spacing in seconds and in milliseconds. Widened when the requested resolution would return
+// too many points and capped at the whole range; None when the spacing overflows
+fn point_width(from: u64, to: u64, interval: u64) -> Option<(u64, u64)> {
+    let range_secs = ((to - from) / 1000).max(1);
+    let min_interval = (range_secs / MAX_POINTS).max(1);
+    let interval = interval.max(min_interval).min(range_secs);
+    Some((interval, interval.checked_mul(1000)?))
+}
+
+// Walks back from the current balance to the start of the range, then forward through the intervals
+fn balance_points(from: u64, to: u64, interval_ms: u64, balance: u64, deltas: Vec<(u64, i64)>, unit: Unit) -> Vec<BalancePoint> {
+    let mut balance = balance as i128 - deltas.iter().map(|(_, delta)| *delta as i128).sum::<i128>();
+    let mut deltas = deltas.into_iter().peekable();
+    let mut points = Vec::new();
+    let mut end = from;
+    loop {
+        end = end.saturating_add(interval_ms).min(to);
+        let mut transactions = 0;
+        while let Some((_, delta)) = deltas.next_if(|(timestamp, _)| *timestamp <= end) {
+            balance += delta as i128;
+            transactions += 1;
+        }
+        // Spends of outputs the index never saw can push the reconstructed balance below zero
+        let sompi = u64::try_from(balance.max(0)).unwrap_or(u64::MAX);
+        points.push(BalancePoint { timestamp: end, balance: Amount::new(sompi, unit), transactions });
+        if end >= to {
+            break;
+        }
+    }
+    points
+}
+
+#[utoipa::path(
+    get,
+    path = "/addresses/{addr}/balance/history",
+    tag = "addresses",
+    params(("addr" = String, Path, description = "Address with its network prefix"), HistoryQuery),
+    responses(
+        (status = 200, description = "Balance at the end of every interval", body = HistoryResponse),
+        (status = 400, description = "Invalid address or range", body = ErrorBody),
+        (status = 500, description = "Index or node request failed", body = ErrorBody),
+    )
+)]
+pub async fn get_balance_history(path: web::Path<String>, query: web::Query<HistoryQuery>, db: web::Data<Db>) -> impl Responder {
+    let address = match RpcAddress::try_from(path.into_inner().as_str()) {
+        Ok(address) => address,
+        Err(err) => return HttpResponse::BadRequest().json(format!("Invalid address: {}", err)),
+    };
+    let unit = query.unit.unwrap_or_default();
+
+    let now = Utc::now().timestamp_millis() as u64;
+    let to = match query.to.as_deref() {
+        Some(to) => match parse_time(to) {
+            Some(to) => to.min(now),
+            None => return HttpResponse::BadRequest().json("Invalid to time"),
+        },
+        None => now,
+    };
+    let from = match query.from.as_deref() {
+        Some(from) => match parse_time(from) {
+            Some(from) => from,
+            None => return HttpResponse::BadRequest().json("Invalid from time"),
+        },
+        None => to.saturating_sub(DEFAULT_RANGE_MS),
+    };
+    if from > to {
+        return HttpResponse::BadRequest().json("from must not be after to");
+    }
+
+    let (interval, interval_ms) = match point_width(from, to, query.interval.unwrap_or(DEFAULT_INTERVAL_SECS)) {
+        Some(width) => width,
+        None => return HttpResponse::BadRequest().json("interval is too large"),
+    };
+
+    let client = match crate::get_client().await {
+        Ok(client) => client,
+        Err(err) => return err,
+    };
+    let balance = match metrics::rpc("get_balance_by_address", client.get_balance_by_address(address.clone())).await {
+        Ok(balance) => balance,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to get balance: {:?}", err)),
+    };
+    if let Err(disconnect_err) = client.disconnect().await {
+        return HttpResponse::InternalServerError().json(format!("Failed to disconnect: {}", disconnect_err));
+    }
+
+    let key = address.to_string();
+    let history = db
+        .call(move |conn| {
+            let indexed_since = conn
+                .query_row("SELECT MIN(timestamp) FROM blocks", [], |row| row.get::<_, Option<i64>>(0))?
+                .map(|timestamp| timestamp as u64);
+            let mut stmt = conn.prepare(
+                "SELECT timestamp, delta FROM address_deltas WHERE address = ?1 AND timestamp > ?2 ORDER BY timestamp",
+            )?;
+            let rows = stmt.query_map(params![key, from as i64], |row| {
+                Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)?))
+            })?;
+            let deltas = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok((indexed_since, deltas))
+        })
+        .await;
+    let (indexed_since, deltas) = match history {
+        Ok(history) => history,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to read address history: {:?}", err)),
+    };
+
+    let points = balance_points(from, to, interval_ms, balance, deltas, unit);
+    HttpResponse::Ok().json(HistoryResponse { address: address.to_string(), from, to, interval, indexed_since, points })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn balances(points: &[BalancePoint]) -> Vec<(u64, String, u64)> {
+        points
+            .iter()
+            .map(|point| (point.timestamp, point.balance.sompi.clone().unwrap(), point.transactions))
+            .collect()
+    }
+
+    #[test]
+    fn width_is_capped_at_the_range() {
+        assert_eq!(point_width(0, 60_000, 3600), Some((60, 60_000)));
+        assert_eq!(point_width(0, 500, 10), Some((1, 1000)));
+        assert_eq!(point_width(u64::MAX - 1, u64::MAX, u64::MAX), Some((1, 1000)));
+        assert_eq!(point_width(0, u64::MAX, u64::MAX), Some((u64::MAX / 1000, u64::MAX / 1000 * 1000)));
+    }
+
+    #[test]
+    fn width_is_widened_to_bound_the_points() {
+        let range_ms = MAX_POINTS * 10 * 1000;
+        assert_eq!(point_width(0, range_ms, 1), Some((10, 10_000)));
+        assert_eq!(point_width(0, range_ms, 20), Some((20, 20_000)));
+    }
+
+    #[test]
+    fn points_walk_back_from_the_current_balance() {
+        let deltas = vec![(1_500, 100), (2_000, -30), (2_500, 50)];
+        let points = balance_points(1_000, 3_000, 1_000, 500, deltas, Unit::Sompi);
+        assert_eq!(balances(&points), vec![(2_000, "450".to_string(), 2), (3_000, "500".to_string(), 1)]);
+    }
+
+    #[test]
+    fn last_point_ends_at_the_range_end() {
+        let points = balance_points(0, 2_500, 1_000, 10, Vec::new(), Unit::Sompi);
+        let timestamps: Vec<u64> = points.iter().map(|point| point.timestamp).collect();
+        assert_eq!(timestamps, vec![1_000, 2_000, 2_500]);
+    }
+
+    #[test]
+    fn negative_reconstructed_balances_are_clamped() {
+        let points = balance_points(0, 2_000, 1_000, 0, vec![(1_500, 40)], Unit::Sompi);
+        assert_eq!(balances(&points), vec![(1_000, "0".to_string(), 0), (2_000, "0".to_string(), 1)]);
+    }
+}
@@ -0,0 +1,429 @@
+use std::collections::{HashMap, HashSet};
+use kaspa_rpc_core::api::rpc::RpcApi;
+use kaspa_rpc_core::{RpcBlock, RpcHash, RpcTransactionId};
+use kaspa_wrpc_client::KaspaRpcClient;
+use rusqlite::{params, OptionalExtension, Row};
+use serde::Serialize;
+use utoipa::ToSchema;
+use tokio::time::{sleep, Duration};
+use tracing::error;
+use crate::db::Db;
+use crate::metrics;
+
+// Upper bound of get_blocks round trips per sync pass, so one pass can't run forever
+const MAX_PAGES_PER_SYNC: usize = 100;
+const LAST_LOW_HASH_KEY: &str = "last_low_hash";
+
+// Header summary stored in the local index
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexedBlock {
+    pub hash: String,
+    pub daa_score: u64,
+    pub blue_score: u64,
+    pub timestamp: u64,
+    pub bits: u32,
+}
+
+impl From<&RpcBlock> for IndexedBlock {
+    fn from(block: &RpcBlock) -> Self {
+        IndexedBlock {
+            hash: block.header.hash.to_string(),
+            daa_score: block.header.daa_score,
+            blue_score: block.header.blue_score,
+            timestamp: block.header.timestamp,
+            bits: block.header.bits,
+        }
+    }
+}
+
+impl IndexedBlock {
+    fn from_row(row: &Row) -> rusqlite::Result<IndexedBlock> {
+        Ok(IndexedBlock {
+            hash: row.get(0)?,
+            daa_score: row.get::<_, i64>(1)? as u64,
+            blue_score: row.get::<_, i64>(2)? as u64,
+            timestamp: row.get::<_, i64>(3)? as u64,
+            bits: row.get::<_, i64>(4)? as u32,
+        })
+    }
+}
+
+// Range of blocks to look up, by DAA score, blue score or header timestamp (ms)
+#[derive(Debug, Clone, Copy)]
+pub enum BlockRange {
+    Daa { low: u64, high: u64 },
+    Blue { low: u64, high: u64 },
+    Time { from: u64, to: u64 },
+}
+
+impl BlockRange {
+    pub fn contains(&self, block: &IndexedBlock) -> bool {
+        match *self {
+            BlockRange::Daa { low, high } => block.daa_score >= low && block.daa_score <= high,
+            BlockRange::Blue { low, high } => block.blue_score >= low && block.blue_score <= high,
+            BlockRange::Time { from, to } => block.timestamp >= from && block.timestamp <= to,
+        }
+    }
+
+    // Whether the range reaches past the newest indexed block
+    pub fn extends_past(&self, tip: &IndexedBlock) -> bool {
+        match *self {
+            BlockRange::Daa { high, .. } => high > tip.daa_score,
+            BlockRange::Blue { high, .. } => high > tip.blue_score,
+            BlockRange::Time { to, .. } => to > tip.timestamp,
+        }
+    }
+
+    fn column(&self) -> &'static str {
+        match self {
+            BlockRange::Daa { .. } => "daa_score",
+            BlockRange::Blue { .. } => "blue_score",
+            BlockRange::Time { .. } => "timestamp",
+        }
+    }
+
+    // SQLite integers are signed, open-ended ranges are clamped to i64::MAX
+    fn bounds(&self) -> (i64, i64) {
+        let (low, high) = match *self {
+            BlockRange::Daa { low, high } | BlockRange::Blue { low, high } => (low, high),
+            BlockRange::Time { from, to } => (from, to),
+        };
+        (i64::try_from(low).unwrap_or(i64::MAX), i64::try_from(high).unwrap_or(i64::MAX))
+    }
+}
+
+pub async fn blocks_in_range(db: &Db, range: BlockRange, limit: usize, offset: usize) -> anyhow::Result<Vec<IndexedBlock>> {
+    db.call(move |conn| {
+        let sql = format!(
+            "SELECT hash, daa_score, blue_score, timestamp, bits FROM blocks
+             WHERE {0} BETWEEN ?1 AND ?2 ORDER BY {0}, hash LIMIT ?3 OFFSET ?4",
+            range.column()
+        );
+        let (low, high) = range.bounds();
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params![low, high, limit as i64, offset as i64], IndexedBlock::from_row)?;
+        rows.collect()
+    })
+        .await
+}
+
+pub async fn count_in_range(db: &Db, range: BlockRange) -> anyhow::Result<usize> {
+    db.call(move |conn| {
+        let sql = format!("SELECT COUNT(*) FROM blocks WHERE {} BETWEEN ?1 AND ?2", range.column());
+        let (low, high) = range.bounds();
+        conn.query_row(&sql, params![low, high], |row| row.get::<_, i64>(0).map(|count| count as usize))
+    })
+        .await
+}
+
+// Hashes of the indexed blocks at or above the DAA score
+pub async fn hashes_from(db: &Db, daa_score: u64) -> anyhow::Result<HashSet<String>> {
+    db.call(move |conn| {
+        let mut stmt = conn.prepare("SELECT hash FROM blocks WHERE daa_score >= ?1")?;
+        let rows = stmt.query_map(params![i64::try_from(daa_score).unwrap_or(i64::MAX)], |row| row.get(0))?;
+        rows.collect()
+    })
+        .await
+}
+
+// Newest indexed block by DAA score
+pub async fn tip(db: &Db) -> anyhow::Result<Option<IndexedBlock>> {
+    db.call(|conn| {
+        conn.query_row(
+            "SELECT hash, daa_score, blue_score, timestamp, bits FROM blocks ORDER BY daa_score DESC LIMIT 1",
+            [],
+            IndexedBlock::from_row,
+        )
+            .optional()
+    })
+        .await
+}
+
+// Block the index found an accepted transaction in
+pub async fn transaction_block(db: &Db, transaction_id: String) -> anyhow::Result<Option<String>> {
+    db.call(move |conn| {
+        conn.query_row(
+            "SELECT block_hash FROM transactions WHERE transaction_id = ?1",
+            params![transaction_id],
+            |row| row.get(0),
+        )
+            .optional()
+    })
+        .await
+}
+
+// What the address index keeps of one transaction
+struct IndexedTransaction {
+    id: String,
+    // First indexed block including the transaction
+    block_hash: String,
+    daa_score: u64,
+    timestamp: u64,
+    // Output index, address and amount
+    outputs: Vec<(u32, String, u64)>,
+    // Spent outpoints as transaction id and output index
+    inputs: Vec<(String, u32)>,
+}
+
+// Transactions of the blocks accepted by the virtual chain, once each even when several blocks include them
+fn indexed_transactions(blocks: &[RpcBlock], accepted: &HashSet<RpcTransactionId>) -> Vec<IndexedTransaction> {
+    let mut seen = HashSet::new();
+    blocks
+        .iter()
+        .flat_map(|block| block.transactions.iter().map(move |tx| (block, tx)))
+        .filter_map(|(block, tx)| {
+            let id = tx.verbose_data.as_ref()?.transaction_id;
+            if !accepted.contains(&id) || !seen.insert(id) {
+                return None;
+            }
+            Some(IndexedTransaction {
+                id: id.to_string(),
+                block_hash: block.header.hash.to_string(),
+                daa_score: block.header.daa_score,
+                timestamp: block.header.timestamp,
+                outputs: tx
+                    .outputs
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, output)| {
+                        let data = output.verbose_data.as_ref()?;
+                        Some((index as u32, data.script_public_key_address.to_string(), output.value))
+                    })
+                    .collect(),
+                inputs: tx
+                    .inputs
+                    .iter()
+                    .map(|input| (input.previous_outpoint.transaction_id.to_string(), input.previous_outpoint.index))
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+async fn store(db: &Db, blocks: Vec<IndexedBlock>, transactions: Vec<IndexedTransaction>, low_hash: String) -> anyhow::Result<()> {
+    db.call(move |conn| {
+        let tx = conn.transaction()?;
+        {
+            let mut insert = tx.prepare(
+                "INSERT OR IGNORE INTO blocks (hash, daa_score, blue_score, timestamp, bits) VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for block in &blocks {
+                insert.execute(params![
+                    block.hash,
+                    block.daa_score as i64,
+                    block.blue_score as i64,
+                    block.timestamp as i64,
+                    block.bits as i64
+                ])?;
+            }
+
+            // Transactions already indexed from an earlier page keep their first block
+            let mut transaction = tx.prepare(
+                "INSERT OR IGNORE INTO transactions (transaction_id, block_hash, daa_score, timestamp) VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for indexed in &transactions {
+                transaction.execute(params![
+                    indexed.id,
+                    indexed.block_hash,
+                    indexed.daa_score as i64,
+                    indexed.timestamp as i64
+                ])?;
+            }
+
+            // Outputs go in first so inputs spending an output of the same page can be resolved
+            let mut seen = tx.prepare(
+                "INSERT INTO addresses (address, first_seen_daa, last_seen_daa) VALUES (?1, ?2, ?2)
+                 ON CONFLICT (address) DO UPDATE SET
+                     first_seen_daa = MIN(first_seen_daa, excluded.first_seen_daa),
+                     last_seen_daa = MAX(last_seen_daa, excluded.last_seen_daa)",
+            )?;
+            let mut output = tx.prepare(
+                "INSERT OR IGNORE INTO tx_outputs (transaction_id, output_index, address, amount) VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for transaction in &transactions {
+                for (index, address, amount) in &transaction.outputs {
+                    seen.execute(params![address, transaction.daa_score as i64])?;
+                    output.execute(params![transaction.id, index, address, *amount as i64])?;
+                }
+            }
+
+            // Spent outputs are looked up in the indexed outputs, older ones in the UTXO snapshot
+            let mut spent = tx.prepare(
+                "SELECT address, amount FROM tx_outputs WHERE transaction_id = ?1 AND output_index = ?2
+                 UNION ALL
+                 SELECT address, amount FROM utxos WHERE transaction_id = ?1 AND output_index = ?2
+                 LIMIT 1",
+            )?;
+            // A transaction included by several blocks is only counted once
+            let mut delta = tx.prepare(
+                "INSERT OR IGNORE INTO address_deltas (address, transaction_id, daa_score, timestamp, delta)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for transaction in &transactions {
+                let mut deltas: HashMap<&str, i64> = HashMap::new();
+                for (_, address, amount) in &transaction.outputs {
+                    *deltas.entry(address.as_str()).or_default() += *amount as i64;
+                }
+                let mut debits: Vec<(String, i64)> = Vec::new();
+                for (transaction_id, index) in &transaction.inputs {
+                    if let Some(debit) = spent
+                        .query_row(params![transaction_id, index], |row| Ok((row.get(0)?, row.get(1)?)))
+                        .optional()?
+                    {
+                        debits.push(debit);
+                    }
+                }
+                for (address, amount) in &debits {
+                    *deltas.entry(address.as_str()).or_default() -= amount;
+                }
+                for (address, change) in deltas {
+                    delta.execute(params![
+                        address,
+                        transaction.id,
+                        transaction.daa_score as i64,
+                        transaction.timestamp as i64,
+                        change
+                    ])?;
+                }
+            }
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO index_state (key, value) VALUES (?1, ?2)",
+            params![LAST_LOW_HASH_KEY, low_hash],
+        )?;
+        tx.commit()
+    })
+        .await
+}
+
+async fn last_low_hash(db: &Db) -> anyhow::Result<Option<String>> {
+    db.call(|conn| {
+        conn.query_row("SELECT value FROM index_state WHERE key = ?1", params![LAST_LOW_HASH_KEY], |row| row.get(0))
+            .optional()
+    })
+        .await
+}
+
+// Newest chain block of a get_blocks page, the low hash to continue walking from
+pub fn next_low_hash(blocks: &[RpcBlock], low_hash: RpcHash) -> RpcHash {
+    blocks
+        .iter()
+        .filter(|block| block.verbose_data.as_ref().map_or(false, |data| data.is_chain_block))
+        .max_by_key(|block| block.header.blue_score)
+        .map(|block| block.header.hash)
+        .unwrap_or(low_hash)
+}
+
+// Chain blocks and accepted transactions of the virtual chain past the low hash
+struct VirtualChain {
+    chain_blocks: HashSet<RpcHash>,
+    accepted: HashSet<RpcTransactionId>,
+}
+
+async fn virtual_chain(client: &KaspaRpcClient, low_hash: RpcHash) -> anyhow::Result<VirtualChain> {
+    let response =
+        metrics::rpc("get_virtual_chain_from_block", client.get_virtual_chain_from_block(low_hash, true)).await?;
+    Ok(VirtualChain {
+        chain_blocks: response.added_chain_block_hashes.into_iter().collect(),
+        accepted: response
+            .accepted_transaction_ids
+            .into_iter()
+            .flat_map(|accepted| accepted.accepted_transaction_ids)
+            .collect(),
+    })
+}
+
+// Background task keeping the block index up to date with the node
+pub async fn run(db: Db, interval: Duration) {
+    loop {
+        if let Err(err) = sync(&db).await {
+            error!(error = ?err, "Block indexer failed");
+        }
+        sleep(interval).await;
+    }
+}
+
+async fn sync(db: &Db) -> anyhow::Result<()> {
+    let client = crate::connect_node().await?;
+
+    let mut low_hash: RpcHash = match last_low_hash(db).await? {
+        Some(hash) => hash.parse()?,
+        // A fresh index starts from the pruning point, the oldest block the node still knows
+        None => metrics::rpc("get_block_dag_info", client.get_block_dag_info()).await?.pruning_point_hash,
+    };
+
+    // Transactions are only indexed once a chain block accepted them. Blocks merged after this snapshot
+    // are walked again by a later pass, as the low hash never moves past the snapshot's chain
+    let chain = virtual_chain(&client, low_hash).await?;
+
+    for _ in 0..MAX_PAGES_PER_SYNC {
+        let response = metrics::rpc("get_blocks", client.get_blocks(Some(low_hash), true, true)).await?;
+
+        let next_hash = next_low_hash(&response.blocks, low_hash);
+        let next_hash = if chain.chain_blocks.contains(&next_hash) { next_hash } else { low_hash };
+
+        let blocks = response.blocks.iter().map(IndexedBlock::from).collect();
+        let transactions = indexed_transactions(&response.blocks, &chain.accepted);
+        store(db, blocks, transactions, next_hash.to_string()).await?;
+
+        if next_hash == low_hash {
+            break;
+        }
+        low_hash = next_hash;
+    }
+
+    let virtual_daa_score = metrics::rpc("get_block_dag_info", client.get_block_dag_info()).await?.virtual_daa_score;
+    client.disconnect().await?;
+
+    if let Some(tip) = tip(db).await? {
+        let metrics = metrics::get();
+        metrics.indexer_tip_daa_score.set(tip.daa_score as i64);
+        metrics.indexer_daa_score_lag.set(virtual_daa_score.saturating_sub(tip.daa_score) as i64);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(daa_score: u64, blue_score: u64, timestamp: u64) -> IndexedBlock {
+        IndexedBlock { hash: String::new(), daa_score, blue_score, timestamp, bits: 0 }
+    }
+
+    #[test]
+    fn bounds_pass_through_in_range_values() {
+        assert_eq!(BlockRange::Daa { low: 10, high: 20 }.bounds(), (10, 20));
+        assert_eq!(BlockRange::Blue { low: 3, high: 4 }.bounds(), (3, 4));
+        assert_eq!(BlockRange::Time { from: 1_000, to: 2_000 }.bounds(), (1_000, 2_000));
+    }
+
+    #[test]
+    fn bounds_clamp_open_ranges_to_sqlite_integers() {
+        assert_eq!(BlockRange::Daa { low: 5, high: u64::MAX }.bounds(), (5, i64::MAX));
+        assert_eq!(BlockRange::Blue { low: u64::MAX, high: u64::MAX }.bounds(), (i64::MAX, i64::MAX));
+        assert_eq!(BlockRange::Time { from: 0, to: i64::MAX as u64 + 1 }.bounds(), (0, i64::MAX));
+    }
+
+    #[test]
+    fn ranges_match_their_own_column() {
+        let block = block(100, 90, 5_000);
+        assert!(BlockRange::Daa { low: 100, high: 100 }.contains(&block));
+        assert!(!BlockRange::Daa { low: 90, high: 99 }.contains(&block));
+        assert!(BlockRange::Blue { low: 90, high: 90 }.contains(&block));
+        assert!(!BlockRange::Blue { low: 100, high: 200 }.contains(&block));
+        assert!(BlockRange::Time { from: 5_000, to: 6_000 }.contains(&block));
+        assert_eq!(BlockRange::Blue { low: 0, high: 0 }.column(), "blue_score");
+    }
+
+    #[test]
+    fn ranges_extend_past_the_tip_by_their_own_column() {
+        let tip = block(100, 90, 5_000);
+        assert!(BlockRange::Daa { low: 0, high: 101 }.extends_past(&tip));
+        assert!(!BlockRange::Daa { low: 0, high: 100 }.extends_past(&tip));
+        assert!(BlockRange::Blue { low: 0, high: 91 }.extends_past(&tip));
+        assert!(!BlockRange::Blue { low: 0, high: 90 }.extends_past(&tip));
+        assert!(!BlockRange::Time { from: 0, to: 4_999 }.extends_past(&tip));
+    }
+}
@@ -0,0 +1,128 @@
+use actix_web::body::{to_bytes, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue, CONTENT_TYPE};
+use serde::Serialize;
+use serde_json::Value;
+use tracing_subscriber::EnvFilter;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use crate::config;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// Body of every 4xx and 5xx response
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    #[schema(value_type = String)]
+    error: Value,
+    request_id: String,
+}
+
+// Install the global subscriber, RUST_LOG takes precedence over the configured level
+pub fn init() {
+    let config = config::get();
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config.log_level));
+    if config.log_json {
+        tracing_subscriber::fmt().json().with_current_span(true).with_span_list(false).with_env_filter(filter).init();
+    } else {
+        tracing_subscriber::fmt().with_env_filter(filter).init();
+    }
+}
+
+// Reuse the caller's request id when it looks sane, otherwise generate one
+pub fn request_id(req: &ServiceRequest) -> String {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| {
+            !value.is_empty()
+                && value.len() <= 64
+                && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+// Echo the request id in a header and, for errors, wrap the body as {"error": ..., "request_id": ...}
+pub async fn with_request_id<B>(response: ServiceResponse<B>, request_id: &str) -> Result<ServiceResponse<BoxBody>, actix_web::Error>
+where
+    B: MessageBody + 'static,
+{
+    let mut response = response.map_into_boxed_body();
+    if let Ok(value) = HeaderValue::from_str(request_id) {
+        response.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    if !response.status().is_client_error() && !response.status().is_server_error() {
+        return Ok(response);
+    }
+
+    let (req, res) = response.into_parts();
+    let (mut res, body) = res.into_parts();
+    let bytes = to_bytes(body).await.map_err(|err| actix_web::error::ErrorInternalServerError(err.to_string()))?;
+    let error = serde_json::from_slice(&bytes).unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()));
+    res.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    let body = serde_json::to_string(&ErrorBody { error, request_id: request_id.to_string() })
+        .map_err(|err| actix_web::error::ErrorInternalServerError(err.to_string()))?;
+    Ok(ServiceResponse::new(req, res.set_body(body).map_into_boxed_body()))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::dev::Service;
+    use actix_web::{test, web, App, HttpResponse};
+    use super::*;
+
+    async fn call(incoming: Option<&str>, path: &str) -> (Option<String>, actix_web::http::StatusCode, Value) {
+        let app = test::init_service(
+            App::new()
+                .wrap_fn(|req, srv| {
+                    let request_id = request_id(&req);
+                    let response = srv.call(req);
+                    async move { with_request_id(response.await?, &request_id).await }
+                })
+                .route("/ok", web::get().to(|| async { HttpResponse::Ok().json(vec![1, 2]) }))
+                .route("/missing", web::get().to(|| async { HttpResponse::NotFound().json("Block not found") }))
+                .route("/plain", web::get().to(|| async { HttpResponse::InternalServerError().body("node down") })),
+        )
+            .await;
+        let mut req = test::TestRequest::get().uri(path);
+        if let Some(incoming) = incoming {
+            req = req.insert_header((REQUEST_ID_HEADER, incoming));
+        }
+        let response = test::call_service(&app, req.to_request()).await;
+        let header = response.headers().get(REQUEST_ID_HEADER).map(|value| value.to_str().unwrap().to_string());
+        let status = response.status();
+        let body: Value = test::read_body_json(response).await;
+        (header, status, body)
+    }
+
+    #[actix_web::test]
+    async fn propagates_the_incoming_request_id() {
+        let (header, status, body) = call(Some("abc-123_x"), "/missing").await;
+        assert_eq!(header.as_deref(), Some("abc-123_x"));
+        assert_eq!(status, 404);
+        assert_eq!(body, serde_json::json!({"error": "Block not found", "request_id": "abc-123_x"}));
+    }
+
+    #[actix_web::test]
+    async fn generates_a_request_id_when_missing_or_malformed() {
+        for incoming in [None, Some("has spaces"), Some(""), Some(&*"a".repeat(65))] {
+            let (header, _, body) = call(incoming, "/missing").await;
+            let header = header.unwrap();
+            assert!(Uuid::parse_str(&header).is_ok(), "{:?} gave {}", incoming, header);
+            assert_eq!(body["request_id"], header.as_str());
+        }
+    }
+
+    #[actix_web::test]
+    async fn wraps_only_error_bodies() {
+        let (header, status, body) = call(Some("ok-1"), "/ok").await;
+        assert_eq!((header.as_deref(), status.as_u16()), (Some("ok-1"), 200));
+        assert_eq!(body, serde_json::json!([1, 2]));
+
+        // Bodies that aren't JSON are kept as a string
+        let (_, status, body) = call(Some("plain-1"), "/plain").await;
+        assert_eq!(status, 500);
+        assert_eq!(body, serde_json::json!({"error": "node down", "request_id": "plain-1"}));
+    }
+}
@@ -3,28 +3,211 @@ use actix_cors::Cors;
 use std::collections::HashMap;
 use std::hash::Hash;
 use actix_web::{web, App, HttpServer, Responder, HttpResponse};
+use actix_web::dev::{Service, ServiceResponse};
 use serde::{Deserialize, Serialize};
 use kaspa_rpc_core::api::rpc::RpcApi;
-use kaspa_rpc_core::RpcAddress;
+use kaspa_rpc_core::{RpcAddress, RpcHash};
 use kaspa_wrpc_client::{KaspaRpcClient, WrpcEncoding};
 use kaspa_wrpc_client::prelude::ConnectOptions;
-use tokio::time::Duration;
+use tokio::time::{Duration, Instant};
 use anyhow::Context;
 use kaspa_consensus_core::constants::SOMPI_PER_KASPA;
-use serde_json::json;
 use chrono::{Utc, TimeZone};
 use futures_util::future::err;
+use tracing::{debug, info, info_span, warn, Instrument};
+use utoipa::ToSchema;
+use utoipa_redoc::{Redoc, Servable};
+use crate::amount::{parse_xen, Amount, Unit, UnitQuery};
+use crate::auth::ApiKeys;
+use crate::dto::SupplyDto;
+use crate::db::Db;
+use crate::health::HealthState;
+use crate::logging::ErrorBody;
+use crate::payments::Payments;
+use crate::ratelimit::RateLimiter;
+use crate::webhooks::Webhooks;
+
+mod address;
+mod amount;
+mod auth;
+mod batch;
+mod blockrate;
+mod blocks;
+mod chain;
+mod config;
+mod dag;
+mod db;
+mod difficulty;
+mod dto;
+mod export;
+mod health;
+mod history;
+mod index;
+mod logging;
+mod metrics;
+mod node;
+mod notify;
+mod openapi;
+mod payments;
+mod ratelimit;
+mod richlist;
+mod search;
+mod stats;
+mod utxoset;
+mod utxostats;
+mod v1;
+mod webhooks;
 
 // Add this import
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, ToSchema)]
 struct BalanceResponse {
-    balance: String,
+    balance: Amount,
+}
+
+#[derive(Serialize)]
+pub struct CoinSupply {
+    pub circulating_sompi: u64,
+    pub max_sompi: u64,
+}
+
+// Registers the API routes and lists them for the OpenAPI coverage test.
+// Routes match in order, so static paths such as /addresses/top come before /addresses/{addr}
+macro_rules! api_routes {
+    ($($method:ident $path:literal => $handler:path,)*) => {
+        #[cfg(test)]
+        const ROUTES: &[(&str, &str)] = &[$((stringify!($method), $path),)*];
+
+        fn configure_routes(cfg: &mut web::ServiceConfig) {
+            $(cfg.route($path, web::$method().to($handler));)*
+        }
+    };
+}
+
+api_routes! {
+    get "/blocks" => blocks::list_blocks,
+    get "/blocks/export" => export::export_blocks,
+    get "/blocks/{hash}" => get_block,
+    get "/blocks/{hash}/is-chain" => chain::get_is_chain,
+    get "/blocks/{hash}/accepting-block" => chain::get_accepting_block,
+    get "/chain" => chain::get_chain,
+    get "/dag/graph" => dag::get_graph,
+    get "/dag/tip-graph" => dag::get_tip_graph,
+    get "/info/blockreward" => get_block_reward,
+    get "/transactions/{hash}" => get_transaction,
+    get "/info/blockdag" => get_block_dag_info,
+    get "/info/kaspad" => get_kaspad_info,
+    get "/info/node" => node::get_node_info,
+    get "/info/hashrate/max" => get_max_hashrate,
+    get "/info/coinsupply" => get_coin_supply,
+    get "/addresses/top" => richlist::get_top,
+    get "/addresses/{addr}" => address::get_address,
+    get "/addresses/{addr}/balance" => get_balance_by_address,
+    get "/addresses/{addr}/balance/history" => history::get_balance_history,
+    get "/addresses/{addr}/transactions.csv" => export::get_transactions_csv,
+    get "/addresses/{addr}/transactions.jsonl" => export::get_transactions_jsonl,
+    get "/scripts/{spk}/address" => address::get_script_address,
+    get "/info/halving" => get_halving,
+    get "/info/difficulty" => difficulty::get_difficulty,
+    get "/info/difficulty/convert" => difficulty::convert,
+    get "/info/blockrate" => blockrate::get_block_rate,
+    get "/health" => health::report,
+    get "/health/live" => health::live,
+    get "/health/ready" => health::ready,
+    get "/metrics" => metrics::export,
+    get "/search" => search::search,
+    get "/stats/distribution" => richlist::get_distribution,
+    get "/stats/utxo" => utxostats::get_utxo_stats,
+    get "/stats/{metric}" => stats::get_series,
+    post "/batch/blocks" => batch::get_blocks,
+    post "/batch/balances" => batch::get_balances,
+    get "/admin/keys" => auth::list_keys,
+    post "/admin/keys" => auth::create_key,
+    delete "/admin/keys/{id}" => auth::revoke_key,
+    get "/webhooks" => webhooks::list_webhooks,
+    post "/webhooks" => webhooks::create_webhook,
+    get "/webhooks/{id}" => webhooks::get_webhook,
+    delete "/webhooks/{id}" => webhooks::delete_webhook,
+    post "/webhooks/{id}/ping" => webhooks::ping_webhook,
+    get "/webhooks/{id}/deliveries" => webhooks::list_deliveries,
+    post "/payments" => payments::create_payment,
+    get "/payments/{id}" => payments::get_payment,
+    get "/v1/blocks/{hash}" => v1::get_block,
+    get "/v1/transactions/{id}" => v1::get_transaction,
+    get "/v1/info/blockdag" => v1::get_block_dag_info,
+    get "/v1/info/node" => v1::get_node_info,
+    get "/v1/info/coinsupply" => v1::get_coin_supply,
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    HttpServer::new(|| {
+    logging::init();
+    let config = config::get();
+    let db = Db::open(&config.db_path)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, format!("{:?}", err)))?;
+    let api_keys = ApiKeys::load(&db)
+        .await
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, format!("{:?}", err)))?;
+
+    // Keep the local block index in sync in the background
+    tokio::spawn(index::run(db.clone(), config.index_interval));
+    // Sample network statistics for the /stats series
+    tokio::spawn(stats::run(db.clone(), config.stats_interval));
+    // Snapshot the UTXOs of every indexed address for the rich list, distribution and UTXO set stats
+    tokio::spawn(utxoset::run(db.clone(), config.utxo_snapshot_interval));
+    // Turn UTXO changes of watched addresses into webhook events and deliver them
+    let webhook_state = web::Data::new(Webhooks::default());
+    tokio::spawn(webhooks::listen(db.clone(), webhook_state.clone().into_inner()));
+    tokio::spawn(webhooks::deliver(db.clone()));
+    // Match incoming UTXOs to open payment requests
+    let payment_state = web::Data::new(Payments::default());
+    tokio::spawn(payments::listen(db.clone(), payment_state.clone().into_inner()));
+
+    let db = web::Data::new(db);
+    let health = web::Data::new(HealthState::default());
+    let limiter = web::Data::new(RateLimiter::default());
+    let api_keys = web::Data::new(api_keys);
+    HttpServer::new(move || {
         App::new()
+            .app_data(db.clone())
+            .app_data(health.clone())
+            .app_data(limiter.clone())
+            .app_data(api_keys.clone())
+            .app_data(webhook_state.clone())
+            .app_data(payment_state.clone())
+            // Reject clients over their budget before the handler opens a node connection
+            .wrap_fn(|req, srv| {
+                let rejection = req
+                    .app_data::<web::Data<RateLimiter>>()
+                    .and_then(|limiter| ratelimit::check(limiter, &req))
+                    // Daily quotas are only spent on requests the rate limiter lets through
+                    .or_else(|| req.app_data::<web::Data<ApiKeys>>().and_then(|keys| auth::check_quota(keys, &req)));
+                let response = match rejection {
+                    None => Ok(srv.call(req)),
+                    Some(rejection) => Err(req.into_response(rejection)),
+                };
+                async move {
+                    match response {
+                        Ok(response) => response.await.map(ServiceResponse::map_into_boxed_body),
+                        Err(rejection) => Ok(rejection),
+                    }
+                }
+            })
+            // Validate API keys and scopes, the rate limiter then budgets by the resolved key
+            .wrap_fn(|req, srv| {
+                let rejection = req
+                    .app_data::<web::Data<ApiKeys>>()
+                    .and_then(|keys| auth::authenticate(keys, &req));
+                let response = match rejection {
+                    None => Ok(srv.call(req)),
+                    Some(rejection) => Err(req.into_response(rejection)),
+                };
+                async move {
+                    match response {
+                        Ok(response) => response.await.map(ServiceResponse::map_into_boxed_body),
+                        Err(rejection) => Ok(rejection),
+                    }
+                }
+            })
             .wrap(
                 Cors::default()
                     .allow_any_origin()
@@ -32,15 +215,31 @@ async fn main() -> std::io::Result<()> {
                     .allow_any_header()
                     .max_age(3600)
             )
-            .service(web::resource("/blocks/{hash}").route(web::get().to(get_block)))
-            .service(web::resource("/info/blockreward").route(web::get().to(get_block_reward)))
-            .service(web::resource("/transactions/{hash}").route(web::get().to(get_transaction)))
-            .service(web::resource("/info/blockdag").route(web::get().to(get_block_dag_info)))
-            .service(web::resource("/info/kaspad").route(web::get().to(get_kaspad_info)))
-            .service(web::resource("/info/hashrate/max").route(web::get().to(get_max_hashrate)))
-            .service(web::resource("/info/coinsupply").route(web::get().to(get_coin_supply)))
-            .service(web::resource("/addresses/{addr}/balance").route(web::get().to(get_balance_by_address)))
-            .service(web::resource("/info/halving").route(web::get().to(get_halving)))
+            // Count, time and trace every request by its route pattern
+            .wrap_fn(|req, srv| {
+                let route = metrics::route(&req);
+                let method = req.method().to_string();
+                let request_id = logging::request_id(&req);
+                let span = info_span!("request", request_id = %request_id, route = %route, method = %method);
+                let start = Instant::now();
+                let response = srv.call(req);
+                async move {
+                    let response = response.await?;
+                    let status = response.status().as_u16();
+                    let elapsed = start.elapsed();
+                    metrics::observe_request(&route, &method, status, elapsed.as_secs_f64());
+                    if status >= 500 {
+                        warn!(status, duration_ms = elapsed.as_millis() as u64, "Request failed");
+                    } else {
+                        info!(status, duration_ms = elapsed.as_millis() as u64, "Request completed");
+                    }
+                    logging::with_request_id(response, &request_id).await
+                }
+                    .instrument(span)
+            })
+            .configure(configure_routes)
+            .route("/openapi.json", web::get().to(openapi::spec))
+            .service(Redoc::with_url("/docs", openapi::get().clone()))
     })
         .bind(("0.0.0.0", 3001))?
         .run()
@@ -48,15 +247,34 @@ async fn main() -> std::io::Result<()> {
 }
 
 
+#[derive(Serialize, ToSchema)]
+struct BlockRewardResponse {
+    block_hash: String,
+    block_reward: Amount,
+}
+
 async fn get_client() -> Result<KaspaRpcClient, HttpResponse> {
+    connect_node().await.map_err(|_| {
+        HttpResponse::InternalServerError().json("Failed to connect to Kaspa node")
+    })
+}
+
+// Parse a block hash or transaction id from a path or query parameter
+fn parse_hash(value: &str) -> Result<RpcHash, HttpResponse> {
+    value
+        .parse()
+        .map_err(|_| HttpResponse::BadRequest().json(format!("Invalid hash: {}", value)))
+}
+
+async fn connect_node() -> anyhow::Result<KaspaRpcClient> {
     // Create a new KaspaRpcClient
-    let mut kaspa_rpc = KaspaRpcClient::new(
+    let kaspa_rpc = KaspaRpcClient::new(
         WrpcEncoding::SerdeJson,
-        Some("ws://eu.losmuchachos.digital:19910"),
+        Some(&config::get().node_url),
         None,
         None,
         None,
-    ).unwrap();
+    ).context("Failed to create Kaspa RPC client")?;
 
     // Define the connection options
     let connect_options = ConnectOptions {
@@ -66,13 +284,11 @@ async fn get_client() -> Result<KaspaRpcClient, HttpResponse> {
     };
 
     // Attempt to connect with proper error handling
-    kaspa_rpc
-        .connect(Some(connect_options))
-        .await
-        .with_context(|| "Failed to connect to Kaspa node")
-        .map_err(|_| {
-            HttpResponse::InternalServerError().json("Failed to connect to Kaspa node")
-        })?;
+    if let Err(err) = kaspa_rpc.connect(Some(connect_options)).await {
+        metrics::get().upstream_connect_failures.inc();
+        return Err(err).with_context(|| "Failed to connect to Kaspa node");
+    }
+    metrics::get().upstream_connects.inc();
 
     // Return the connected client
     Ok(kaspa_rpc)
@@ -84,6 +300,16 @@ async fn disconnect_client(client: web::Data<KaspaRpcClient>) -> HttpResponse {
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/blocks/{hash}",
+    tag = "blocks",
+    params(("hash" = String, Path, description = "Block hash")),
+    responses(
+        (status = 200, description = "Block with its transactions and verbose data", body = Object),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
 async fn get_block(path: web::Path<String>) -> impl Responder {
     let hash = path.into_inner();
     let mut client = match get_client().await {
@@ -91,7 +317,7 @@ async fn get_block(path: web::Path<String>) -> impl Responder {
         Err(err) => return err,
     };
 
-   let block = match client.get_block(hash.parse().unwrap(), true).await {
+   let block = match metrics::rpc("get_block", client.get_block(hash.parse().unwrap(), true)).await {
         Ok(block) => block,
       Err(err) => {
             return HttpResponse::InternalServerError().json(format!("Failed to get block DAG info: {:?}", err));
@@ -106,7 +332,17 @@ async fn get_block(path: web::Path<String>) -> impl Responder {
     HttpResponse::Ok().json(block)
 }
 
-async fn get_block_reward() -> impl Responder {
+#[utoipa::path(
+    get,
+    path = "/info/blockreward",
+    tag = "info",
+    params(UnitQuery),
+    responses(
+        (status = 200, description = "Coinbase reward of the current tip", body = BlockRewardResponse),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
+async fn get_block_reward(query: web::Query<UnitQuery>) -> impl Responder {
     // Step 1: Get the Kaspa RPC client
     let mut client = match get_client().await {
         Ok(client) => client,
@@ -114,14 +350,17 @@ async fn get_block_reward() -> impl Responder {
     };
 
     // Step 2: Get the latest block's hash from the DAG info
-    let block_dag_info = match client.get_block_dag_info().await {
+    let block_dag_info = match metrics::rpc("get_block_dag_info", client.get_block_dag_info()).await {
         Ok(info) => info,
         Err(_) => return HttpResponse::InternalServerError().json("Failed to fetch block DAG info"),
     };
-    let latest_block_hash = block_dag_info.tip_hashes[0].clone();
+    let latest_block_hash = match block_dag_info.tip_hashes.first() {
+        Some(hash) => *hash,
+        None => return HttpResponse::InternalServerError().json("Node reported no tip hashes"),
+    };
 
     // Step 3: Get the latest block using its hash, including transactions
-    let block_result = match client.get_block(latest_block_hash, true).await {
+    let block_result = match metrics::rpc("get_block", client.get_block(latest_block_hash, true)).await {
         Ok(block) => block,
         Err(_) => return HttpResponse::InternalServerError().json("Failed to fetch block information"),
     };
@@ -140,18 +379,27 @@ async fn get_block_reward() -> impl Responder {
     let mut block_reward: u64 = 0;  // Use `u64` for reward in smallest unit
 
     for output in &coinbase_tx.outputs {
-        println!("The block reward is not stable at 500 XEN, it is: {}", output.value);
+        debug!(value = output.value, "Coinbase output, the block reward is not stable at 500 XEN");
         block_reward = output.value;
     }
-    let block_reward_in_xen = block_reward as f64 / SOMPI_PER_KASPA as f64;
     // Step 6: Respond with the block reward and block hash
-    HttpResponse::Ok().json(json!({
-        "block_hash": latest_block_hash,
-        "block_reward": block_reward_in_xen
-    }))
+    HttpResponse::Ok().json(BlockRewardResponse {
+        block_hash: latest_block_hash.to_string(),
+        block_reward: Amount::new(block_reward, query.unit()),
+    })
 
 }
 
+#[utoipa::path(
+    get,
+    path = "/transactions/{hash}",
+    tag = "blocks",
+    params(("hash" = String, Path, description = "Hash of the block holding the transactions")),
+    responses(
+        (status = 200, description = "Block with its transactions", body = Object),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
 async fn get_transaction(path: web::Path<String>) -> impl Responder {
     let hash = path.into_inner();
     let mut client = match get_client().await {
@@ -159,7 +407,7 @@ async fn get_transaction(path: web::Path<String>) -> impl Responder {
         Err(err) => return err,
     };
 
-  let transaction =  match client.get_block(hash.parse().unwrap(), true).await {
+  let transaction =  match metrics::rpc("get_block", client.get_block(hash.parse().unwrap(), true)).await {
         Ok(transaction) => transaction,
       Err(err) => {
             return HttpResponse::InternalServerError().json(format!("Failed to get block DAG info: {:?}", err));
@@ -175,13 +423,22 @@ async fn get_transaction(path: web::Path<String>) -> impl Responder {
 
 }
 
+#[utoipa::path(
+    get,
+    path = "/info/blockdag",
+    tag = "info",
+    responses(
+        (status = 200, description = "Block DAG info of the node", body = Object),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
 async fn get_block_dag_info() -> impl Responder {
     let mut client = match get_client().await {
         Ok(client) => client,
         Err(err) => return err,
     };
 
-    let info = match client.get_block_dag_info().await {
+    let info = match metrics::rpc("get_block_dag_info", client.get_block_dag_info()).await {
         Ok(info) => info,
       Err(err) => {
             return HttpResponse::InternalServerError().json(format!("Failed to get block DAG info: {:?}", err));
@@ -195,13 +452,22 @@ async fn get_block_dag_info() -> impl Responder {
     HttpResponse::Ok().json(info)
 }
 
+#[utoipa::path(
+    get,
+    path = "/info/kaspad",
+    tag = "info",
+    responses(
+        (status = 200, description = "Version, mempool and sync state of the node", body = Object),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
 async fn get_kaspad_info() -> impl Responder {
     let mut client = match get_client().await {
         Ok(client) => client,
         Err(err) => return err,
     };
 
-   let info = match client.get_info().await {
+   let info = match metrics::rpc("get_info", client.get_info()).await {
         Ok(info) => info,
      Err(err) => {
             return HttpResponse::InternalServerError().json(format!("Failed to get block DAG info: {:?}", err));
@@ -214,6 +480,15 @@ async fn get_kaspad_info() -> impl Responder {
     HttpResponse::Ok().json(info)
 }
 
+#[utoipa::path(
+    get,
+    path = "/info/hashrate/max",
+    tag = "info",
+    responses(
+        (status = 200, description = "Estimated network hashes per second", body = u64),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
 async fn get_max_hashrate() -> impl Responder {
     // Attempt to get the client
     let client = match get_client().await {
@@ -224,7 +499,7 @@ async fn get_max_hashrate() -> impl Responder {
     };
 
     // Attempt to get the block DAG info
-    let block_dag_info = match client.get_block_dag_info().await {
+    let block_dag_info = match metrics::rpc("get_block_dag_info", client.get_block_dag_info()).await {
         Ok(info) => info,
         Err(err) => {
             return HttpResponse::InternalServerError().json(format!("Failed to get block DAG info: {:?}", err));
@@ -232,10 +507,13 @@ async fn get_max_hashrate() -> impl Responder {
     };
 
     // Fetch the latest block hash
-    let latest_block_hash = block_dag_info.tip_hashes[0].clone();
+    let latest_block_hash = match block_dag_info.tip_hashes.first() {
+        Some(hash) => *hash,
+        None => return HttpResponse::InternalServerError().json("Node reported no tip hashes"),
+    };
 
     // Attempt to get the block
-    let block = match client.get_block(latest_block_hash.clone(), false).await {
+    let block = match metrics::rpc("get_block", client.get_block(latest_block_hash.clone(), false)).await {
         Ok(block) => block,
         Err(err) => {
             return HttpResponse::InternalServerError().json(format!("Failed to get block: {:?}", err));
@@ -243,7 +521,7 @@ async fn get_max_hashrate() -> impl Responder {
     };
 
     // Estimate the network hashrate
-   let hashrate = match client.estimate_network_hashes_per_second(6000, Some(block.header.hash)).await {
+   let hashrate = match metrics::rpc("estimate_network_hashes_per_second", client.estimate_network_hashes_per_second(6000, Some(block.header.hash))).await {
         Ok(hashrate) => hashrate,
      Err(err) => {
             return HttpResponse::InternalServerError().json(format!("Failed to get block DAG info: {:?}", err));
@@ -256,34 +534,57 @@ async fn get_max_hashrate() -> impl Responder {
     HttpResponse::Ok().json(hashrate)
 }
 
-async fn get_coin_supply() -> impl Responder {
-    let mut client = match get_client().await {
-        Ok(client) => client,
-        Err(err) => return err,
-    };
+#[utoipa::path(
+    get,
+    path = "/info/coinsupply",
+    tag = "info",
+    params(UnitQuery),
+    responses(
+        (status = 200, description = "Circulating and maximum supply", body = SupplyDto),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
+async fn get_coin_supply(query: web::Query<UnitQuery>) -> impl Responder {
+    match fetch_coin_supply().await {
+        Ok(supply) => HttpResponse::Ok().json(SupplyDto::new(&supply, query.unit())),
+        Err(err) => err,
+    }
+}
 
-   let supply = match client.get_coin_supply().await {
-        Ok(supply) => supply ,
-     Err(err) => {
-            return HttpResponse::InternalServerError().json(format!("Failed to get block DAG info: {:?}", err));
+// Supply in sompi, shared by the legacy and /v1 routes
+async fn fetch_coin_supply() -> Result<CoinSupply, HttpResponse> {
+    let client = get_client().await?;
+
+    let supply = match metrics::rpc("get_coin_supply", client.get_coin_supply()).await {
+        Ok(supply) => supply,
+        Err(err) => {
+            return Err(HttpResponse::InternalServerError().json(format!("Failed to get coin supply: {:?}", err)));
         }
     };
     if let Err(disconnect_err) = client.disconnect().await {
-        // Log or handle disconnect error if needed
-        return HttpResponse::InternalServerError().json(format!("Failed to disconnect: {}", disconnect_err));
+        return Err(HttpResponse::InternalServerError().json(format!("Failed to disconnect: {}", disconnect_err)));
     }
-    HttpResponse::Ok().json(supply.circulating_sompi.to_string())
-
+    Ok(CoinSupply { circulating_sompi: supply.circulating_sompi, max_sompi: supply.max_sompi })
 }
 
-async fn get_balance_by_address(path: web::Path<String>) -> impl Responder {
+#[utoipa::path(
+    get,
+    path = "/addresses/{addr}/balance",
+    tag = "addresses",
+    params(("addr" = String, Path, description = "Address with its network prefix"), UnitQuery),
+    responses(
+        (status = 200, description = "Balance of the address", body = BalanceResponse),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
+async fn get_balance_by_address(path: web::Path<String>, query: web::Query<UnitQuery>) -> impl Responder {
     let addr = path.into_inner();
     let mut client = match get_client().await {
         Ok(client) => client,
         Err(err) => return err,
     };
 
-    let balance =  match client.get_balance_by_address(RpcAddress::try_from(addr).unwrap()).await {
+    let balance =  match metrics::rpc("get_balance_by_address", client.get_balance_by_address(RpcAddress::try_from(addr).unwrap())).await {
         Ok(balance) => balance,
       Err(err) => {
             return HttpResponse::InternalServerError().json(format!("Failed to get block DAG info: {:?}", err));
@@ -293,7 +594,7 @@ async fn get_balance_by_address(path: web::Path<String>) -> impl Responder {
         // Log or handle disconnect error if needed
         return HttpResponse::InternalServerError().json(format!("Failed to disconnect: {}", disconnect_err));
     }
-    HttpResponse::Ok().json(BalanceResponse { balance: balance.to_string() })
+    HttpResponse::Ok().json(BalanceResponse { balance: Amount::new(balance, query.unit()) })
 }
 #[derive(Serialize)]
 struct HalvingInfo {
@@ -310,20 +611,16 @@ const SECONDS_PER_HALVING: u64 = SECONDS_PER_MONTH * 12; // Halving every 12 mon
 // Structure to hold the halving information
 
 
-// Function to get blocks per second (example value)
-fn get_blocks_per_second() -> u64 {
-    40 // Assuming 40 blocks per second as given
-}
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct HalvingResponse {
     next_halving_timestamp: i64,
     next_halving_date: String,
-    next_halving_amount: f64,
+    next_halving_amount: Amount,
 }
 
 
 // Calculate halving information
-async fn calculate_halving_info(daa_score: u64) -> HalvingResponse {
+async fn calculate_halving_info(daa_score: u64, unit: Unit) -> HalvingResponse {
     let mut data: HashMap<u64, f64> = HashMap::new();
     data.insert(15519600, 500.0);
     data.insert(18149400, 440.0);
@@ -616,37 +913,64 @@ async fn calculate_halving_info(daa_score: u64) -> HalvingResponse {
     let next_halving_timestamp = Utc::now().timestamp() + (daa_breakpoint as i64 - daa_score as i64);
     let next_halving_date = Utc.timestamp(next_halving_timestamp, 0).to_string(); // Format date
 
+    // The schedule is kept in XEN, its decimal form converts to sompi exactly
+    let future_reward_sompi = parse_xen(&future_reward.to_string())
+        .unwrap_or_else(|| (future_reward * SOMPI_PER_KASPA as f64).round() as u64);
+
     HalvingResponse {
         next_halving_timestamp,
         next_halving_date,
-        next_halving_amount: future_reward,
+        next_halving_amount: Amount::new(future_reward_sompi, unit),
     }
 
 }
 // Function to calculate the next halving timestamp and amount
-async fn get_halving() -> impl Responder {
+#[utoipa::path(
+    get,
+    path = "/info/halving",
+    tag = "info",
+    params(UnitQuery),
+    responses(
+        (status = 200, description = "Next subsidy reduction", body = HalvingResponse),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
+async fn get_halving(query: web::Query<UnitQuery>) -> impl Responder {
 
     let mut client = match get_client().await {
         Ok(client) => client,
         Err(err) => return err,
     };
     // Step 2: Get the latest block's hash from the DAG info
-    let block_dag_info = match client.get_block_dag_info().await {
+    let block_dag_info = match metrics::rpc("get_block_dag_info", client.get_block_dag_info()).await {
         Ok(info) => info,
         Err(_) => return HttpResponse::InternalServerError().json("Failed to fetch block DAG info"),
     };
-    let latest_block_hash = block_dag_info.tip_hashes[0].clone();
+    let latest_block_hash = match block_dag_info.tip_hashes.first() {
+        Some(hash) => *hash,
+        None => return HttpResponse::InternalServerError().json("Node reported no tip hashes"),
+    };
 
     // Step 3: Get the latest block using its hash, including transactions
-    let block_result = match client.get_block(latest_block_hash, true).await {
+    let block_result = match metrics::rpc("get_block", client.get_block(latest_block_hash, true)).await {
         Ok(block) => block,
         Err(_) => return HttpResponse::InternalServerError().json("Failed to fetch block information"),
     };
-    let halving_info = calculate_halving_info(block_result.header.daa_score).await;
+    let halving_info = calculate_halving_info(block_result.header.daa_score, query.unit()).await;
     if let Err(disconnect_err) = client.disconnect().await {
         // Log or handle disconnect error if needed
         return HttpResponse::InternalServerError().json(format!("Failed to disconnect: {}", disconnect_err));
     }
     HttpResponse::Ok().json(halving_info)
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_routes_documented() {
+        let undocumented = openapi::undocumented(ROUTES);
+        assert!(undocumented.is_empty(), "Routes missing from the OpenAPI document: {}", undocumented.join(", "));
+    }
+}
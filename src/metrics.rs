@@ -0,0 +1,176 @@
+use std::future::Future;
+use std::sync::OnceLock;
+use actix_web::dev::ServiceRequest;
+use actix_web::{HttpResponse, Responder};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use tokio::time::Instant;
+use tracing::{debug, debug_span, warn, Instrument};
+
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests: IntCounterVec,
+    pub http_request_duration: HistogramVec,
+    pub rpc_calls: IntCounterVec,
+    pub rpc_failures: IntCounterVec,
+    pub rpc_duration: HistogramVec,
+    pub upstream_connects: IntCounter,
+    pub upstream_connect_failures: IntCounter,
+    pub indexer_tip_daa_score: IntGauge,
+    pub indexer_daa_score_lag: IntGauge,
+}
+
+impl Metrics {
+    fn new() -> Metrics {
+        let registry = Registry::new_custom(Some("xenom_api".to_string()), None).unwrap();
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route, method and status"),
+            &["route", "method", "status"],
+        )
+            .unwrap();
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "HTTP request latency by route and method"),
+            &["route", "method"],
+        )
+            .unwrap();
+        let rpc_calls = IntCounterVec::new(Opts::new("rpc_calls_total", "Node RPC calls by method"), &["method"]).unwrap();
+        let rpc_failures =
+            IntCounterVec::new(Opts::new("rpc_failures_total", "Failed node RPC calls by method"), &["method"]).unwrap();
+        let rpc_duration = HistogramVec::new(
+            HistogramOpts::new("rpc_duration_seconds", "Node RPC latency by method"),
+            &["method"],
+        )
+            .unwrap();
+        let upstream_connects = IntCounter::new("upstream_connects_total", "Connections opened to the node").unwrap();
+        let upstream_connect_failures =
+            IntCounter::new("upstream_connect_failures_total", "Failed connection attempts to the node").unwrap();
+        let indexer_tip_daa_score = IntGauge::new("indexer_tip_daa_score", "DAA score of the newest indexed block").unwrap();
+        let indexer_daa_score_lag =
+            IntGauge::new("indexer_daa_score_lag", "Virtual DAA score minus the newest indexed DAA score").unwrap();
+
+        registry.register(Box::new(http_requests.clone())).unwrap();
+        registry.register(Box::new(http_request_duration.clone())).unwrap();
+        registry.register(Box::new(rpc_calls.clone())).unwrap();
+        registry.register(Box::new(rpc_failures.clone())).unwrap();
+        registry.register(Box::new(rpc_duration.clone())).unwrap();
+        registry.register(Box::new(upstream_connects.clone())).unwrap();
+        registry.register(Box::new(upstream_connect_failures.clone())).unwrap();
+        registry.register(Box::new(indexer_tip_daa_score.clone())).unwrap();
+        registry.register(Box::new(indexer_daa_score_lag.clone())).unwrap();
+
+        Metrics {
+            registry,
+            http_requests,
+            http_request_duration,
+            rpc_calls,
+            rpc_failures,
+            rpc_duration,
+            upstream_connects,
+            upstream_connect_failures,
+            indexer_tip_daa_score,
+            indexer_daa_score_lag,
+        }
+    }
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+pub fn get() -> &'static Metrics {
+    METRICS.get_or_init(Metrics::new)
+}
+
+// Time a node RPC call and count it, and its failure, under the method name
+pub async fn rpc<T, E, F>(method: &'static str, call: F) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    let metrics = get();
+    let start = Instant::now();
+    let result = call.instrument(debug_span!("rpc", method)).await;
+    let elapsed = start.elapsed();
+    metrics.rpc_calls.with_label_values(&[method]).inc();
+    metrics.rpc_duration.with_label_values(&[method]).observe(elapsed.as_secs_f64());
+    if result.is_err() {
+        metrics.rpc_failures.with_label_values(&[method]).inc();
+        warn!(method, duration_ms = elapsed.as_millis() as u64, "Node RPC call failed");
+    } else {
+        debug!(method, duration_ms = elapsed.as_millis() as u64, "Node RPC call completed");
+    }
+    result
+}
+
+// Label of a request: its route pattern, so /blocks/{hash} is one series however many hashes are asked for
+pub fn route(req: &ServiceRequest) -> String {
+    req.match_pattern().unwrap_or_else(|| "unmatched".to_string())
+}
+
+pub fn observe_request(route: &str, method: &str, status: u16, seconds: f64) {
+    let metrics = get();
+    metrics.http_requests.with_label_values(&[route, method, &status.to_string()]).inc();
+    metrics.http_request_duration.with_label_values(&[route, method]).observe(seconds);
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "health",
+    responses((status = 200, description = "Prometheus text exposition", body = String, content_type = "text/plain"))
+)]
+pub async fn export() -> impl Responder {
+    let metrics = get();
+
+    let mut buffer = Vec::new();
+    if let Err(err) = TextEncoder::new().encode(&metrics.registry.gather(), &mut buffer) {
+        return HttpResponse::InternalServerError().json(format!("Failed to encode metrics: {}", err));
+    }
+    HttpResponse::Ok().content_type(prometheus::TEXT_FORMAT).body(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use actix_web::body::to_bytes;
+    use actix_web::dev::Service;
+    use actix_web::{test, web, App};
+    use super::*;
+
+    #[actix_web::test]
+    async fn labels_requests_by_route_pattern() {
+        let routes = Arc::new(Mutex::new(Vec::new()));
+        let seen = routes.clone();
+        let app = test::init_service(
+            App::new()
+                .wrap_fn(move |req, srv| {
+                    seen.lock().unwrap().push(route(&req));
+                    srv.call(req)
+                })
+                .route("/labels/{hash}", web::get().to(HttpResponse::Ok)),
+        )
+            .await;
+
+        test::call_service(&app, test::TestRequest::get().uri("/labels/abc").to_request()).await;
+        test::call_service(&app, test::TestRequest::get().uri("/labels/def").to_request()).await;
+        test::call_service(&app, test::TestRequest::get().uri("/elsewhere").to_request()).await;
+        assert_eq!(*routes.lock().unwrap(), ["/labels/{hash}", "/labels/{hash}", "unmatched"]);
+    }
+
+    #[actix_web::test]
+    async fn counts_requests_by_route_method_and_status() {
+        observe_request("/counted/{id}", "GET", 200, 0.01);
+        observe_request("/counted/{id}", "GET", 200, 0.02);
+        observe_request("/counted/{id}", "GET", 404, 0.01);
+        observe_request("/counted/{id}", "POST", 503, 2.0);
+
+        let requests = |method, status| get().http_requests.with_label_values(&["/counted/{id}", method, status]).get();
+        assert_eq!(requests("GET", "200"), 2);
+        assert_eq!(requests("GET", "404"), 1);
+        assert_eq!(requests("POST", "503"), 1);
+        assert_eq!(get().http_request_duration.with_label_values(&["/counted/{id}", "GET"]).get_sample_count(), 3);
+
+        let response = export().await.respond_to(&test::TestRequest::default().to_http_request());
+        let text = String::from_utf8(to_bytes(response.into_body()).await.ok().unwrap().to_vec()).unwrap();
+        assert!(text.contains(r#"xenom_api_http_requests_total{method="GET",route="/counted/{id}",status="404"} 1"#), "{}", text);
+    }
+}
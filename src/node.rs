@@ -0,0 +1,226 @@
+use actix_web::{HttpResponse, Responder};
+use kaspa_rpc_core::api::rpc::RpcApi;
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+use crate::logging::ErrorBody;
+use crate::metrics;
+
+#[derive(Serialize, ToSchema)]
+struct ServerInfo {
+    server_version: String,
+    network_id: String,
+    has_utxo_index: bool,
+    is_synced: bool,
+    virtual_daa_score: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct PeerInfo {
+    id: String,
+    address: String,
+    user_agent: String,
+    protocol_version: u32,
+    is_outbound: bool,
+    is_ibd_peer: bool,
+    latency_ms: u64,
+    time_offset_ms: i64,
+    connected_at: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct PeersSummary {
+    count: usize,
+    outbound: usize,
+    inbound: usize,
+    average_latency_ms: Option<u64>,
+    peers: Vec<PeerInfo>,
+}
+
+impl PeersSummary {
+    fn of(peers: Vec<PeerInfo>) -> PeersSummary {
+        let outbound = peers.iter().filter(|peer| peer.is_outbound).count();
+        let average_latency_ms =
+            (!peers.is_empty()).then(|| peers.iter().map(|peer| peer.latency_ms).sum::<u64>() / peers.len() as u64);
+        PeersSummary { count: peers.len(), outbound, inbound: peers.len() - outbound, average_latency_ms, peers }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct NodeMetrics {
+    server_time: u64,
+    #[schema(value_type = Object)]
+    process: Value,
+    #[schema(value_type = Object)]
+    connection: Value,
+}
+
+#[derive(Serialize, ToSchema)]
+struct NodeResponse {
+    server: ServerInfo,
+    is_synced: bool,
+    peers: PeersSummary,
+    // Missing when the node doesn't expose metrics
+    metrics: Option<NodeMetrics>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/info/node",
+    tag = "info",
+    responses(
+        (status = 200, description = "Server, sync, peer and metrics details of the node", body = NodeResponse),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
+pub async fn get_node_info() -> impl Responder {
+    let client = match crate::get_client().await {
+        Ok(client) => client,
+        Err(err) => return err,
+    };
+
+    let server_info = match metrics::rpc("get_server_info", client.get_server_info()).await {
+        Ok(info) => info,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to get server info: {:?}", err)),
+    };
+    let is_synced = match metrics::rpc("get_sync_status", client.get_sync_status()).await {
+        Ok(is_synced) => is_synced,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to get sync status: {:?}", err)),
+    };
+    let peer_info = match metrics::rpc("get_connected_peer_info", client.get_connected_peer_info()).await {
+        Ok(response) => response.peer_info,
+        Err(err) => {
+            return HttpResponse::InternalServerError().json(format!("Failed to get connected peer info: {:?}", err));
+        }
+    };
+    // Metrics are best effort, older or restricted nodes refuse them
+    let node_metrics = metrics::rpc("get_metrics", client.get_metrics(true, true, false, false, false, false)).await.ok();
+
+    if let Err(disconnect_err) = client.disconnect().await {
+        return HttpResponse::InternalServerError().json(format!("Failed to disconnect: {}", disconnect_err));
+    }
+
+    let peers: Vec<PeerInfo> = peer_info
+        .iter()
+        .map(|peer| PeerInfo {
+            id: peer.id.to_string(),
+            address: peer.address.to_string(),
+            user_agent: peer.user_agent.clone(),
+            protocol_version: peer.advertised_protocol_version,
+            is_outbound: peer.is_outbound,
+            is_ibd_peer: peer.is_ibd_peer,
+            latency_ms: peer.last_ping_duration,
+            time_offset_ms: peer.time_offset,
+            connected_at: peer.time_connected,
+        })
+        .collect();
+    let metrics = node_metrics.map(|node_metrics| NodeMetrics {
+        server_time: node_metrics.server_time,
+        process: serde_json::to_value(&node_metrics.process_metrics).unwrap_or(Value::Null),
+        connection: serde_json::to_value(&node_metrics.connection_metrics).unwrap_or(Value::Null),
+    });
+
+    HttpResponse::Ok().json(NodeResponse {
+        server: ServerInfo {
+            server_version: server_info.server_version,
+            network_id: server_info.network_id.to_string(),
+            has_utxo_index: server_info.has_utxo_index,
+            is_synced: server_info.is_synced,
+            virtual_daa_score: server_info.virtual_daa_score,
+        },
+        is_synced,
+        peers: PeersSummary::of(peers),
+        metrics,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn peer(id: &str, is_outbound: bool, latency_ms: u64) -> PeerInfo {
+        PeerInfo {
+            id: id.to_string(),
+            address: "10.0.0.1:19911".to_string(),
+            user_agent: "/xenomd:0.15.2/".to_string(),
+            protocol_version: 7,
+            is_outbound,
+            is_ibd_peer: false,
+            latency_ms,
+            time_offset_ms: -12,
+            connected_at: 1_700_000_000_000,
+        }
+    }
+
+    fn server() -> ServerInfo {
+        ServerInfo {
+            server_version: "0.15.2".to_string(),
+            network_id: "mainnet".to_string(),
+            has_utxo_index: true,
+            is_synced: true,
+            virtual_daa_score: 1234,
+        }
+    }
+
+    #[test]
+    fn summarizes_peers() {
+        let summary = PeersSummary::of(vec![peer("a", true, 10), peer("b", true, 21), peer("c", false, 30)]);
+        assert_eq!((summary.count, summary.outbound, summary.inbound), (3, 2, 1));
+        assert_eq!(summary.average_latency_ms, Some(20));
+
+        let empty = PeersSummary::of(Vec::new());
+        assert_eq!((empty.count, empty.outbound, empty.inbound, empty.average_latency_ms), (0, 0, 0, None));
+    }
+
+    #[test]
+    fn serializes_the_node_response() {
+        let response = NodeResponse {
+            server: server(),
+            is_synced: true,
+            peers: PeersSummary::of(vec![peer("a", false, 15)]),
+            metrics: Some(NodeMetrics { server_time: 5, process: json!({"cpu": 1}), connection: Value::Null }),
+        };
+        assert_eq!(
+            serde_json::to_value(&response).unwrap(),
+            json!({
+                "server": {
+                    "server_version": "0.15.2",
+                    "network_id": "mainnet",
+                    "has_utxo_index": true,
+                    "is_synced": true,
+                    "virtual_daa_score": 1234,
+                },
+                "is_synced": true,
+                "peers": {
+                    "count": 1,
+                    "outbound": 0,
+                    "inbound": 1,
+                    "average_latency_ms": 15,
+                    "peers": [{
+                        "id": "a",
+                        "address": "10.0.0.1:19911",
+                        "user_agent": "/xenomd:0.15.2/",
+                        "protocol_version": 7,
+                        "is_outbound": false,
+                        "is_ibd_peer": false,
+                        "latency_ms": 15,
+                        "time_offset_ms": -12,
+                        "connected_at": 1_700_000_000_000u64,
+                    }],
+                },
+                "metrics": {"server_time": 5, "process": {"cpu": 1}, "connection": null},
+            })
+        );
+    }
+
+    #[test]
+    fn keeps_missing_metrics_as_null() {
+        let response =
+            NodeResponse { server: server(), is_synced: false, peers: PeersSummary::of(Vec::new()), metrics: None };
+        let value = serde_json::to_value(&response).unwrap();
+        assert_eq!(value["metrics"], Value::Null);
+        assert_eq!(value["peers"]["average_latency_ms"], Value::Null);
+        assert_eq!(value["peers"]["peers"], json!([]));
+    }
+}
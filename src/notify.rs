@@ -0,0 +1,44 @@
+use async_channel::Receiver;
+use kaspa_notify::listener::ListenerId;
+use kaspa_notify::scope::Scope;
+use kaspa_rpc_core::api::rpc::RpcApi;
+use kaspa_rpc_core::notify::connection::{ChannelConnection, ChannelType};
+use kaspa_rpc_core::Notification;
+use kaspa_wrpc_client::KaspaRpcClient;
+
+// A node connection with one listener receiving the notifications of the given scopes
+pub struct Subscription {
+    client: KaspaRpcClient,
+    listener_id: ListenerId,
+    receiver: Receiver<Notification>,
+}
+
+impl Subscription {
+    pub async fn start(name: &'static str, scopes: Vec<Scope>) -> anyhow::Result<Subscription> {
+        let client = crate::connect_node().await?;
+        let (sender, receiver) = async_channel::unbounded();
+        let listener_id = client.register_new_listener(ChannelConnection::new(name, sender, ChannelType::Closable));
+        for scope in scopes {
+            if let Err(err) = client.start_notify(listener_id, scope).await {
+                let _ = client.disconnect().await;
+                return Err(err.into());
+            }
+        }
+        Ok(Subscription { client, listener_id, receiver })
+    }
+
+    // The connection the notifications arrive on, for requests made while handling them
+    pub fn client(&self) -> &KaspaRpcClient {
+        &self.client
+    }
+
+    // Next notification, an error once the node connection is gone
+    pub async fn recv(&self) -> anyhow::Result<Notification> {
+        self.receiver.recv().await.map_err(|_| anyhow::anyhow!("Node notification channel closed"))
+    }
+
+    pub async fn close(self) {
+        let _ = self.client.unregister_listener(self.listener_id).await;
+        let _ = self.client.disconnect().await;
+    }
+}
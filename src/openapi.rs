@@ -0,0 +1,114 @@
+use std::sync::OnceLock;
+use actix_web::{HttpResponse, Responder};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, Http, HttpAuthScheme, SecurityScheme};
+use utoipa::openapi::OpenApi as Spec;
+use utoipa::{Modify, OpenApi};
+use crate::ratelimit::API_KEY_HEADER;
+
+// Every documented route; schemas of the referenced types are collected from the paths
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Xenom API",
+        description = "Block explorer and network statistics API for the Xenom network. Routes under /v1 return stable \
+                       camelCase schemas, the unversioned block, transaction and node info routes pass node RPC types through."
+    ),
+    paths(
+        crate::blocks::list_blocks,
+        crate::get_block,
+        crate::chain::get_is_chain,
+        crate::chain::get_accepting_block,
+        crate::chain::get_chain,
+        crate::dag::get_graph,
+        crate::dag::get_tip_graph,
+        crate::get_block_reward,
+        crate::get_transaction,
+        crate::get_block_dag_info,
+        crate::get_kaspad_info,
+        crate::node::get_node_info,
+        crate::get_max_hashrate,
+        crate::get_coin_supply,
+        crate::richlist::get_top,
+        crate::address::get_address,
+        crate::get_balance_by_address,
+        crate::history::get_balance_history,
+        crate::export::get_transactions_csv,
+        crate::export::get_transactions_jsonl,
+        crate::export::export_blocks,
+        crate::address::get_script_address,
+        crate::get_halving,
+        crate::difficulty::get_difficulty,
+        crate::difficulty::convert,
+        crate::blockrate::get_block_rate,
+        crate::health::report,
+        crate::health::live,
+        crate::health::ready,
+        crate::metrics::export,
+        crate::search::search,
+        crate::richlist::get_distribution,
+        crate::utxostats::get_utxo_stats,
+        crate::stats::get_series,
+        crate::batch::get_blocks,
+        crate::batch::get_balances,
+        crate::auth::list_keys,
+        crate::auth::create_key,
+        crate::auth::revoke_key,
+        crate::webhooks::list_webhooks,
+        crate::webhooks::create_webhook,
+        crate::webhooks::get_webhook,
+        crate::webhooks::delete_webhook,
+        crate::webhooks::ping_webhook,
+        crate::webhooks::list_deliveries,
+        crate::payments::create_payment,
+        crate::payments::get_payment,
+        crate::v1::get_block,
+        crate::v1::get_transaction,
+        crate::v1::get_block_dag_info,
+        crate::v1::get_node_info,
+        crate::v1::get_coin_supply,
+    ),
+    modifiers(&SecuritySchemes)
+)]
+pub struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut Spec) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("api_key", SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(API_KEY_HEADER))));
+        components.add_security_scheme("admin_token", SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)));
+    }
+}
+
+static SPEC: OnceLock<Spec> = OnceLock::new();
+
+pub fn get() -> &'static Spec {
+    SPEC.get_or_init(ApiDoc::openapi)
+}
+
+#[cfg(test)]
+fn has_operation(item: &utoipa::openapi::PathItem, method: &str) -> bool {
+    match method {
+        "get" => item.get.is_some(),
+        "post" => item.post.is_some(),
+        "put" => item.put.is_some(),
+        "patch" => item.patch.is_some(),
+        "delete" => item.delete.is_some(),
+        _ => false,
+    }
+}
+
+// Registered routes that have no operation in the document, as "METHOD /path"
+#[cfg(test)]
+pub fn undocumented(routes: &[(&str, &str)]) -> Vec<String> {
+    routes
+        .iter()
+        .filter(|(method, path)| !get().paths.paths.get(*path).map_or(false, |item| has_operation(item, method)))
+        .map(|(method, path)| format!("{} {}", method.to_uppercase(), path))
+        .collect()
+}
+
+pub async fn spec() -> impl Responder {
+    HttpResponse::Ok().json(get())
+}
@@ -0,0 +1,735 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use kaspa_notify::scope::{Scope, UtxosChangedScope, VirtualDaaScoreChangedScope};
+use kaspa_rpc_core::api::rpc::RpcApi;
+use kaspa_rpc_core::{Notification, RpcAddress, RpcUtxosByAddressesEntry};
+use kaspa_wrpc_client::KaspaRpcClient;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tokio::time::{sleep, Duration};
+use tracing::{error, info};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use crate::amount::{parse_xen, Amount, Unit};
+use crate::auth::ApiKey;
+use crate::db::Db;
+use crate::logging::ErrorBody;
+use crate::metrics;
+use crate::notify::Subscription;
+
+const DEFAULT_EXPIRY_SECS: u64 = 60 * 60;
+const MAX_EXPIRY_SECS: u64 = 7 * 24 * 60 * 60;
+// DAA score depth at which a paid request becomes confirmed, unless given
+const DEFAULT_CONFIRMATIONS: u64 = 100;
+const MAX_CONFIRMATIONS: u64 = 100_000;
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+// Wakes the listener when a payment request for a new address is created
+#[derive(Default)]
+pub struct Payments {
+    changed: Notify,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum PaymentState {
+    Pending,
+    PartiallyPaid,
+    Paid,
+    Confirmed,
+    Expired,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreatePaymentRequest {
+    // Address the customer pays to, best used for this request only
+    address: String,
+    // Requested amount in XEN
+    amount: String,
+    // Seconds the request stays open, an hour unless given
+    expires_in: Option<u64>,
+    // DAA score depth the paying UTXOs need to count as confirmed
+    confirmations: Option<u64>,
+    // Free text for the merchant, e.g. an order number
+    label: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PaymentQuery {
+    unit: Option<Unit>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct PaymentUtxo {
+    transaction_id: String,
+    output_index: u32,
+    amount: Amount,
+    block_daa_score: u64,
+    confirmations: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct PaymentResponse {
+    id: String,
+    state: PaymentState,
+    address: String,
+    amount: Amount,
+    received: Amount,
+    confirmations: u64,
+    label: Option<String>,
+    // Unix seconds
+    created_at: i64,
+    expires_at: i64,
+    paid_at: Option<i64>,
+    confirmed_at: Option<i64>,
+    // Only UTXOs created from this DAA score until expires_at count towards the request
+    start_daa_score: u64,
+    utxos: Vec<PaymentUtxo>,
+}
+
+// A payment request as stored
+struct Payment {
+    id: String,
+    address: String,
+    amount: u64,
+    confirmations: u64,
+    label: Option<String>,
+    start_daa_score: u64,
+    created_at: i64,
+    expires_at: i64,
+    paid_at: Option<i64>,
+    confirmed_at: Option<i64>,
+}
+
+const PAYMENT_COLUMNS: &str = "id, address, amount, confirmations, label, start_daa_score, created_at, expires_at, paid_at, confirmed_at";
+
+impl Payment {
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Payment> {
+        Ok(Payment {
+            id: row.get(0)?,
+            address: row.get(1)?,
+            amount: row.get::<_, i64>(2)? as u64,
+            confirmations: row.get::<_, i64>(3)? as u64,
+            label: row.get(4)?,
+            start_daa_score: row.get::<_, i64>(5)? as u64,
+            created_at: row.get(6)?,
+            expires_at: row.get(7)?,
+            paid_at: row.get(8)?,
+            confirmed_at: row.get(9)?,
+        })
+    }
+
+    // Whether a UTXO of a block with this DAA score and time (unix seconds) pays the request
+    fn counts(&self, block_daa_score: u64, block_time: i64) -> bool {
+        block_daa_score >= self.start_daa_score && block_time <= self.expires_at
+    }
+
+    // Received and confirmed sompi of the UTXOs
+    fn totals(&self, utxos: &[(String, u32, u64, u64)], virtual_daa_score: u64) -> (u64, u64) {
+        let received = utxos.iter().map(|(_, _, amount, _)| amount).sum();
+        let confirmed = utxos
+            .iter()
+            .filter(|(_, _, _, block_daa_score)| virtual_daa_score.saturating_sub(*block_daa_score) >= self.confirmations)
+            .map(|(_, _, amount, _)| amount)
+            .sum();
+        (received, confirmed)
+    }
+
+    // State from the received UTXOs, recording when the request was seen paid and first confirmed
+    fn update(&mut self, utxos: &[(String, u32, u64, u64)], virtual_daa_score: u64, now: i64) -> PaymentState {
+        let (received, confirmed) = self.totals(utxos, virtual_daa_score);
+        // UTXOs spent or reorged out before the request was confirmed no longer pay it
+        if received < self.amount && self.confirmed_at.is_none() {
+            self.paid_at = None;
+        }
+        if received >= self.amount && self.paid_at.is_none() {
+            self.paid_at = Some(now);
+        }
+        if confirmed >= self.amount && self.confirmed_at.is_none() {
+            self.confirmed_at = Some(now);
+        }
+
+        if self.confirmed_at.is_some() {
+            PaymentState::Confirmed
+        } else if self.paid_at.is_some() {
+            PaymentState::Paid
+        } else if now >= self.expires_at {
+            PaymentState::Expired
+        } else if received > 0 {
+            PaymentState::PartiallyPaid
+        } else {
+            PaymentState::Pending
+        }
+    }
+}
+
+// Header time in unix seconds of an indexed block with the DAA score. Blocks the indexer hasn't
+// reached yet are recent, they are taken as created now
+fn block_time(conn: &Connection, block_daa_score: u64, now: i64) -> rusqlite::Result<i64> {
+    let timestamp: Option<i64> = conn.query_row(
+        "SELECT MIN(timestamp) FROM blocks WHERE daa_score = ?1",
+        params![block_daa_score as i64],
+        |row| row.get(0),
+    )?;
+    Ok(timestamp.map_or(now, |timestamp| timestamp / 1000))
+}
+
+fn record_utxos(conn: &Connection, entries: &[RpcUtxosByAddressesEntry], now: i64) -> rusqlite::Result<()> {
+    let mut open = conn.prepare(&format!(
+        "SELECT {} FROM payments WHERE address = ?1 AND confirmed_at IS NULL AND (paid_at IS NOT NULL OR expires_at > ?2)",
+        PAYMENT_COLUMNS
+    ))?;
+    let mut insert = conn.prepare(
+        "INSERT OR IGNORE INTO payment_utxos (payment_id, transaction_id, output_index, amount, block_daa_score)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?;
+    for entry in entries {
+        let Some(address) = entry.address.as_ref() else { continue };
+        let payments = open
+            .query_map(params![address.to_string(), now], Payment::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        if payments.is_empty() {
+            continue;
+        }
+        let block_time = block_time(conn, entry.utxo_entry.block_daa_score, now)?;
+        for payment in payments {
+            if payment.counts(entry.utxo_entry.block_daa_score, block_time) {
+                insert.execute(params![
+                    payment.id,
+                    entry.outpoint.transaction_id.to_string(),
+                    entry.outpoint.index,
+                    entry.utxo_entry.amount as i64,
+                    entry.utxo_entry.block_daa_score as i64
+                ])?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Drops UTXOs the node reports as removed from the requests not confirmed yet
+fn forget_utxos(conn: &Connection, entries: &[RpcUtxosByAddressesEntry]) -> rusqlite::Result<()> {
+    let mut delete = conn.prepare(
+        "DELETE FROM payment_utxos WHERE transaction_id = ?1 AND output_index = ?2
+         AND payment_id IN (SELECT id FROM payments WHERE confirmed_at IS NULL)",
+    )?;
+    for entry in entries {
+        delete.execute(params![entry.outpoint.transaction_id.to_string(), entry.outpoint.index])?;
+    }
+    Ok(())
+}
+
+// Keeps only the UTXOs of a request that are among the current UTXOs of its address
+fn keep_live_utxos(conn: &Connection, id: &str, live: &HashSet<(String, u32)>) -> rusqlite::Result<()> {
+    let mut delete =
+        conn.prepare("DELETE FROM payment_utxos WHERE payment_id = ?1 AND transaction_id = ?2 AND output_index = ?3")?;
+    for (transaction_id, output_index, _, _) in payment_utxos(conn, id)? {
+        if !live.contains(&(transaction_id.clone(), output_index)) {
+            delete.execute(params![id, transaction_id, output_index])?;
+        }
+    }
+    Ok(())
+}
+
+fn live_outpoints(entries: &[RpcUtxosByAddressesEntry]) -> HashSet<(String, u32)> {
+    entries.iter().map(|entry| (entry.outpoint.transaction_id.to_string(), entry.outpoint.index)).collect()
+}
+
+fn payment_utxos(conn: &Connection, id: &str) -> rusqlite::Result<Vec<(String, u32, u64, u64)>> {
+    let mut stmt = conn.prepare(
+        "SELECT transaction_id, output_index, amount, block_daa_score FROM payment_utxos
+         WHERE payment_id = ?1 ORDER BY block_daa_score, transaction_id, output_index",
+    )?;
+    let rows = stmt.query_map(params![id], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get::<_, i64>(2)? as u64, row.get::<_, i64>(3)? as u64))
+    })?;
+    rows.collect()
+}
+
+fn store_progress(conn: &Connection, payment: &Payment) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE payments SET paid_at = ?1, confirmed_at = ?2 WHERE id = ?3",
+        params![payment.paid_at, payment.confirmed_at, payment.id],
+    )?;
+    Ok(())
+}
+
+// Unconfirmed requests that received anything, leaving out the ones that expired unpaid
+fn tracked_payments(conn: &Connection, now: i64) -> rusqlite::Result<Vec<Payment>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM payments WHERE confirmed_at IS NULL AND (paid_at IS NOT NULL OR expires_at > ?1)
+         AND id IN (SELECT payment_id FROM payment_utxos)",
+        PAYMENT_COLUMNS
+    ))?;
+    let rows = stmt.query_map(params![now], Payment::from_row)?;
+    rows.collect()
+}
+
+// Re-evaluate the tracked requests, the virtual moving on may confirm them. Requests about to be
+// confirmed are checked against the UTXOs the node still has first
+async fn on_virtual_daa_score(db: &Db, client: &KaspaRpcClient, virtual_daa_score: u64) -> anyhow::Result<()> {
+    let confirming = db
+        .call(move |conn| {
+            let mut confirming = Vec::new();
+            for payment in tracked_payments(conn, Utc::now().timestamp())? {
+                let utxos = payment_utxos(conn, &payment.id)?;
+                if payment.totals(&utxos, virtual_daa_score).1 >= payment.amount {
+                    confirming.push((payment.id, payment.address));
+                }
+            }
+            Ok(confirming)
+        })
+        .await?;
+    let live = if confirming.is_empty() {
+        HashSet::new()
+    } else {
+        let addresses: HashSet<&str> = confirming.iter().map(|(_, address)| address.as_str()).collect();
+        let addresses = addresses.into_iter().filter_map(|address| RpcAddress::try_from(address).ok()).collect();
+        let entries = metrics::rpc("get_utxos_by_addresses", client.get_utxos_by_addresses(addresses)).await?;
+        live_outpoints(&entries)
+    };
+
+    db.call(move |conn| {
+        let tx = conn.transaction()?;
+        for (id, _) in &confirming {
+            keep_live_utxos(&tx, id, &live)?;
+        }
+        let now = Utc::now().timestamp();
+        for mut payment in tracked_payments(&tx, now)? {
+            let (paid_at, confirmed_at) = (payment.paid_at, payment.confirmed_at);
+            let utxos = payment_utxos(&tx, &payment.id)?;
+            payment.update(&utxos, virtual_daa_score, now);
+            if (paid_at, confirmed_at) != (payment.paid_at, payment.confirmed_at) {
+                store_progress(&tx, &payment)?;
+            }
+        }
+        tx.commit()
+    })
+        .await
+}
+
+async fn open_addresses(db: &Db) -> anyhow::Result<Vec<RpcAddress>> {
+    let addresses: Vec<String> = db
+        .call(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT DISTINCT address FROM payments
+                 WHERE confirmed_at IS NULL AND (paid_at IS NOT NULL OR expires_at > ?1)",
+            )?;
+            let rows = stmt.query_map(params![Utc::now().timestamp()], |row| row.get(0))?;
+            rows.collect()
+        })
+        .await?;
+    Ok(addresses.iter().filter_map(|address| RpcAddress::try_from(address.as_str()).ok()).collect())
+}
+
+// Background task recording UTXOs paid to open payment requests as the node reports them
+pub async fn listen(db: Db, payments: Arc<Payments>) {
+    loop {
+        if let Err(err) = watch(&db, &payments).await {
+            error!(error = ?err, "Payment listener failed");
+            sleep(RECONNECT_DELAY).await;
+        }
+    }
+}
+
+// Runs one subscription until a request is created or the node goes away
+async fn watch(db: &Db, payments: &Payments) -> anyhow::Result<()> {
+    let addresses = open_addresses(db).await?;
+    // An empty scope would subscribe to every address of the network
+    if addresses.is_empty() {
+        payments.changed.notified().await;
+        return Ok(());
+    }
+
+    let count = addresses.len();
+    let subscription = Subscription::start(
+        "payments",
+        vec![
+            Scope::UtxosChanged(UtxosChangedScope::new(addresses)),
+            Scope::VirtualDaaScoreChanged(VirtualDaaScoreChangedScope {}),
+        ],
+    )
+        .await?;
+    info!(addresses = count, "Watching addresses for payments");
+
+    // Latest virtual DAA score, payments are re-evaluated with it as soon as their UTXOs change
+    let mut virtual_daa_score = None;
+    let result = loop {
+        tokio::select! {
+            _ = payments.changed.notified() => break Ok(()),
+            notification = subscription.recv() => {
+                let handled = match notification {
+                    Ok(Notification::UtxosChanged(notification)) => {
+                        let (added, removed) = (notification.added.clone(), notification.removed.clone());
+                        let recorded = db
+                            .call(move |conn| {
+                                let tx = conn.transaction()?;
+                                forget_utxos(&tx, &removed)?;
+                                record_utxos(&tx, &added, Utc::now().timestamp())?;
+                                tx.commit()
+                            })
+                            .await;
+                        match (recorded, virtual_daa_score) {
+                            (Ok(()), Some(score)) => on_virtual_daa_score(db, subscription.client(), score).await,
+                            (recorded, _) => recorded,
+                        }
+                    }
+                    Ok(Notification::VirtualDaaScoreChanged(notification)) => {
+                        virtual_daa_score = Some(notification.virtual_daa_score);
+                        on_virtual_daa_score(db, subscription.client(), notification.virtual_daa_score).await
+                    }
+                    Ok(_) => Ok(()),
+                    Err(err) => Err(err),
+                };
+                if let Err(err) = handled {
+                    break Err(err);
+                }
+            }
+        }
+    };
+
+    subscription.close().await;
+    result
+}
+
+#[utoipa::path(
+    post,
+    path = "/payments",
+    tag = "payments",
+    request_body = CreatePaymentRequest,
+    params(PaymentQuery),
+    security(("api_key" = [])),
+    responses(
+        (status = 201, description = "The new payment request", body = PaymentResponse),
+        (status = 400, description = "Invalid address, amount, expiry or depth", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = ErrorBody),
+        (status = 403, description = "API key lacks the payments scope", body = ErrorBody),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
+pub async fn create_payment(
+    req: HttpRequest,
+    body: web::Json<CreatePaymentRequest>,
+    query: web::Query<PaymentQuery>,
+    db: web::Data<Db>,
+    payments: web::Data<Payments>,
+) -> impl Responder {
+    let Some(owner) = req.extensions().get::<ApiKey>().map(|key| key.id.clone()) else {
+        return HttpResponse::Unauthorized().json("An API key is required");
+    };
+    let body = body.into_inner();
+    let address = match RpcAddress::try_from(body.address.as_str()) {
+        Ok(address) => address,
+        Err(err) => return HttpResponse::BadRequest().json(format!("Invalid address: {}", err)),
+    };
+    let amount = match parse_xen(&body.amount) {
+        Some(amount) if amount > 0 => amount,
+        _ => return HttpResponse::BadRequest().json(format!("Invalid XEN amount: {}", body.amount)),
+    };
+    let expires_in = body.expires_in.unwrap_or(DEFAULT_EXPIRY_SECS);
+    if expires_in == 0 || expires_in > MAX_EXPIRY_SECS {
+        return HttpResponse::BadRequest().json(format!("expires_in must be between 1 and {} seconds", MAX_EXPIRY_SECS));
+    }
+    let confirmations = body.confirmations.unwrap_or(DEFAULT_CONFIRMATIONS);
+    if confirmations > MAX_CONFIRMATIONS {
+        return HttpResponse::BadRequest().json(format!("confirmations must not exceed {}", MAX_CONFIRMATIONS));
+    }
+
+    let client = match crate::get_client().await {
+        Ok(client) => client,
+        Err(err) => return err,
+    };
+    let start_daa_score = match metrics::rpc("get_block_dag_info", client.get_block_dag_info()).await {
+        Ok(info) => info.virtual_daa_score,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to get block DAG info: {:?}", err)),
+    };
+    if let Err(disconnect_err) = client.disconnect().await {
+        return HttpResponse::InternalServerError().json(format!("Failed to disconnect: {}", disconnect_err));
+    }
+
+    let now = Utc::now().timestamp();
+    let payment = Payment {
+        id: Uuid::new_v4().to_string(),
+        address: address.to_string(),
+        amount,
+        confirmations,
+        label: body.label,
+        start_daa_score,
+        created_at: now,
+        expires_at: now + expires_in as i64,
+        paid_at: None,
+        confirmed_at: None,
+    };
+
+    let stored = db
+        .call(move |conn| {
+            conn.execute(
+                "INSERT INTO payments
+                     (id, owner, address, amount, confirmations, label, start_daa_score, created_at, expires_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    payment.id,
+                    owner,
+                    payment.address,
+                    payment.amount as i64,
+                    payment.confirmations as i64,
+                    payment.label,
+                    payment.start_daa_score as i64,
+                    payment.created_at,
+                    payment.expires_at
+                ],
+            )?;
+            Ok(payment)
+        })
+        .await;
+    let mut payment = match stored {
+        Ok(payment) => payment,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to store payment request: {:?}", err)),
+    };
+    payments.changed.notify_one();
+
+    info!(id = %payment.id, address = %payment.address, amount = payment.amount, "Created payment request");
+    let state = payment.update(&[], start_daa_score, now);
+    HttpResponse::Created().json(response(payment, state, Vec::new(), start_daa_score, query.unit.unwrap_or_default()))
+}
+
+fn response(
+    payment: Payment,
+    state: PaymentState,
+    utxos: Vec<(String, u32, u64, u64)>,
+    virtual_daa_score: u64,
+    unit: Unit,
+) -> PaymentResponse {
+    let received = utxos.iter().map(|(_, _, amount, _)| amount).sum();
+    PaymentResponse {
+        id: payment.id,
+        state,
+        address: payment.address,
+        amount: Amount::new(payment.amount, unit),
+        received: Amount::new(received, unit),
+        confirmations: payment.confirmations,
+        label: payment.label,
+        created_at: payment.created_at,
+        expires_at: payment.expires_at,
+        paid_at: payment.paid_at,
+        confirmed_at: payment.confirmed_at,
+        start_daa_score: payment.start_daa_score,
+        utxos: utxos
+            .into_iter()
+            .map(|(transaction_id, output_index, amount, block_daa_score)| PaymentUtxo {
+                transaction_id,
+                output_index,
+                amount: Amount::new(amount, unit),
+                block_daa_score,
+                confirmations: virtual_daa_score.saturating_sub(block_daa_score),
+            })
+            .collect(),
+    }
+}
+
+// Readable without a key so a checkout page can poll it, the random id is the capability
+#[utoipa::path(
+    get,
+    path = "/payments/{id}",
+    tag = "payments",
+    params(("id" = String, Path, description = "Payment request id"), PaymentQuery),
+    responses(
+        (status = 200, description = "State of the payment request and the UTXOs paying it", body = PaymentResponse),
+        (status = 404, description = "No payment request with this id", body = ErrorBody),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
+pub async fn get_payment(path: web::Path<String>, query: web::Query<PaymentQuery>, db: web::Data<Db>) -> impl Responder {
+    let id = path.into_inner();
+    let lookup_id = id.clone();
+    let payment = db
+        .call(move |conn| {
+            conn.query_row(&format!("SELECT {} FROM payments WHERE id = ?1", PAYMENT_COLUMNS), params![lookup_id], Payment::from_row)
+                .optional()
+        })
+        .await;
+    let payment = match payment {
+        Ok(Some(payment)) => payment,
+        Ok(None) => return HttpResponse::NotFound().json(format!("No payment request with id {}", id)),
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to read payment request: {:?}", err)),
+    };
+
+    // Ask the node directly as well, UTXOs that arrived while the listener was reconnecting are picked up here
+    let client = match crate::get_client().await {
+        Ok(client) => client,
+        Err(err) => return err,
+    };
+    let address = match RpcAddress::try_from(payment.address.as_str()) {
+        Ok(address) => address,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Corrupt payment address: {}", err)),
+    };
+    let entries = match metrics::rpc("get_utxos_by_addresses", client.get_utxos_by_addresses(vec![address])).await {
+        Ok(entries) => entries,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to get UTXOs: {:?}", err)),
+    };
+    let virtual_daa_score = match metrics::rpc("get_block_dag_info", client.get_block_dag_info()).await {
+        Ok(info) => info.virtual_daa_score,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to get block DAG info: {:?}", err)),
+    };
+    if let Err(disconnect_err) = client.disconnect().await {
+        return HttpResponse::InternalServerError().json(format!("Failed to disconnect: {}", disconnect_err));
+    }
+
+    let updated = db
+        .call(move |conn| {
+            let tx = conn.transaction()?;
+            let now = Utc::now().timestamp();
+            record_utxos(&tx, &entries, now)?;
+            let mut payment = payment;
+            if payment.confirmed_at.is_none() {
+                keep_live_utxos(&tx, &payment.id, &live_outpoints(&entries))?;
+            }
+            let (paid_at, confirmed_at) = (payment.paid_at, payment.confirmed_at);
+            let utxos = payment_utxos(&tx, &payment.id)?;
+            let state = payment.update(&utxos, virtual_daa_score, now);
+            if (paid_at, confirmed_at) != (payment.paid_at, payment.confirmed_at) {
+                store_progress(&tx, &payment)?;
+            }
+            tx.commit()?;
+            Ok((payment, state, utxos))
+        })
+        .await;
+
+    match updated {
+        Ok((payment, state, utxos)) => {
+            HttpResponse::Ok().json(response(payment, state, utxos, virtual_daa_score, query.unit.unwrap_or_default()))
+        }
+        Err(err) => HttpResponse::InternalServerError().json(format!("Failed to update payment request: {:?}", err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payment(amount: u64, expires_at: i64) -> Payment {
+        Payment {
+            id: "payment".to_string(),
+            address: "xenom:merchant".to_string(),
+            amount,
+            confirmations: 10,
+            label: None,
+            start_daa_score: 100,
+            created_at: 0,
+            expires_at,
+            paid_at: None,
+            confirmed_at: None,
+        }
+    }
+
+    fn utxo(index: u32, amount: u64, block_daa_score: u64) -> (String, u32, u64, u64) {
+        ("tx".to_string(), index, amount, block_daa_score)
+    }
+
+    #[test]
+    fn counts_utxos_from_the_start_score_until_the_expiry() {
+        let payment = payment(1_000, 500);
+        assert!(payment.counts(100, 500));
+        assert!(!payment.counts(99, 400));
+        assert!(!payment.counts(1_000, 501));
+    }
+
+    #[test]
+    fn moves_from_partially_paid_to_confirmed() {
+        let mut payment = payment(1_000, 500);
+        assert_eq!(payment.update(&[], 100, 10), PaymentState::Pending);
+        assert_eq!(payment.update(&[utxo(0, 400, 105)], 105, 20), PaymentState::PartiallyPaid);
+
+        let utxos = [utxo(0, 400, 105), utxo(1, 600, 110)];
+        assert_eq!(payment.update(&utxos, 115, 30), PaymentState::Paid);
+        assert_eq!(payment.paid_at, Some(30));
+        assert_eq!(payment.update(&utxos, 119, 40), PaymentState::Paid);
+        assert_eq!(payment.update(&utxos, 120, 50), PaymentState::Confirmed);
+        assert_eq!((payment.paid_at, payment.confirmed_at), (Some(30), Some(50)));
+    }
+
+    #[test]
+    fn removed_utxos_undo_an_unconfirmed_payment() {
+        let mut payment = payment(1_000, 500);
+        assert_eq!(payment.update(&[utxo(0, 1_000, 105)], 105, 20), PaymentState::Paid);
+        assert_eq!(payment.update(&[], 110, 30), PaymentState::Pending);
+        assert_eq!(payment.paid_at, None);
+        assert_eq!(payment.update(&[], 110, 600), PaymentState::Expired);
+    }
+
+    #[test]
+    fn expires_unpaid_requests() {
+        let mut payment = payment(1_000, 500);
+        assert_eq!(payment.update(&[utxo(0, 999, 105)], 200, 499), PaymentState::PartiallyPaid);
+        assert_eq!(payment.update(&[utxo(0, 999, 105)], 200, 500), PaymentState::Expired);
+    }
+
+    #[tokio::test]
+    async fn tracks_only_requests_that_can_still_complete() {
+        let db = Db::open(":memory:").unwrap();
+        let tracked = db
+            .call(|conn| {
+                for (id, expires_at, paid_at) in [("open", 2_000, None), ("expired", 500, None), ("paid", 500, Some(400))] {
+                    conn.execute(
+                        "INSERT INTO payments
+                             (id, owner, address, amount, confirmations, start_daa_score, created_at, expires_at, paid_at)
+                         VALUES (?1, 'owner', 'xenom:merchant', 1000, 10, 100, 0, ?2, ?3)",
+                        params![id, expires_at, paid_at],
+                    )?;
+                    conn.execute(
+                        "INSERT INTO payment_utxos (payment_id, transaction_id, output_index, amount, block_daa_score)
+                         VALUES (?1, 'tx', 0, 500, 105)",
+                        params![id],
+                    )?;
+                }
+                tracked_payments(conn, 1_000)
+            })
+            .await
+            .unwrap();
+        let mut ids: Vec<String> = tracked.into_iter().map(|payment| payment.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["open".to_string(), "paid".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn keeps_only_live_utxos() {
+        let db = Db::open(":memory:").unwrap();
+        let remaining = db
+            .call(|conn| {
+                for index in 0..3 {
+                    conn.execute(
+                        "INSERT INTO payment_utxos (payment_id, transaction_id, output_index, amount, block_daa_score)
+                         VALUES ('payment', 'tx', ?1, 500, 105)",
+                        params![index],
+                    )?;
+                }
+                let live = HashSet::from([("tx".to_string(), 0), ("tx".to_string(), 2)]);
+                keep_live_utxos(conn, "payment", &live)?;
+                payment_utxos(conn, "payment")
+            })
+            .await
+            .unwrap();
+        let indexes: Vec<u32> = remaining.iter().map(|(_, index, _, _)| *index).collect();
+        assert_eq!(indexes, vec![0, 2]);
+    }
+
+    #[tokio::test]
+    async fn block_time_falls_back_to_now_for_unindexed_blocks() {
+        let db = Db::open(":memory:").unwrap();
+        let times = db
+            .call(|conn| {
+                conn.execute(
+                    "INSERT INTO blocks (hash, daa_score, blue_score, timestamp, bits) VALUES ('block', 105, 100, 42500, 0)",
+                    [],
+                )?;
+                Ok((block_time(conn, 105, 1_000)?, block_time(conn, 106, 1_000)?))
+            })
+            .await
+            .unwrap();
+        assert_eq!(times, (42, 1_000));
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use actix_web::dev::ServiceRequest;
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::{HttpMessage, HttpResponse};
+use tokio::time::Instant;
+use crate::auth::ApiKey;
+use crate::config;
+
+// Buckets untouched for this long are full again and can be dropped
+const IDLE_BUCKET_SECS: u64 = 10 * 60;
+const CLEANUP_THRESHOLD: usize = 10_000;
+pub const API_KEY_HEADER: &str = "x-api-key";
+pub const API_KEY_PARAM: &str = "api_key";
+
+// Routes grouped by how much node work one request costs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteClass {
+    // Single info calls and lookups
+    Cheap,
+    Standard,
+    // Range walks, graphs and other multi-call queries
+    Expensive,
+}
+
+impl RouteClass {
+    pub fn of(route: &str) -> Option<RouteClass> {
+        match route {
+            // Probes and scrapes are never limited
+            "/health" | "/health/live" | "/health/ready" | "/metrics" => None,
+            "/info/blockdag" | "/info/coinsupply" | "/info/blockrate" | "/info/difficulty/convert" | "/openapi.json"
+            | "/docs" | "/v1/info/blockdag" | "/v1/info/coinsupply" => Some(RouteClass::Cheap),
+            "/blocks" | "/chain" | "/dag/graph" | "/dag/tip-graph" | "/stats/{metric}" | "/info/difficulty"
+            | "/blocks/{hash}/accepting-block" | "/batch/blocks" | "/batch/balances"
+            | "/addresses/{addr}/balance/history" | "/addresses/{addr}/transactions.csv"
+            | "/addresses/{addr}/transactions.jsonl" | "/blocks/export" => Some(RouteClass::Expensive),
+            _ => Some(RouteClass::Standard),
+        }
+    }
+}
+
+// Bucket size and refill rate of one route class
+#[derive(Debug, Clone, Copy)]
+pub struct Budget {
+    pub burst: f64,
+    pub per_second: f64,
+}
+
+impl Budget {
+    pub fn scaled(self, factor: f64) -> Budget {
+        Budget { burst: self.burst * factor, per_second: self.per_second * factor }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Budgets {
+    pub cheap: Budget,
+    pub standard: Budget,
+    pub expensive: Budget,
+}
+
+impl Budgets {
+    pub fn get(&self, class: RouteClass) -> Budget {
+        match class {
+            RouteClass::Cheap => self.cheap,
+            RouteClass::Standard => self.standard,
+            RouteClass::Expensive => self.expensive,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ClientId {
+    Ip(IpAddr),
+    ApiKey(String),
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<(ClientId, RouteClass), Bucket>>,
+}
+
+impl RateLimiter {
+    // Take one token, or return how many seconds until one is available
+    pub fn acquire(&self, client: ClientId, class: RouteClass, budget: Budget) -> Result<(), u64> {
+        self.acquire_at(client, class, budget, Instant::now())
+    }
+
+    fn acquire_at(&self, client: ClientId, class: RouteClass, budget: Budget, now: Instant) -> Result<(), u64> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > CLEANUP_THRESHOLD {
+            buckets.retain(|_, bucket| now.saturating_duration_since(bucket.updated).as_secs() < IDLE_BUCKET_SECS);
+        }
+
+        let bucket = buckets.entry((client, class)).or_insert(Bucket { tokens: budget.burst, updated: now });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * budget.per_second).min(budget.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else if budget.per_second > 0.0 {
+            Err(((1.0 - bucket.tokens) / budget.per_second).ceil() as u64)
+        } else {
+            Err(IDLE_BUCKET_SECS)
+        }
+    }
+}
+
+// API key from the header or the query string
+pub fn api_key(req: &ServiceRequest) -> Option<String> {
+    if let Some(key) = req.headers().get(API_KEY_HEADER).and_then(|value| value.to_str().ok()) {
+        return Some(key.to_string());
+    }
+    req.query_string()
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| *name == API_KEY_PARAM)
+        .map(|(_, key)| key.to_string())
+}
+
+fn is_trusted_proxy(ip: &IpAddr) -> bool {
+    config::get().trusted_proxies.iter().any(|(network, prefix)| in_network(ip, network, *prefix))
+}
+
+fn in_network(ip: &IpAddr, network: &IpAddr, prefix: u8) -> bool {
+    let (ip, network, bits) = match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => (u32::from(*ip) as u128, u32::from(*network) as u128, 32),
+        (IpAddr::V6(ip), IpAddr::V6(network)) => (u128::from(*ip), u128::from(*network), 128),
+        _ => return false,
+    };
+    let prefix = u32::from(prefix.min(bits));
+    if prefix == 0 {
+        return true;
+    }
+    let shift = bits as u32 - prefix;
+    (ip >> shift) == (network >> shift)
+}
+
+// Client address, taken from X-Forwarded-For only when the peer is a trusted proxy
+pub fn client_ip(req: &ServiceRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    if !is_trusted_proxy(&peer) {
+        return Some(peer);
+    }
+    let forwarded = req.headers().get("x-forwarded-for").and_then(|value| value.to_str().ok());
+    // Walk from the closest hop back and stop at the first address that isn't one of our proxies
+    let client = forwarded
+        .into_iter()
+        .flat_map(|value| value.rsplit(','))
+        .filter_map(|hop| hop.trim().parse::<IpAddr>().ok())
+        .find(|hop| !is_trusted_proxy(hop));
+    Some(client.unwrap_or(peer))
+}
+
+// Returns the rejection response when the request is over budget
+pub fn check(limiter: &RateLimiter, req: &ServiceRequest) -> Option<HttpResponse> {
+    let route = req.match_pattern().unwrap_or_else(|| req.path().to_string());
+    let class = RouteClass::of(&route)?;
+    let config = config::get();
+
+    // Keys were validated by the auth middleware, anything else is limited by address
+    let key = req.extensions().get::<ApiKey>().map(|key| (key.id.clone(), key.rate_factor));
+    let (client, budget) = match key {
+        Some((id, rate_factor)) => (ClientId::ApiKey(id), config.rate_limits.get(class).scaled(rate_factor)),
+        None => (ClientId::Ip(client_ip(req)?), config.rate_limits.get(class)),
+    };
+
+    match limiter.acquire(client, class, budget) {
+        Ok(()) => None,
+        Err(retry_after) => Some(
+            HttpResponse::TooManyRequests()
+                .insert_header((RETRY_AFTER, retry_after.max(1).to_string()))
+                .json(format!("Rate limit exceeded, retry in {} seconds", retry_after.max(1))),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+
+    const BUDGET: Budget = Budget { burst: 3.0, per_second: 0.5 };
+
+    fn client() -> ClientId {
+        ClientId::Ip(IpAddr::from([192, 0, 2, 1]))
+    }
+
+    #[test]
+    fn allows_a_burst_then_asks_to_wait() {
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(limiter.acquire_at(client(), RouteClass::Standard, BUDGET, now), Ok(()));
+        }
+        // One token takes two seconds at half a token per second
+        assert_eq!(limiter.acquire_at(client(), RouteClass::Standard, BUDGET, now), Err(2));
+    }
+
+    #[test]
+    fn refills_over_time_up_to_the_burst() {
+        let limiter = RateLimiter::default();
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire_at(client(), RouteClass::Standard, BUDGET, start).unwrap();
+        }
+        let later = start + Duration::from_secs(2);
+        assert_eq!(limiter.acquire_at(client(), RouteClass::Standard, BUDGET, later), Ok(()));
+        assert_eq!(limiter.acquire_at(client(), RouteClass::Standard, BUDGET, later), Err(2));
+
+        // A long pause refills no more than the burst
+        let much_later = later + Duration::from_secs(3600);
+        for _ in 0..3 {
+            assert_eq!(limiter.acquire_at(client(), RouteClass::Standard, BUDGET, much_later), Ok(()));
+        }
+        assert!(limiter.acquire_at(client(), RouteClass::Standard, BUDGET, much_later).is_err());
+    }
+
+    #[test]
+    fn keeps_separate_buckets_per_client_and_class() {
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+        let single = Budget { burst: 1.0, per_second: 1.0 };
+        assert_eq!(limiter.acquire_at(client(), RouteClass::Standard, single, now), Ok(()));
+        assert_eq!(limiter.acquire_at(client(), RouteClass::Standard, single, now), Err(1));
+        assert_eq!(limiter.acquire_at(client(), RouteClass::Expensive, single, now), Ok(()));
+        assert_eq!(limiter.acquire_at(ClientId::ApiKey("key".to_string()), RouteClass::Standard, single, now), Ok(()));
+    }
+
+    #[test]
+    fn scales_budgets_by_the_key_factor() {
+        let scaled = BUDGET.scaled(4.0);
+        assert_eq!((scaled.burst, scaled.per_second), (12.0, 2.0));
+    }
+
+    #[test]
+    fn classifies_routes() {
+        assert_eq!(RouteClass::of("/health/ready"), None);
+        assert_eq!(RouteClass::of("/metrics"), None);
+        assert_eq!(RouteClass::of("/info/blockdag"), Some(RouteClass::Cheap));
+        assert_eq!(RouteClass::of("/blocks/export"), Some(RouteClass::Expensive));
+        assert_eq!(RouteClass::of("/blocks/{hash}"), Some(RouteClass::Standard));
+    }
+
+    #[test]
+    fn matches_networks_by_prefix() {
+        let ip: IpAddr = "10.1.2.3".parse().unwrap();
+        assert!(in_network(&ip, &"10.0.0.0".parse().unwrap(), 8));
+        assert!(!in_network(&ip, &"10.0.0.0".parse().unwrap(), 16));
+        assert!(in_network(&ip, &"0.0.0.0".parse().unwrap(), 0));
+        assert!(!in_network(&ip, &"::".parse().unwrap(), 0));
+        let ip: IpAddr = "2001:db8::1".parse().unwrap();
+        assert!(in_network(&ip, &"2001:db8::".parse().unwrap(), 32));
+        assert!(!in_network(&ip, &"2001:db9::".parse().unwrap(), 32));
+    }
+}
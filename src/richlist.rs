@@ -0,0 +1,301 @@
+use actix_web::{web, HttpResponse, Responder};
+use kaspa_consensus_core::constants::SOMPI_PER_KASPA;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::amount::{Amount, Unit};
+use crate::db::Db;
+use crate::logging::ErrorBody;
+use crate::utxoset::{self, SnapshotInfo};
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+// Holder counts whose share of the supply is reported
+const TOP_HOLDERS: [usize; 4] = [10, 100, 1000, 10000];
+// Balance bucket bounds in XEN, each bucket ends where the next begins
+const BUCKET_BOUNDS: [u64; 9] = [0, 1, 10, 100, 1_000, 10_000, 100_000, 1_000_000, 10_000_000];
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TopQuery {
+    limit: Option<usize>,
+    offset: Option<usize>,
+    unit: Option<Unit>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DistributionQuery {
+    unit: Option<Unit>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct Holder {
+    rank: usize,
+    address: String,
+    balance: Amount,
+    utxo_count: u64,
+    // Percent of the circulating supply at snapshot time
+    share: f64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct TopResponse {
+    snapshot: SnapshotInfo,
+    holders: Vec<Holder>,
+    limit: usize,
+    offset: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+struct BalanceBucket {
+    min: Amount,
+    // Missing for the open ended top bucket
+    max: Option<Amount>,
+    addresses: u64,
+    total: Amount,
+}
+
+#[derive(Serialize, ToSchema)]
+struct TopShare {
+    holders: usize,
+    total: Amount,
+    // Percent of the circulating supply at snapshot time
+    share: f64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct DistributionResponse {
+    snapshot: SnapshotInfo,
+    addresses: u64,
+    // Sum of all indexed balances, below the circulating supply for addresses the index hasn't seen
+    indexed_total: Amount,
+    circulating: Amount,
+    buckets: Vec<BalanceBucket>,
+    top: Vec<TopShare>,
+}
+
+// Balance buckets as (min, max, addresses, total), top holder totals and the overall count and
+// total, all in sompi
+struct Distribution {
+    buckets: Vec<(u64, Option<u64>, u64, u64)>,
+    top: Vec<(usize, u64)>,
+    addresses: u64,
+    total: u64,
+}
+
+fn share(sompi: u64, circulating: u64) -> f64 {
+    if circulating == 0 {
+        return 0.0;
+    }
+    sompi as f64 / circulating as f64 * 100.0
+}
+
+// Snapshot metadata, or 503 while the first snapshot is still being taken
+async fn snapshot(db: &Db) -> Result<SnapshotInfo, HttpResponse> {
+    match utxoset::snapshot_info(db).await {
+        Ok(Some(info)) => Ok(info),
+        Ok(None) => Err(HttpResponse::ServiceUnavailable().json("UTXO snapshot has not been taken yet")),
+        Err(err) => Err(HttpResponse::InternalServerError().json(format!("Failed to read UTXO snapshot: {:?}", err))),
+    }
+}
+
+// Address, balance and UTXO count of the largest balances
+async fn top_balances(db: &Db, limit: usize, offset: usize) -> anyhow::Result<Vec<(String, u64, u64)>> {
+    db.call(move |conn| {
+        let mut stmt = conn.prepare(
+            "SELECT address, balance, utxo_count FROM balances ORDER BY balance DESC, address LIMIT ?1 OFFSET ?2",
+        )?;
+        let rows = stmt.query_map(params![limit as i64, offset as i64], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64, row.get::<_, i64>(2)? as u64))
+        })?;
+        rows.collect()
+    })
+        .await
+}
+
+async fn distribution(db: &Db) -> anyhow::Result<Distribution> {
+    db.call(|conn| {
+        let mut buckets = Vec::with_capacity(BUCKET_BOUNDS.len());
+        for (i, min) in BUCKET_BOUNDS.iter().enumerate() {
+            let min = min * SOMPI_PER_KASPA;
+            let max = BUCKET_BOUNDS.get(i + 1).map(|max| max * SOMPI_PER_KASPA);
+            let (addresses, total) = conn.query_row(
+                "SELECT COUNT(*), COALESCE(SUM(balance), 0) FROM balances WHERE balance >= ?1 AND balance < ?2",
+                params![min as i64, max.map_or(i64::MAX, |max| max as i64)],
+                |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64)),
+            )?;
+            buckets.push((min, max, addresses, total));
+        }
+
+        let mut top = Vec::with_capacity(TOP_HOLDERS.len());
+        for holders in TOP_HOLDERS {
+            let total = conn.query_row(
+                "SELECT COALESCE(SUM(balance), 0) FROM (SELECT balance FROM balances ORDER BY balance DESC LIMIT ?1)",
+                params![holders as i64],
+                |row| row.get::<_, i64>(0).map(|total| total as u64),
+            )?;
+            top.push((holders, total));
+        }
+
+        let (addresses, total) = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(balance), 0) FROM balances",
+            [],
+            |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64)),
+        )?;
+        Ok(Distribution { buckets, top, addresses, total })
+    })
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/addresses/top",
+    tag = "addresses",
+    params(TopQuery),
+    responses(
+        (status = 200, description = "Addresses with the largest balances", body = TopResponse),
+        (status = 503, description = "No UTXO snapshot yet", body = ErrorBody),
+    )
+)]
+pub async fn get_top(query: web::Query<TopQuery>, db: web::Data<Db>) -> impl Responder {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+    let unit = query.unit.unwrap_or_default();
+    let snapshot = match snapshot(&db).await {
+        Ok(snapshot) => snapshot,
+        Err(err) => return err,
+    };
+
+    let rows = match top_balances(&db, limit, offset).await {
+        Ok(rows) => rows,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to read balances: {:?}", err)),
+    };
+
+    let holders = rows
+        .into_iter()
+        .enumerate()
+        .map(|(i, (address, balance, utxo_count))| Holder {
+            rank: offset + i + 1,
+            address,
+            balance: Amount::new(balance, unit),
+            utxo_count,
+            share: share(balance, snapshot.circulating_sompi),
+        })
+        .collect();
+    HttpResponse::Ok().json(TopResponse { snapshot, holders, limit, offset })
+}
+
+#[utoipa::path(
+    get,
+    path = "/stats/distribution",
+    tag = "stats",
+    params(DistributionQuery),
+    responses(
+        (status = 200, description = "Addresses per balance bucket and supply held by the largest holders", body = DistributionResponse),
+        (status = 503, description = "No UTXO snapshot yet", body = ErrorBody),
+    )
+)]
+pub async fn get_distribution(query: web::Query<DistributionQuery>, db: web::Data<Db>) -> impl Responder {
+    let unit = query.unit.unwrap_or_default();
+    let snapshot = match snapshot(&db).await {
+        Ok(snapshot) => snapshot,
+        Err(err) => return err,
+    };
+
+    let Distribution { buckets, top, addresses, total } = match distribution(&db).await {
+        Ok(distribution) => distribution,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to read balances: {:?}", err)),
+    };
+
+    let circulating = snapshot.circulating_sompi;
+    HttpResponse::Ok().json(DistributionResponse {
+        addresses,
+        indexed_total: Amount::new(total, unit),
+        circulating: Amount::new(circulating, unit),
+        buckets: buckets
+            .into_iter()
+            .map(|(min, max, addresses, total)| BalanceBucket {
+                min: Amount::new(min, unit),
+                max: max.map(|max| Amount::new(max, unit)),
+                addresses,
+                total: Amount::new(total, unit),
+            })
+            .collect(),
+        top: top
+            .into_iter()
+            .map(|(holders, total)| TopShare { holders, total: Amount::new(total, unit), share: share(total, circulating) })
+            .collect(),
+        snapshot,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XEN: u64 = SOMPI_PER_KASPA;
+
+    async fn insert_balances(db: &Db, balances: Vec<u64>) {
+        db.call(move |conn| {
+            let tx = conn.transaction()?;
+            for (i, balance) in balances.iter().enumerate() {
+                tx.execute(
+                    "INSERT INTO balances (address, balance, utxo_count) VALUES (?1, ?2, 1)",
+                    params![format!("xenom:{:04}", i), *balance as i64],
+                )?;
+            }
+            tx.commit()
+        })
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn shares_are_percent_of_the_circulating_supply() {
+        assert_eq!(share(25, 100), 25.0);
+        assert_eq!(share(100, 100), 100.0);
+        assert_eq!(share(1, 0), 0.0);
+    }
+
+    #[tokio::test]
+    async fn buckets_include_their_minimum_and_exclude_their_maximum() {
+        let db = Db::open(":memory:").unwrap();
+        insert_balances(&db, vec![0, XEN - 1, XEN, 10 * XEN - 1, 10 * XEN, 10_000_000 * XEN, 50_000_000 * XEN]).await;
+
+        let distribution = distribution(&db).await.unwrap();
+        let buckets = &distribution.buckets;
+        assert_eq!(buckets.len(), BUCKET_BOUNDS.len());
+        assert_eq!(buckets[0], (0, Some(XEN), 2, XEN - 1));
+        assert_eq!(buckets[1], (XEN, Some(10 * XEN), 2, 11 * XEN - 1));
+        assert_eq!(buckets[2], (10 * XEN, Some(100 * XEN), 1, 10 * XEN));
+        // The last bucket is open ended
+        assert_eq!(buckets[8], (10_000_000 * XEN, None, 2, 60_000_000 * XEN));
+        assert_eq!(buckets.iter().map(|bucket| bucket.2).sum::<u64>(), distribution.addresses);
+        assert_eq!(buckets.iter().map(|bucket| bucket.3).sum::<u64>(), distribution.total);
+    }
+
+    #[tokio::test]
+    async fn top_holder_totals_sum_the_largest_balances() {
+        let db = Db::open(":memory:").unwrap();
+        insert_balances(&db, (1..=20).map(|xen| xen * XEN).collect()).await;
+
+        let distribution = distribution(&db).await.unwrap();
+        assert_eq!(distribution.top[0], (10, (11..=20).sum::<u64>() * XEN));
+        // Fewer addresses than holders counts all of them
+        assert_eq!(distribution.top[1], (100, 210 * XEN));
+        assert_eq!(distribution.addresses, 20);
+        assert_eq!(distribution.total, 210 * XEN);
+    }
+
+    #[tokio::test]
+    async fn top_balances_page_by_rank() {
+        let db = Db::open(":memory:").unwrap();
+        insert_balances(&db, vec![5, 30, 20, 30]).await;
+
+        let first = top_balances(&db, 2, 0).await.unwrap();
+        assert_eq!(first, [("xenom:0001".to_string(), 30, 1), ("xenom:0003".to_string(), 30, 1)]);
+        let second = top_balances(&db, 2, 2).await.unwrap();
+        assert_eq!(second.iter().map(|(_, balance, _)| *balance).collect::<Vec<_>>(), [20, 5]);
+    }
+}
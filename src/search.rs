@@ -0,0 +1,236 @@
+use actix_web::{web, HttpResponse, Responder};
+use kaspa_rpc_core::api::rpc::RpcApi;
+use kaspa_rpc_core::{RpcAddress, RpcHash};
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::db::Db;
+use crate::logging::ErrorBody;
+use crate::metrics;
+
+// Blocks returned for a blue score shared by several blocks
+const MAX_BLUE_SCORE_BLOCKS: usize = 10;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    // Block hash, transaction id, address, DAA score or blue score
+    q: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum EntityKind {
+    Block,
+    Transaction,
+    Address,
+    DaaScore,
+    BlueScore,
+}
+
+// What a query looks like, before anything is looked up
+#[derive(Debug, PartialEq)]
+enum Query {
+    Address(RpcAddress),
+    Hash(RpcHash),
+    // DAA or blue score
+    Score(u64),
+}
+
+impl Query {
+    fn classify(q: &str) -> Option<Query> {
+        if let Ok(address) = RpcAddress::try_from(q) {
+            return Some(Query::Address(address));
+        }
+        // Checked before scores, a hash can be all decimal digits
+        if q.len() == 64 {
+            if let Ok(hash) = q.parse::<RpcHash>() {
+                return Some(Query::Hash(hash));
+            }
+        }
+        q.parse().ok().map(Query::Score)
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct SearchResult {
+    kind: EntityKind,
+    // Normalized form of the query, e.g. the lowercase hash
+    id: String,
+    // Resource to fetch the entity from
+    url: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SearchResponse {
+    query: String,
+    results: Vec<SearchResult>,
+}
+
+// Finds a hash among the indexed blocks and transactions, without asking the node
+async fn find_indexed_hash(db: &Db, hash: String) -> anyhow::Result<Option<EntityKind>> {
+    db.call(move |conn| {
+        let block = conn
+            .query_row("SELECT 1 FROM blocks WHERE hash = ?1", params![hash], |_| Ok(()))
+            .optional()?;
+        if block.is_some() {
+            return Ok(Some(EntityKind::Block));
+        }
+        let transaction = conn
+            .query_row("SELECT 1 FROM transactions WHERE transaction_id = ?1", params![hash], |_| Ok(()))
+            .optional()?;
+        Ok(transaction.map(|_| EntityKind::Transaction))
+    })
+        .await
+}
+
+// Asks the node for a block, then a mempool transaction, with the hash
+async fn find_live_hash(hash: RpcHash) -> Result<Option<EntityKind>, HttpResponse> {
+    let client = crate::get_client().await?;
+    let kind = if metrics::rpc("get_block", client.get_block(hash, false)).await.is_ok() {
+        Some(EntityKind::Block)
+    } else if metrics::rpc("get_mempool_entry", client.get_mempool_entry(hash, true, false)).await.is_ok() {
+        Some(EntityKind::Transaction)
+    } else {
+        None
+    };
+    if let Err(disconnect_err) = client.disconnect().await {
+        return Err(HttpResponse::InternalServerError().json(format!("Failed to disconnect: {}", disconnect_err)));
+    }
+    Ok(kind)
+}
+
+// Whether an indexed block has the DAA score, and the blocks with the blue score
+async fn find_scores(db: &Db, score: u64) -> anyhow::Result<(bool, Vec<String>)> {
+    db.call(move |conn| {
+        let daa_score = conn
+            .query_row("SELECT 1 FROM blocks WHERE daa_score = ?1 LIMIT 1", params![score as i64], |_| Ok(()))
+            .optional()?
+            .is_some();
+        let mut stmt = conn.prepare("SELECT hash FROM blocks WHERE blue_score = ?1 ORDER BY hash LIMIT ?2")?;
+        let rows = stmt.query_map(params![score as i64, MAX_BLUE_SCORE_BLOCKS as i64], |row| row.get(0))?;
+        Ok((daa_score, rows.collect::<rusqlite::Result<Vec<_>>>()?))
+    })
+        .await
+}
+
+// Link to a block or transaction found by its hash
+fn hash_result(kind: EntityKind, id: String) -> Option<SearchResult> {
+    let url = match kind {
+        EntityKind::Block => format!("/v1/blocks/{}", id),
+        EntityKind::Transaction => format!("/v1/transactions/{}", id),
+        // Hash lookups find nothing else
+        EntityKind::Address | EntityKind::DaaScore | EntityKind::BlueScore => return None,
+    };
+    Some(SearchResult { kind, id, url })
+}
+
+#[utoipa::path(
+    get,
+    path = "/search",
+    tag = "search",
+    params(SearchQuery),
+    responses(
+        (status = 200, description = "Entities matching the query with their resource URLs", body = SearchResponse),
+        (status = 400, description = "Empty query", body = ErrorBody),
+        (status = 404, description = "Nothing matches the query", body = ErrorBody),
+        (status = 500, description = "Index or node request failed", body = ErrorBody),
+    )
+)]
+pub async fn search(query: web::Query<SearchQuery>, db: web::Data<Db>) -> impl Responder {
+    let q = query.q.trim();
+    if q.is_empty() {
+        return HttpResponse::BadRequest().json("Empty query");
+    }
+
+    let mut results = Vec::new();
+    match Query::classify(q) {
+        Some(Query::Address(address)) => {
+            let id = address.to_string();
+            results.push(SearchResult { kind: EntityKind::Address, url: format!("/addresses/{}", id), id });
+        }
+        Some(Query::Score(score)) => {
+            let (daa_score, blue_score_blocks) = match find_scores(&db, score).await {
+                Ok(scores) => scores,
+                Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to query block index: {:?}", err)),
+            };
+            if daa_score {
+                results.push(SearchResult {
+                    kind: EntityKind::DaaScore,
+                    id: score.to_string(),
+                    url: format!("/blocks?lowDaa={0}&highDaa={0}", score),
+                });
+            }
+            results.extend(blue_score_blocks.into_iter().map(|hash| SearchResult {
+                kind: EntityKind::BlueScore,
+                id: score.to_string(),
+                url: format!("/v1/blocks/{}", hash),
+            }));
+        }
+        Some(Query::Hash(hash)) => {
+            let id = hash.to_string();
+            let kind = match find_indexed_hash(&db, id.clone()).await {
+                Ok(Some(kind)) => Some(kind),
+                Ok(None) => match find_live_hash(hash).await {
+                    Ok(kind) => kind,
+                    Err(err) => return err,
+                },
+                Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to query block index: {:?}", err)),
+            };
+            results.extend(kind.and_then(|kind| hash_result(kind, id)));
+        }
+        None => {}
+    }
+
+    if results.is_empty() {
+        return HttpResponse::NotFound().json(format!("Nothing found for {}", q));
+    }
+    HttpResponse::Ok().json(SearchResponse { query: q.to_string(), results })
+}
+
+#[cfg(test)]
+mod tests {
+    use kaspa_addresses::{Prefix, Version};
+    use super::*;
+
+    #[test]
+    fn classifies_addresses_of_any_network() {
+        for prefix in [Prefix::Mainnet, Prefix::Testnet] {
+            let address = RpcAddress::new(prefix, Version::PubKey, &[7; 32]);
+            assert_eq!(Query::classify(&address.to_string()), Some(Query::Address(address)));
+        }
+    }
+
+    #[test]
+    fn classifies_hashes() {
+        let hash = "b".repeat(64);
+        assert_eq!(Query::classify(&hash), Some(Query::Hash(hash.parse().unwrap())));
+        // All digits, yet a hash rather than a score
+        let digits = format!("{:064}", 42);
+        assert_eq!(Query::classify(&digits), Some(Query::Hash(digits.parse().unwrap())));
+    }
+
+    #[test]
+    fn classifies_scores() {
+        assert_eq!(Query::classify("0"), Some(Query::Score(0)));
+        assert_eq!(Query::classify("123456"), Some(Query::Score(123_456)));
+        assert_eq!(Query::classify(&u64::MAX.to_string()), Some(Query::Score(u64::MAX)));
+    }
+
+    #[test]
+    fn leaves_other_queries_unclassified() {
+        for q in ["", "-1", "18446744073709551616", "xyz", "b".repeat(63).as_str(), "g".repeat(64).as_str()] {
+            assert_eq!(Query::classify(q), None, "{}", q);
+        }
+    }
+
+    #[test]
+    fn links_hash_hits_to_the_v1_routes() {
+        let id = "b".repeat(64);
+        let block = hash_result(EntityKind::Block, id.clone()).unwrap();
+        assert_eq!(block.url, format!("/v1/blocks/{}", id));
+        let transaction = hash_result(EntityKind::Transaction, id.clone()).unwrap();
+        assert_eq!(transaction.url, format!("/v1/transactions/{}", id));
+        assert!(hash_result(EntityKind::Address, id).is_none());
+    }
+}
@@ -0,0 +1,343 @@
+use actix_web::{web, HttpResponse, Responder};
+use chrono::Utc;
+use kaspa_rpc_core::api::rpc::RpcApi;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use tokio::time::{sleep, Duration};
+use tracing::error;
+use crate::amount::{Amount, Unit};
+use crate::blocks::parse_time;
+use crate::db::Db;
+use crate::logging::ErrorBody;
+use crate::metrics;
+
+// Series recorded by the collector
+pub const METRICS: &[&str] = &[
+    "block_count",
+    "header_count",
+    "difficulty",
+    "virtual_daa_score",
+    "circulating_supply",
+    "hashrate",
+    "mempool_size",
+    "block_rate",
+    // Recorded by the UTXO summary after every snapshot
+    "utxo_count",
+    "utxo_value",
+    "utxo_coinbase_count",
+    "utxo_dust_count",
+    "utxo_mean_age",
+];
+// Series of amounts, stored as exact sompi and bucketed as amounts
+const AMOUNT_METRICS: &[&str] = &["circulating_supply", "utxo_value"];
+
+// Window (in blocks) used for the hashrate estimate of each sample
+const HASHRATE_WINDOW: u32 = 1000;
+const DEFAULT_RANGE_MS: u64 = 24 * 60 * 60 * 1000;
+const DEFAULT_INTERVAL_SECS: u64 = 60 * 60;
+// Upper bound of buckets returned for one series
+const MAX_BUCKETS: u64 = 2000;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SeriesQuery {
+    from: Option<String>,
+    to: Option<String>,
+    // Bucket width in seconds
+    interval: Option<u64>,
+    // Unit of amount series: sompi, xen or both (default)
+    unit: Option<Unit>,
+}
+
+// One sampled value; amounts are kept as sompi so large supplies don't lose precision to f64
+#[derive(Debug, Clone, Copy)]
+pub enum Sample {
+    Value(f64),
+    Sompi(u64),
+}
+
+#[derive(Serialize, ToSchema)]
+struct Bucket {
+    timestamp: u64,
+    avg: f64,
+    min: f64,
+    max: f64,
+    samples: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct AmountBucket {
+    timestamp: u64,
+    // Rounded down to whole sompi
+    avg: Amount,
+    min: Amount,
+    max: Amount,
+    samples: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+enum Buckets {
+    Values(Vec<Bucket>),
+    Amounts(Vec<AmountBucket>),
+}
+
+#[derive(Serialize, ToSchema)]
+struct SeriesResponse {
+    metric: String,
+    from: u64,
+    to: u64,
+    interval: u64,
+    buckets: Buckets,
+}
+
+pub async fn store_samples(db: &Db, timestamp: u64, samples: Vec<(&'static str, Sample)>) -> anyhow::Result<()> {
+    db.call(move |conn| {
+        let tx = conn.transaction()?;
+        {
+            let mut value = tx.prepare("INSERT OR REPLACE INTO samples (metric, timestamp, value) VALUES (?1, ?2, ?3)")?;
+            let mut sompi =
+                tx.prepare("INSERT OR REPLACE INTO amount_samples (metric, timestamp, sompi) VALUES (?1, ?2, ?3)")?;
+            for (metric, sample) in &samples {
+                match *sample {
+                    Sample::Value(sample) => value.execute(params![metric, timestamp as i64, sample])?,
+                    Sample::Sompi(sample) => {
+                        sompi.execute(params![metric, timestamp as i64, sample.min(i64::MAX as u64) as i64])?
+                    }
+                };
+            }
+        }
+        tx.commit()
+    })
+        .await
+}
+
+fn value_buckets(conn: &Connection, metric: &str, bucket_ms: i64, from: u64, to: u64) -> rusqlite::Result<Vec<Bucket>> {
+    let mut stmt = conn.prepare(
+        "SELECT (timestamp / ?1) * ?1 AS bucket, AVG(value), MIN(value), MAX(value), COUNT(*)
+         FROM samples WHERE metric = ?2 AND timestamp BETWEEN ?3 AND ?4
+         GROUP BY bucket ORDER BY bucket",
+    )?;
+    let rows = stmt.query_map(params![bucket_ms, metric, from as i64, to as i64], |row| {
+        Ok(Bucket {
+            timestamp: row.get::<_, i64>(0)? as u64,
+            avg: row.get(1)?,
+            min: row.get(2)?,
+            max: row.get(3)?,
+            samples: row.get::<_, i64>(4)? as u64,
+        })
+    })?;
+    rows.collect()
+}
+
+// The average is taken over the distance from the bucket minimum so it stays an exact integer
+// without summing whole supplies
+fn amount_buckets(
+    conn: &Connection,
+    metric: &str,
+    bucket_ms: i64,
+    from: u64,
+    to: u64,
+    unit: Unit,
+) -> rusqlite::Result<Vec<AmountBucket>> {
+    let mut stmt = conn.prepare(
+        "WITH bucketed AS (
+             SELECT (timestamp / ?1) * ?1 AS bucket, sompi FROM amount_samples
+             WHERE metric = ?2 AND timestamp BETWEEN ?3 AND ?4
+         ), lows AS (
+             SELECT bucket, MIN(sompi) AS low FROM bucketed GROUP BY bucket
+         )
+         SELECT bucket, low + SUM(sompi - low) / COUNT(*), low, MAX(sompi), COUNT(*)
+         FROM bucketed JOIN lows USING (bucket)
+         GROUP BY bucket ORDER BY bucket",
+    )?;
+    let rows = stmt.query_map(params![bucket_ms, metric, from as i64, to as i64], |row| {
+        Ok(AmountBucket {
+            timestamp: row.get::<_, i64>(0)? as u64,
+            avg: Amount::new(row.get::<_, i64>(1)? as u64, unit),
+            min: Amount::new(row.get::<_, i64>(2)? as u64, unit),
+            max: Amount::new(row.get::<_, i64>(3)? as u64, unit),
+            samples: row.get::<_, i64>(4)? as u64,
+        })
+    })?;
+    rows.collect()
+}
+
+// Background task sampling network statistics into the database
+pub async fn run(db: Db, interval: Duration) {
+    let mut previous: Option<(u64, u64)> = None;
+    loop {
+        match collect(previous).await {
+            Ok((timestamp, block_count, samples)) => {
+                previous = Some((timestamp, block_count));
+                if let Err(err) = store_samples(&db, timestamp, samples).await {
+                    error!(error = ?err, "Failed to store statistics");
+                }
+            }
+            Err(err) => error!(error = ?err, "Statistics collector failed"),
+        }
+        sleep(interval).await;
+    }
+}
+
+// Takes one sample of every metric; `previous` is the timestamp and block count of the last sample
+async fn collect(previous: Option<(u64, u64)>) -> anyhow::Result<(u64, u64, Vec<(&'static str, Sample)>)> {
+    let client = crate::connect_node().await?;
+
+    let block_dag_info = metrics::rpc("get_block_dag_info", client.get_block_dag_info()).await?;
+    let coin_supply = metrics::rpc("get_coin_supply", client.get_coin_supply()).await?;
+    let hashrate = metrics::rpc("estimate_network_hashes_per_second", client.estimate_network_hashes_per_second(HASHRATE_WINDOW, None)).await?;
+    let info = metrics::rpc("get_info", client.get_info()).await?;
+    client.disconnect().await?;
+
+    let timestamp = Utc::now().timestamp_millis() as u64;
+    let mut samples = vec![
+        ("block_count", Sample::Value(block_dag_info.block_count as f64)),
+        ("header_count", Sample::Value(block_dag_info.header_count as f64)),
+        ("difficulty", Sample::Value(block_dag_info.difficulty)),
+        ("virtual_daa_score", Sample::Value(block_dag_info.virtual_daa_score as f64)),
+        ("circulating_supply", Sample::Sompi(coin_supply.circulating_sompi)),
+        ("hashrate", Sample::Value(hashrate as f64)),
+        ("mempool_size", Sample::Value(info.mempool_size as f64)),
+    ];
+
+    // Block rate is derived from the block count growth since the previous sample
+    if let Some((previous_timestamp, previous_block_count)) = previous {
+        let elapsed_secs = timestamp.saturating_sub(previous_timestamp) as f64 / 1000.0;
+        if elapsed_secs > 0.0 && block_dag_info.block_count >= previous_block_count {
+            let rate = (block_dag_info.block_count - previous_block_count) as f64 / elapsed_secs;
+            samples.push(("block_rate", Sample::Value(rate)));
+        }
+    }
+
+    Ok((timestamp, block_dag_info.block_count, samples))
+}
+
+// Bucket width in seconds and in milliseconds, widened when the requested resolution would return
+// too many buckets; None when the width doesn't fit an SQLite integer
+pub fn bucket_width(from: u64, to: u64, interval: u64) -> Option<(u64, i64)> {
+    let min_interval = ((to - from) / 1000 / MAX_BUCKETS).max(1);
+    let interval = interval.max(min_interval);
+    let bucket_ms = interval.checked_mul(1000).and_then(|ms| i64::try_from(ms).ok())?;
+    Some((interval, bucket_ms))
+}
+
+#[utoipa::path(
+    get,
+    path = "/stats/{metric}",
+    tag = "stats",
+    params(("metric" = String, Path, description = "One of the collected metrics, e.g. hashrate"), SeriesQuery),
+    responses(
+        (status = 200, description = "Bucketed samples of the metric", body = SeriesResponse),
+        (status = 400, description = "Invalid range", body = ErrorBody),
+        (status = 404, description = "Unknown metric", body = ErrorBody),
+    )
+)]
+pub async fn get_series(path: web::Path<String>, query: web::Query<SeriesQuery>, db: web::Data<Db>) -> impl Responder {
+    let metric = path.into_inner();
+    if !METRICS.contains(&metric.as_str()) {
+        return HttpResponse::NotFound().json(format!("Unknown metric {}, available: {}", metric, METRICS.join(", ")));
+    }
+
+    let to = match query.to.as_deref() {
+        // SQLite integers are signed, later times are clamped to i64::MAX
+        Some(to) => match parse_time(to) {
+            Some(to) => to.min(i64::MAX as u64),
+            None => return HttpResponse::BadRequest().json("Invalid to time"),
+        },
+        None => Utc::now().timestamp_millis() as u64,
+    };
+    let from = match query.from.as_deref() {
+        Some(from) => match parse_time(from) {
+            Some(from) => from,
+            None => return HttpResponse::BadRequest().json("Invalid from time"),
+        },
+        None => to.saturating_sub(DEFAULT_RANGE_MS),
+    };
+    if from > to {
+        return HttpResponse::BadRequest().json("from must not be after to");
+    }
+
+    let (interval, bucket_ms) = match bucket_width(from, to, query.interval.unwrap_or(DEFAULT_INTERVAL_SECS)) {
+        Some(width) => width,
+        None => return HttpResponse::BadRequest().json("interval is too large"),
+    };
+
+    let name = metric.clone();
+    let unit = query.unit.unwrap_or_default();
+    let buckets = db
+        .call(move |conn| {
+            if AMOUNT_METRICS.contains(&name.as_str()) {
+                amount_buckets(conn, &name, bucket_ms, from, to, unit).map(Buckets::Amounts)
+            } else {
+                value_buckets(conn, &name, bucket_ms, from, to).map(Buckets::Values)
+            }
+        })
+        .await;
+
+    match buckets {
+        Ok(buckets) => HttpResponse::Ok().json(SeriesResponse { metric, from, to, interval, buckets }),
+        Err(err) => HttpResponse::InternalServerError().json(format!("Failed to query statistics: {:?}", err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_requested_interval_within_the_bucket_limit() {
+        let day = 24 * 60 * 60 * 1000;
+        assert_eq!(bucket_width(0, day, 3600), Some((3600, 3_600_000)));
+        assert_eq!(bucket_width(0, 0, 0), Some((1, 1000)));
+    }
+
+    #[test]
+    fn widens_the_interval_for_long_ranges() {
+        // A year at one minute would be over 500k buckets
+        let year = 365 * 24 * 60 * 60 * 1000;
+        let (interval, bucket_ms) = bucket_width(0, year, 60).unwrap();
+        assert_eq!(interval, 365 * 24 * 60 * 60 / MAX_BUCKETS);
+        assert_eq!(bucket_ms, interval as i64 * 1000);
+        assert!(year / bucket_ms as u64 <= MAX_BUCKETS + 1);
+    }
+
+    #[test]
+    fn rejects_intervals_overflowing_milliseconds() {
+        assert_eq!(bucket_width(0, 1000, 2305843009213693952), None);
+        assert_eq!(bucket_width(0, 1000, u64::MAX), None);
+        assert_eq!(bucket_width(0, 1000, i64::MAX as u64 / 1000 + 1), None);
+        assert!(bucket_width(0, 1000, i64::MAX as u64 / 1000).is_some());
+    }
+
+    #[tokio::test]
+    async fn amount_series_stay_exact_sompi() {
+        let db = Db::open(":memory:").unwrap();
+        // Above 2^53, where an f64 can no longer hold every sompi
+        let supply = 290_000_000_000_000_001;
+        store_samples(&db, 1_000, vec![("circulating_supply", Sample::Sompi(supply)), ("hashrate", Sample::Value(2.5))])
+            .await
+            .unwrap();
+        store_samples(&db, 2_000, vec![("circulating_supply", Sample::Sompi(supply + 2))]).await.unwrap();
+        store_samples(&db, 5_000, vec![("circulating_supply", Sample::Sompi(supply + 10))]).await.unwrap();
+
+        let buckets = db
+            .call(|conn| amount_buckets(conn, "circulating_supply", 4_000, 0, 10_000, Unit::Sompi))
+            .await
+            .unwrap();
+        let sompi = |amount: &Amount| amount.sompi.clone().unwrap();
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0].timestamp, 0);
+        assert_eq!(sompi(&buckets[0].min), supply.to_string());
+        assert_eq!(sompi(&buckets[0].avg), (supply + 1).to_string());
+        assert_eq!(sompi(&buckets[0].max), (supply + 2).to_string());
+        assert_eq!(buckets[0].samples, 2);
+        assert_eq!(buckets[1].timestamp, 4_000);
+        assert_eq!(sompi(&buckets[1].avg), (supply + 10).to_string());
+
+        let values = db.call(|conn| value_buckets(conn, "hashrate", 4_000, 0, 10_000)).await.unwrap();
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0].avg, 2.5);
+    }
+}
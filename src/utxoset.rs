@@ -0,0 +1,222 @@
+use chrono::Utc;
+use kaspa_rpc_core::api::rpc::RpcApi;
+use kaspa_rpc_core::RpcAddress;
+use kaspa_wrpc_client::KaspaRpcClient;
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+use tokio::time::{sleep, Duration};
+use tracing::{error, info};
+use utoipa::ToSchema;
+use crate::db::Db;
+use crate::metrics;
+use crate::utxostats;
+
+// Addresses per get_utxos_by_addresses call
+const ADDRESS_BATCH: usize = 500;
+const SNAPSHOT_AT_KEY: &str = "utxo_snapshot_at";
+const SNAPSHOT_DAA_SCORE_KEY: &str = "utxo_snapshot_daa_score";
+const SNAPSHOT_CIRCULATING_KEY: &str = "utxo_snapshot_circulating";
+
+// When the current snapshot was taken and what it is measured against
+#[derive(Serialize, ToSchema)]
+pub struct SnapshotInfo {
+    // Unix milliseconds
+    pub timestamp: u64,
+    pub virtual_daa_score: u64,
+    // Circulating supply in sompi reported by the node at snapshot time
+    pub circulating_sompi: u64,
+}
+
+struct Utxo {
+    transaction_id: String,
+    index: u32,
+    address: String,
+    amount: u64,
+    block_daa_score: u64,
+    is_coinbase: bool,
+}
+
+pub async fn snapshot_info(db: &Db) -> anyhow::Result<Option<SnapshotInfo>> {
+    db.call(|conn| {
+        let value = |key: &str| -> rusqlite::Result<Option<u64>> {
+            conn.query_row("SELECT value FROM index_state WHERE key = ?1", params![key], |row| row.get::<_, String>(0))
+                .optional()
+                .map(|value| value.and_then(|value| value.parse().ok()))
+        };
+        let (Some(timestamp), Some(virtual_daa_score), Some(circulating_sompi)) =
+            (value(SNAPSHOT_AT_KEY)?, value(SNAPSHOT_DAA_SCORE_KEY)?, value(SNAPSHOT_CIRCULATING_KEY)?)
+        else {
+            return Ok(None);
+        };
+        Ok(Some(SnapshotInfo { timestamp, virtual_daa_score, circulating_sompi }))
+    })
+        .await
+}
+
+async fn known_addresses(db: &Db) -> anyhow::Result<Vec<String>> {
+    db.call(|conn| {
+        let mut stmt = conn.prepare("SELECT address FROM addresses")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect()
+    })
+        .await
+}
+
+async fn clear_staged(db: &Db) -> anyhow::Result<()> {
+    db.call(|conn| conn.execute("DELETE FROM utxos_next", []).map(|_| ())).await
+}
+
+// Writes one batch of the snapshot being taken, so only a batch is ever held in memory
+async fn stage(db: &Db, utxos: Vec<Utxo>) -> anyhow::Result<()> {
+    db.call(move |conn| {
+        let tx = conn.transaction()?;
+        {
+            let mut insert = tx.prepare(
+                "INSERT OR REPLACE INTO utxos_next (transaction_id, output_index, address, amount, block_daa_score, is_coinbase)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for utxo in &utxos {
+                insert.execute(params![
+                    utxo.transaction_id,
+                    utxo.index,
+                    utxo.address,
+                    utxo.amount as i64,
+                    utxo.block_daa_score as i64,
+                    utxo.is_coinbase
+                ])?;
+            }
+        }
+        tx.commit()
+    })
+        .await
+}
+
+// Replaces the current snapshot with the staged one, readers see either one as a whole
+async fn publish(db: &Db, info: SnapshotInfo) -> anyhow::Result<()> {
+    db.call(move |conn| {
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM utxos", [])?;
+        tx.execute(
+            "INSERT INTO utxos (transaction_id, output_index, address, amount, block_daa_score, is_coinbase)
+             SELECT transaction_id, output_index, address, amount, block_daa_score, is_coinbase FROM utxos_next",
+            [],
+        )?;
+        tx.execute("DELETE FROM utxos_next", [])?;
+        tx.execute("DELETE FROM balances", [])?;
+        tx.execute(
+            "INSERT INTO balances (address, balance, utxo_count)
+             SELECT address, SUM(amount), COUNT(*) FROM utxos GROUP BY address",
+            [],
+        )?;
+        for (key, value) in [
+            (SNAPSHOT_AT_KEY, info.timestamp),
+            (SNAPSHOT_DAA_SCORE_KEY, info.virtual_daa_score),
+            (SNAPSHOT_CIRCULATING_KEY, info.circulating_sompi),
+        ] {
+            tx.execute(
+                "INSERT OR REPLACE INTO index_state (key, value) VALUES (?1, ?2)",
+                params![key, value.to_string()],
+            )?;
+        }
+        tx.commit()
+    })
+        .await
+}
+
+// Background task refreshing the UTXO snapshot of every address the block index has seen
+pub async fn run(db: Db, interval: Duration) {
+    loop {
+        if let Err(err) = refresh(&db).await {
+            error!(error = ?err, "UTXO snapshot failed");
+        }
+        sleep(interval).await;
+    }
+}
+
+async fn refresh(db: &Db) -> anyhow::Result<()> {
+    let addresses: Vec<RpcAddress> =
+        known_addresses(db).await?.iter().filter_map(|address| RpcAddress::try_from(address.as_str()).ok()).collect();
+    let client = crate::connect_node().await?;
+    let result = take_snapshot(db, &client, &addresses).await;
+    let _ = client.disconnect().await;
+    let (utxos, info) = result?;
+
+    info!(addresses = addresses.len(), utxos, "Refreshed UTXO snapshot");
+    let (timestamp, virtual_daa_score) = (info.timestamp, info.virtual_daa_score);
+    publish(db, info).await?;
+    utxostats::summarize(db, timestamp, virtual_daa_score).await
+}
+
+// Stages the UTXOs of every address batch, returning how many there were and what they are measured against
+async fn take_snapshot(db: &Db, client: &KaspaRpcClient, addresses: &[RpcAddress]) -> anyhow::Result<(usize, SnapshotInfo)> {
+    clear_staged(db).await?;
+    let mut count = 0;
+    for batch in addresses.chunks(ADDRESS_BATCH) {
+        let entries = metrics::rpc("get_utxos_by_addresses", client.get_utxos_by_addresses(batch.to_vec())).await?;
+        let utxos: Vec<Utxo> = entries
+            .into_iter()
+            .filter_map(|entry| {
+                Some(Utxo {
+                    transaction_id: entry.outpoint.transaction_id.to_string(),
+                    index: entry.outpoint.index,
+                    address: entry.address?.to_string(),
+                    amount: entry.utxo_entry.amount,
+                    block_daa_score: entry.utxo_entry.block_daa_score,
+                    is_coinbase: entry.utxo_entry.is_coinbase,
+                })
+            })
+            .collect();
+        count += utxos.len();
+        stage(db, utxos).await?;
+    }
+    let virtual_daa_score = metrics::rpc("get_block_dag_info", client.get_block_dag_info()).await?.virtual_daa_score;
+    let circulating_sompi = metrics::rpc("get_coin_supply", client.get_coin_supply()).await?.circulating_sompi;
+    let timestamp = Utc::now().timestamp_millis() as u64;
+    Ok((count, SnapshotInfo { timestamp, virtual_daa_score, circulating_sompi }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utxo(transaction_id: &str, address: &str, amount: u64) -> Utxo {
+        Utxo {
+            transaction_id: transaction_id.to_string(),
+            index: 0,
+            address: address.to_string(),
+            amount,
+            block_daa_score: 1,
+            is_coinbase: false,
+        }
+    }
+
+    async fn balances(db: &Db) -> Vec<(String, i64, i64)> {
+        db.call(|conn| {
+            let mut stmt = conn.prepare("SELECT address, balance, utxo_count FROM balances ORDER BY address")?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+            rows.collect()
+        })
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn publishes_every_staged_batch_at_once() {
+        let db = Db::open(":memory:").unwrap();
+        let info = |timestamp| SnapshotInfo { timestamp, virtual_daa_score: 10, circulating_sompi: 100 };
+        stage(&db, vec![utxo("a", "xenom:one", 5), utxo("b", "xenom:two", 7)]).await.unwrap();
+        publish(&db, info(1)).await.unwrap();
+
+        // Staged batches of the next snapshot stay invisible until it is published
+        clear_staged(&db).await.unwrap();
+        stage(&db, vec![utxo("c", "xenom:one", 1)]).await.unwrap();
+        assert_eq!(balances(&db).await, [("xenom:one".to_string(), 5, 1), ("xenom:two".to_string(), 7, 1)]);
+        stage(&db, vec![utxo("d", "xenom:one", 2)]).await.unwrap();
+        publish(&db, info(2)).await.unwrap();
+
+        assert_eq!(balances(&db).await, [("xenom:one".to_string(), 3, 2)]);
+        assert_eq!(snapshot_info(&db).await.unwrap().map(|info| info.timestamp), Some(2));
+        let staged = db.call(|conn| conn.query_row("SELECT COUNT(*) FROM utxos_next", [], |row| row.get::<_, i64>(0))).await.unwrap();
+        assert_eq!(staged, 0);
+    }
+}
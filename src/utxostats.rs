@@ -0,0 +1,342 @@
+use actix_web::{web, HttpResponse, Responder};
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::amount::{Amount, Unit};
+use crate::blocks::parse_time;
+use crate::db::Db;
+use crate::logging::ErrorBody;
+use crate::stats::{self, Sample};
+
+// Outputs below this many sompi cost more in fees to spend than a standard P2PK output is worth
+const DUST_THRESHOLD_SOMPI: u64 = 600;
+// Age bucket bounds as DAA score distance from the virtual, each bucket ends where the next begins
+const AGE_BOUNDS: [u64; 7] = [0, 1_000, 10_000, 100_000, 1_000_000, 10_000_000, 100_000_000];
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UtxoStatsQuery {
+    // Latest summary taken at or before this time, the newest one unless given
+    at: Option<String>,
+    unit: Option<Unit>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct UtxoClass {
+    count: u64,
+    value: Amount,
+}
+
+#[derive(Serialize, ToSchema)]
+struct DustStats {
+    threshold: Amount,
+    count: u64,
+    value: Amount,
+}
+
+#[derive(Serialize, ToSchema)]
+struct AgeBucket {
+    // DAA score distance between the virtual and the block that created the output
+    min_age: u64,
+    // Missing for the open ended oldest bucket
+    max_age: Option<u64>,
+    count: u64,
+    value: Amount,
+}
+
+#[derive(Serialize, ToSchema)]
+struct UtxoStatsResponse {
+    // Unix milliseconds of the snapshot the summary was built from
+    timestamp: u64,
+    virtual_daa_score: u64,
+    count: u64,
+    value: Amount,
+    coinbase: UtxoClass,
+    regular: UtxoClass,
+    dust: DustStats,
+    // Average age in DAA score
+    mean_age: f64,
+    age: Vec<AgeBucket>,
+}
+
+// Summary of one snapshot in sompi, as stored
+struct Summary {
+    timestamp: u64,
+    virtual_daa_score: u64,
+    count: u64,
+    value: u64,
+    coinbase_count: u64,
+    coinbase_value: u64,
+    dust_count: u64,
+    dust_value: u64,
+    mean_age: f64,
+    // Minimum age, count and value of every bucket
+    age: Vec<(u64, u64, u64)>,
+}
+
+// Builds the summary of the current UTXO snapshot, keeping earlier ones for comparison over time
+pub async fn summarize(db: &Db, timestamp: u64, virtual_daa_score: u64) -> anyhow::Result<()> {
+    let samples = db
+        .call(move |conn| {
+            let tx = conn.transaction()?;
+            let (count, value, coinbase_count, coinbase_value, dust_count, dust_value, mean_age) = tx.query_row(
+                "SELECT COUNT(*), COALESCE(SUM(amount), 0),
+                     COALESCE(SUM(is_coinbase), 0), COALESCE(SUM(CASE WHEN is_coinbase THEN amount ELSE 0 END), 0),
+                     COALESCE(SUM(amount < ?1), 0), COALESCE(SUM(CASE WHEN amount < ?1 THEN amount ELSE 0 END), 0),
+                     COALESCE(AVG(MAX(?2 - block_daa_score, 0)), 0)
+                 FROM utxos",
+                params![DUST_THRESHOLD_SOMPI as i64, virtual_daa_score as i64],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, i64>(2)?,
+                        row.get::<_, i64>(3)?,
+                        row.get::<_, i64>(4)?,
+                        row.get::<_, i64>(5)?,
+                        row.get::<_, f64>(6)?,
+                    ))
+                },
+            )?;
+            tx.execute(
+                "INSERT OR REPLACE INTO utxo_summaries
+                     (timestamp, virtual_daa_score, count, value, coinbase_count, coinbase_value, dust_count, dust_value, mean_age)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    timestamp as i64,
+                    virtual_daa_score as i64,
+                    count,
+                    value,
+                    coinbase_count,
+                    coinbase_value,
+                    dust_count,
+                    dust_value,
+                    mean_age
+                ],
+            )?;
+            {
+                let mut bucket = tx.prepare(
+                    "INSERT OR REPLACE INTO utxo_age_buckets (timestamp, min_age, count, value)
+                     SELECT ?1, ?2, COUNT(*), COALESCE(SUM(amount), 0) FROM utxos
+                     WHERE MAX(?4 - block_daa_score, 0) >= ?2 AND MAX(?4 - block_daa_score, 0) < ?3",
+                )?;
+                for (i, min) in AGE_BOUNDS.iter().enumerate() {
+                    let max = AGE_BOUNDS.get(i + 1).map_or(i64::MAX, |max| *max as i64);
+                    bucket.execute(params![timestamp as i64, *min as i64, max, virtual_daa_score as i64])?;
+                }
+            }
+            tx.commit()?;
+            Ok(vec![
+                ("utxo_count", Sample::Value(count as f64)),
+                ("utxo_value", Sample::Sompi(value as u64)),
+                ("utxo_coinbase_count", Sample::Value(coinbase_count as f64)),
+                ("utxo_dust_count", Sample::Value(dust_count as f64)),
+                ("utxo_mean_age", Sample::Value(mean_age)),
+            ])
+        })
+        .await?;
+    // The scalar figures also go into the sampled series so they can be charted with /stats/{metric}
+    stats::store_samples(db, timestamp, samples).await
+}
+
+async fn load(db: &Db, at: u64) -> anyhow::Result<Option<Summary>> {
+    db.call(move |conn| {
+        let summary = conn
+            .query_row(
+                "SELECT timestamp, virtual_daa_score, count, value, coinbase_count, coinbase_value, dust_count, dust_value, mean_age
+                 FROM utxo_summaries WHERE timestamp <= ?1 ORDER BY timestamp DESC LIMIT 1",
+                params![at as i64],
+                |row| {
+                    Ok(Summary {
+                        timestamp: row.get::<_, i64>(0)? as u64,
+                        virtual_daa_score: row.get::<_, i64>(1)? as u64,
+                        count: row.get::<_, i64>(2)? as u64,
+                        value: row.get::<_, i64>(3)? as u64,
+                        coinbase_count: row.get::<_, i64>(4)? as u64,
+                        coinbase_value: row.get::<_, i64>(5)? as u64,
+                        dust_count: row.get::<_, i64>(6)? as u64,
+                        dust_value: row.get::<_, i64>(7)? as u64,
+                        mean_age: row.get(8)?,
+                        age: Vec::new(),
+                    })
+                },
+            )
+            .optional()?;
+        let Some(mut summary) = summary else {
+            return Ok(None);
+        };
+        let mut stmt =
+            conn.prepare("SELECT min_age, count, value FROM utxo_age_buckets WHERE timestamp = ?1 ORDER BY min_age")?;
+        let rows = stmt.query_map(params![summary.timestamp as i64], |row| {
+            Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64, row.get::<_, i64>(2)? as u64))
+        })?;
+        summary.age = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(Some(summary))
+    })
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/stats/utxo",
+    tag = "stats",
+    params(UtxoStatsQuery),
+    responses(
+        (status = 200, description = "UTXO count, value, age distribution, coinbase share and dust of a snapshot", body = UtxoStatsResponse),
+        (status = 400, description = "Invalid time", body = ErrorBody),
+        (status = 404, description = "No summary at or before the given time", body = ErrorBody),
+        (status = 503, description = "No UTXO snapshot yet", body = ErrorBody),
+    )
+)]
+pub async fn get_utxo_stats(query: web::Query<UtxoStatsQuery>, db: web::Data<Db>) -> impl Responder {
+    let unit = query.unit.unwrap_or_default();
+    let at = match query.at.as_deref() {
+        Some(at) => match parse_time(at) {
+            Some(at) => Some(at),
+            None => return HttpResponse::BadRequest().json("Invalid at time"),
+        },
+        None => None,
+    };
+
+    let summary = match load(&db, at.unwrap_or(i64::MAX as u64)).await {
+        Ok(Some(summary)) => summary,
+        Ok(None) if at.is_some() => return HttpResponse::NotFound().json("No UTXO summary at or before the given time"),
+        Ok(None) => return HttpResponse::ServiceUnavailable().json("UTXO snapshot has not been taken yet"),
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to read UTXO summary: {:?}", err)),
+    };
+
+    HttpResponse::Ok().json(response(summary, unit))
+}
+
+// Splits the stored summary into the response classes, each age bucket ending where the next begins
+fn response(summary: Summary, unit: Unit) -> UtxoStatsResponse {
+    let age = summary
+        .age
+        .iter()
+        .enumerate()
+        .map(|(i, (min_age, count, value))| AgeBucket {
+            min_age: *min_age,
+            max_age: summary.age.get(i + 1).map(|(max_age, _, _)| *max_age),
+            count: *count,
+            value: Amount::new(*value, unit),
+        })
+        .collect();
+    UtxoStatsResponse {
+        timestamp: summary.timestamp,
+        virtual_daa_score: summary.virtual_daa_score,
+        count: summary.count,
+        value: Amount::new(summary.value, unit),
+        coinbase: UtxoClass { count: summary.coinbase_count, value: Amount::new(summary.coinbase_value, unit) },
+        regular: UtxoClass {
+            count: summary.count - summary.coinbase_count,
+            value: Amount::new(summary.value - summary.coinbase_value, unit),
+        },
+        dust: DustStats {
+            threshold: Amount::new(DUST_THRESHOLD_SOMPI, unit),
+            count: summary.dust_count,
+            value: Amount::new(summary.dust_value, unit),
+        },
+        mean_age: summary.mean_age,
+        age,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIRTUAL: u64 = 200_000_000;
+
+    async fn insert(db: &Db, utxos: Vec<(u64, u64, bool)>) {
+        db.call(move |conn| {
+            for (i, (amount, block_daa_score, is_coinbase)) in utxos.into_iter().enumerate() {
+                conn.execute(
+                    "INSERT INTO utxos (transaction_id, output_index, address, amount, block_daa_score, is_coinbase)
+                     VALUES (?1, 0, 'xenom:a', ?2, ?3, ?4)",
+                    params![format!("tx{}", i), amount as i64, block_daa_score as i64, is_coinbase],
+                )?;
+            }
+            Ok(())
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn summarizes_classes_dust_and_age_buckets() {
+        let db = Db::open(":memory:").unwrap();
+        insert(
+            &db,
+            vec![
+                // Just below the dust threshold, created at the virtual
+                (599, VIRTUAL, true),
+                // At the threshold, the last score of the first bucket
+                (600, VIRTUAL - 999, false),
+                // Exactly on the next bound
+                (1_000, VIRTUAL - 1_000, false),
+                // Created after the virtual score counts as age 0, not negative
+                (5_000, VIRTUAL + 500, true),
+                (7_000_000, VIRTUAL - 2_000_000, false),
+                // Older than the last bound lands in the open ended bucket
+                (100, 0, false),
+            ],
+        )
+        .await;
+        summarize(&db, 1_000, VIRTUAL).await.unwrap();
+
+        let summary = load(&db, 1_000).await.unwrap().unwrap();
+        assert_eq!(summary.virtual_daa_score, VIRTUAL);
+        assert_eq!((summary.count, summary.value), (6, 7_007_299));
+        assert_eq!((summary.coinbase_count, summary.coinbase_value), (2, 5_599));
+        assert_eq!((summary.dust_count, summary.dust_value), (2, 699));
+        assert_eq!(summary.mean_age, (999 + 1_000 + 2_000_000 + VIRTUAL) as f64 / 6.0);
+        assert_eq!(
+            summary.age,
+            vec![
+                (0, 3, 6_199),
+                (1_000, 1, 1_000),
+                (10_000, 0, 0),
+                (100_000, 0, 0),
+                (1_000_000, 1, 7_000_000),
+                (10_000_000, 0, 0),
+                (100_000_000, 1, 100),
+            ]
+        );
+
+        let response = response(summary, Unit::Sompi);
+        assert_eq!(response.regular.count, 4);
+        assert_eq!(response.regular.value.sompi.as_deref(), Some("7001700"));
+        assert_eq!(response.coinbase.count, 2);
+        assert_eq!(response.dust.threshold.sompi.as_deref(), Some("600"));
+        let bounds: Vec<_> = response.age.iter().map(|bucket| (bucket.min_age, bucket.max_age)).collect();
+        assert_eq!(bounds[0], (0, Some(1_000)));
+        assert_eq!(bounds[5], (10_000_000, Some(100_000_000)));
+        assert_eq!(bounds[6], (100_000_000, None));
+    }
+
+    #[tokio::test]
+    async fn keeps_earlier_summaries_and_samples() {
+        let db = Db::open(":memory:").unwrap();
+        summarize(&db, 1_000, VIRTUAL).await.unwrap();
+        insert(&db, vec![(1_000, VIRTUAL, false)]).await;
+        summarize(&db, 2_000, VIRTUAL + 10).await.unwrap();
+
+        let empty = load(&db, 1_500).await.unwrap().unwrap();
+        assert_eq!((empty.timestamp, empty.count, empty.value, empty.mean_age), (1_000, 0, 0, 0.0));
+        assert!(empty.age.iter().all(|(_, count, value)| *count == 0 && *value == 0));
+        let latest = load(&db, u64::MAX >> 1).await.unwrap().unwrap();
+        assert_eq!((latest.timestamp, latest.count, latest.mean_age), (2_000, 1, 10.0));
+        assert!(load(&db, 999).await.unwrap().is_none());
+
+        let counts: Vec<f64> = db
+            .call(|conn| {
+                let mut stmt =
+                    conn.prepare("SELECT value FROM samples WHERE metric = 'utxo_count' ORDER BY timestamp")?;
+                let rows = stmt.query_map([], |row| row.get(0))?;
+                rows.collect()
+            })
+            .await
+            .unwrap();
+        assert_eq!(counts, vec![0.0, 1.0]);
+    }
+}
@@ -0,0 +1,208 @@
+use actix_web::{web, HttpResponse, Responder};
+use kaspa_rpc_core::api::rpc::RpcApi;
+use serde::Deserialize;
+use utoipa::IntoParams;
+use crate::amount::{Unit, UnitQuery};
+use crate::db::Db;
+use crate::dto::{BlockDagInfoDto, BlockDto, NodeInfoDto, SupplyDto, TransactionDto};
+use crate::index;
+use crate::logging::ErrorBody;
+use crate::metrics;
+use crate::parse_hash;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BlockQuery {
+    #[serde(rename = "includeTransactions")]
+    include_transactions: Option<bool>,
+    // Unit of transaction output amounts: sompi, xen or both (default)
+    unit: Option<Unit>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/blocks/{hash}",
+    tag = "v1",
+    params(("hash" = String, Path, description = "Block hash"), BlockQuery),
+    responses(
+        (status = 200, description = "Block header, DAG relations and optionally its transactions", body = BlockDto),
+        (status = 400, description = "Invalid hash", body = ErrorBody),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
+pub async fn get_block(path: web::Path<String>, query: web::Query<BlockQuery>) -> impl Responder {
+    let hash = match parse_hash(&path.into_inner()) {
+        Ok(hash) => hash,
+        Err(err) => return err,
+    };
+    let client = match crate::get_client().await {
+        Ok(client) => client,
+        Err(err) => return err,
+    };
+
+    let include_transactions = query.include_transactions.unwrap_or(false);
+    let block = match metrics::rpc("get_block", client.get_block(hash, include_transactions)).await {
+        Ok(block) => block,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to get block: {:?}", err)),
+    };
+    if let Err(disconnect_err) = client.disconnect().await {
+        return HttpResponse::InternalServerError().json(format!("Failed to disconnect: {}", disconnect_err));
+    }
+
+    HttpResponse::Ok().json(BlockDto::new(&block, query.unit.unwrap_or_default()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/transactions/{id}",
+    tag = "v1",
+    params(("id" = String, Path, description = "Transaction id"), UnitQuery),
+    responses(
+        (status = 200, description = "Accepted transaction with its block, or a mempool transaction without one", body = TransactionDto),
+        (status = 400, description = "Invalid transaction id", body = ErrorBody),
+        (status = 404, description = "Neither indexed nor in the mempool", body = ErrorBody),
+        (status = 500, description = "Index or node request failed", body = ErrorBody),
+    )
+)]
+pub async fn get_transaction(path: web::Path<String>, query: web::Query<UnitQuery>, db: web::Data<Db>) -> impl Responder {
+    let id = match parse_hash(&path.into_inner()) {
+        Ok(id) => id,
+        Err(err) => return err,
+    };
+    // The node has no transaction index, accepted transactions are found through their indexed block
+    let block_hash = match index::transaction_block(&db, id.to_string()).await {
+        Ok(block_hash) => block_hash,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to read transaction index: {:?}", err)),
+    };
+    let block_hash = match block_hash.map(|hash| parse_hash(&hash)).transpose() {
+        Ok(block_hash) => block_hash,
+        Err(err) => return err,
+    };
+    let client = match crate::get_client().await {
+        Ok(client) => client,
+        Err(err) => return err,
+    };
+
+    let unit = query.unit();
+    let transaction = match block_hash {
+        Some(block_hash) => match metrics::rpc("get_block", client.get_block(block_hash, true)).await {
+            Ok(block) => Ok(block
+                .transactions
+                .iter()
+                .find(|tx| tx.verbose_data.as_ref().map_or(false, |data| data.transaction_id == id))
+                .map(|tx| TransactionDto::new(tx, unit))),
+            Err(err) => Err(format!("Failed to get block: {:?}", err)),
+        },
+        None => match metrics::rpc("get_mempool_entry", client.get_mempool_entry(id, true, false)).await {
+            Ok(response) => {
+                let mut transaction = TransactionDto::new(&response.mempool_entry.transaction, unit);
+                // Mempool transactions carry empty block fields
+                transaction.block_hash = None;
+                transaction.block_time = None;
+                transaction.transaction_id = Some(id.to_string());
+                Ok(Some(transaction))
+            }
+            Err(err) if is_not_found(&err) => Ok(None),
+            Err(err) => Err(format!("Failed to get mempool entry: {:?}", err)),
+        },
+    };
+    let _ = client.disconnect().await;
+
+    match transaction {
+        Ok(Some(transaction)) => HttpResponse::Ok().json(transaction),
+        Ok(None) => HttpResponse::NotFound().json(format!("Transaction {} not found", id)),
+        Err(err) => HttpResponse::InternalServerError().json(err),
+    }
+}
+
+// The node answers a lookup of an unknown transaction with an error, over wRPC only its message
+// ("Transaction ... not found") tells it apart from a transport failure
+fn is_not_found(err: &impl std::fmt::Display) -> bool {
+    err.to_string().contains("not found")
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/info/blockdag",
+    tag = "v1",
+    responses(
+        (status = 200, description = "Block DAG state of the node", body = BlockDagInfoDto),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
+pub async fn get_block_dag_info() -> impl Responder {
+    let client = match crate::get_client().await {
+        Ok(client) => client,
+        Err(err) => return err,
+    };
+
+    let info = match metrics::rpc("get_block_dag_info", client.get_block_dag_info()).await {
+        Ok(info) => info,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to get block DAG info: {:?}", err)),
+    };
+    if let Err(disconnect_err) = client.disconnect().await {
+        return HttpResponse::InternalServerError().json(format!("Failed to disconnect: {}", disconnect_err));
+    }
+
+    let info = BlockDagInfoDto::from(&info);
+    HttpResponse::Ok().json(info)
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/info/node",
+    tag = "v1",
+    responses(
+        (status = 200, description = "Version, network and sync state of the node", body = NodeInfoDto),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
+pub async fn get_node_info() -> impl Responder {
+    let client = match crate::get_client().await {
+        Ok(client) => client,
+        Err(err) => return err,
+    };
+
+    let info = match metrics::rpc("get_info", client.get_info()).await {
+        Ok(info) => info,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to get node info: {:?}", err)),
+    };
+    let server_info = match metrics::rpc("get_server_info", client.get_server_info()).await {
+        Ok(info) => info,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("Failed to get server info: {:?}", err)),
+    };
+    if let Err(disconnect_err) = client.disconnect().await {
+        return HttpResponse::InternalServerError().json(format!("Failed to disconnect: {}", disconnect_err));
+    }
+
+    HttpResponse::Ok().json(NodeInfoDto::from((&info, &server_info)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/info/coinsupply",
+    tag = "v1",
+    params(UnitQuery),
+    responses(
+        (status = 200, description = "Circulating and maximum supply", body = SupplyDto),
+        (status = 500, description = "Node request failed", body = ErrorBody),
+    )
+)]
+pub async fn get_coin_supply(query: web::Query<UnitQuery>) -> impl Responder {
+    match crate::fetch_coin_supply().await {
+        Ok(supply) => HttpResponse::Ok().json(SupplyDto::new(&supply, query.unit())),
+        Err(err) => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_missing_transactions_from_failures() {
+        assert!(is_not_found(&"Transaction 1234 not found"));
+        assert!(!is_not_found(&"RPC client is not connected"));
+        assert!(!is_not_found(&"WebSocket error: connection reset"));
+    }
+}
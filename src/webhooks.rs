@@ -0,0 +1,1120 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use futures_util::future::join_all;
+use hmac::{Hmac, Mac};
+use kaspa_notify::scope::{Scope, UtxosChangedScope, VirtualDaaScoreChangedScope};
+use kaspa_rpc_core::{Notification, RpcAddress, RpcUtxosByAddressesEntry};
+use rusqlite::{params, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use tokio::sync::Notify;
+use tokio::time::{sleep, Duration, Instant};
+use tracing::{error, info, warn};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use crate::amount::{parse_xen, Amount, Unit};
+use crate::auth::ApiKey;
+use crate::config;
+use crate::db::Db;
+use crate::logging::ErrorBody;
+use crate::notify::Subscription;
+
+const MAX_ADDRESSES: usize = 1000;
+// Deepest confirmation a webhook can wait for, in DAA score
+const MAX_CONFIRMATIONS: u64 = 100_000;
+const DEFAULT_DELIVERY_LIMIT: usize = 50;
+const MAX_DELIVERY_LIMIT: usize = 500;
+// Events posted per delivery round
+const DELIVERY_BATCH: usize = 50;
+const DELIVERY_INTERVAL: Duration = Duration::from_secs(1);
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+// Failed deliveries are retried after 10s, 20s, 40s, ... up to an hour, then given up
+const RETRY_BASE_SECS: i64 = 10;
+const RETRY_MAX_SECS: i64 = 60 * 60;
+const MAX_ATTEMPTS: i64 = 10;
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+const EVENT_UTXO_ADDED: &str = "utxo.added";
+const EVENT_UTXO_SPENT: &str = "utxo.spent";
+const EVENT_UTXO_CONFIRMED: &str = "utxo.confirmed";
+const EVENT_PING: &str = "ping";
+
+// Wakes the listener when the set of watched addresses changes
+#[derive(Default)]
+pub struct Webhooks {
+    changed: Notify,
+}
+
+impl Webhooks {
+    fn changed(&self) {
+        // A stored permit makes sure a change made while the listener is busy isn't missed
+        self.changed.notify_one();
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateWebhookRequest {
+    // http or https endpoint receiving the POSTs
+    url: String,
+    addresses: Vec<String>,
+    // Smallest UTXO in XEN worth a notification, all of them unless given
+    min_amount: Option<String>,
+    // DAA score depth after which a utxo.confirmed event follows, none unless given
+    confirmations: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeliveryQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct Webhook {
+    id: String,
+    url: String,
+    addresses: Vec<String>,
+    min_amount: Amount,
+    confirmations: u64,
+    created_at: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct CreatedWebhook {
+    // Key of the X-Xenom-Signature HMAC, only ever returned here
+    secret: String,
+    #[serde(flatten)]
+    webhook: Webhook,
+}
+
+#[derive(Serialize, ToSchema)]
+struct QueuedEvent {
+    event_id: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct Delivery {
+    event_id: String,
+    event: String,
+    attempt: u32,
+    timestamp: i64,
+    // HTTP status of the endpoint, missing when it couldn't be reached
+    status: Option<u16>,
+    error: Option<String>,
+    duration_ms: u64,
+    // Whether the event is still waiting for a retry, delivered or given up
+    state: String,
+}
+
+// A UTXO change reported by the node
+struct UtxoChange {
+    event: &'static str,
+    address: String,
+    transaction_id: String,
+    index: u32,
+    amount: u64,
+    block_daa_score: u64,
+    is_coinbase: bool,
+}
+
+impl UtxoChange {
+    fn from_entry(event: &'static str, entry: &RpcUtxosByAddressesEntry) -> Option<UtxoChange> {
+        Some(UtxoChange {
+            event,
+            address: entry.address.as_ref()?.to_string(),
+            transaction_id: entry.outpoint.transaction_id.to_string(),
+            index: entry.outpoint.index,
+            amount: entry.utxo_entry.amount,
+            block_daa_score: entry.utxo_entry.block_daa_score,
+            is_coinbase: entry.utxo_entry.is_coinbase,
+        })
+    }
+}
+
+// Due event with where and how to send it
+struct Outgoing {
+    event_id: String,
+    event: String,
+    payload: String,
+    attempts: i64,
+    url: String,
+    secret: String,
+}
+
+// Queue an event for delivery, the payload carries everything the receiver needs
+fn enqueue(tx: &Transaction, webhook_id: &str, event: &str, data: serde_json::Value) -> rusqlite::Result<String> {
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now().timestamp();
+    let payload = json!({ "id": id, "type": event, "webhook_id": webhook_id, "created_at": now, "data": data });
+    tx.execute(
+        "INSERT INTO webhook_events (id, webhook_id, event, payload, created_at, attempts, next_attempt_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, 0, ?5)",
+        params![id, webhook_id, event, payload.to_string(), now],
+    )?;
+    Ok(id)
+}
+
+fn utxo_data(change: &UtxoChange, confirmations: Option<u64>) -> serde_json::Value {
+    let mut data = json!({
+        "address": change.address,
+        "transaction_id": change.transaction_id,
+        "output_index": change.index,
+        "amount": Amount::new(change.amount, Unit::Both),
+        "block_daa_score": change.block_daa_score,
+        "is_coinbase": change.is_coinbase,
+    });
+    if let Some(confirmations) = confirmations {
+        data["confirmations"] = json!(confirmations);
+    }
+    data
+}
+
+// HMAC-SHA256 over "<timestamp>.<body>" with the webhook secret, hex encoded
+fn sign(secret: &str, timestamp: i64, payload: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.", timestamp).as_bytes());
+    mac.update(payload.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn retry_delay(attempts: i64) -> i64 {
+    RETRY_BASE_SECS.saturating_mul(1 << (attempts - 1).clamp(0, 20)).min(RETRY_MAX_SECS)
+}
+
+async fn watched_addresses(db: &Db) -> anyhow::Result<Vec<RpcAddress>> {
+    let addresses: Vec<String> = db
+        .call(|conn| {
+            let mut stmt = conn.prepare("SELECT DISTINCT address FROM webhook_addresses")?;
+            let rows = stmt.query_map([], |row| row.get(0))?;
+            rows.collect()
+        })
+        .await?;
+    Ok(addresses.iter().filter_map(|address| RpcAddress::try_from(address.as_str()).ok()).collect())
+}
+
+async fn on_utxos_changed(db: &Db, changes: Vec<UtxoChange>) -> anyhow::Result<()> {
+    db.call(move |conn| {
+        let tx = conn.transaction()?;
+        {
+            let mut subscribers = tx.prepare(
+                "SELECT w.id, w.confirmations FROM webhooks w JOIN webhook_addresses a ON a.webhook_id = w.id
+                 WHERE a.address = ?1 AND w.min_amount <= ?2",
+            )?;
+            let mut pending = tx.prepare(
+                "INSERT OR IGNORE INTO webhook_confirmations
+                     (webhook_id, transaction_id, output_index, address, amount, block_daa_score, is_coinbase, target_daa_score)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )?;
+            // A UTXO spent before reaching its depth never gets a utxo.confirmed
+            let mut spent = tx.prepare(
+                "DELETE FROM webhook_confirmations WHERE webhook_id = ?1 AND transaction_id = ?2 AND output_index = ?3",
+            )?;
+            for change in &changes {
+                let rows = subscribers.query_map(params![change.address, change.amount as i64], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64))
+                })?;
+                for (webhook_id, confirmations) in rows.collect::<rusqlite::Result<Vec<_>>>()? {
+                    enqueue(&tx, &webhook_id, change.event, utxo_data(change, None))?;
+                    if change.event == EVENT_UTXO_ADDED && confirmations > 0 {
+                        pending.execute(params![
+                            webhook_id,
+                            change.transaction_id,
+                            change.index,
+                            change.address,
+                            change.amount as i64,
+                            change.block_daa_score as i64,
+                            change.is_coinbase,
+                            (change.block_daa_score + confirmations) as i64
+                        ])?;
+                    } else if change.event == EVENT_UTXO_SPENT {
+                        spent.execute(params![webhook_id, change.transaction_id, change.index])?;
+                    }
+                }
+            }
+        }
+        tx.commit()
+    })
+        .await
+}
+
+// Queue utxo.confirmed for every UTXO whose target depth the virtual has reached
+async fn on_virtual_daa_score(db: &Db, virtual_daa_score: u64) -> anyhow::Result<()> {
+    db.call(move |conn| {
+        let tx = conn.transaction()?;
+        let reached = {
+            let mut stmt = tx.prepare(
+                "SELECT webhook_id, transaction_id, output_index, address, amount, block_daa_score, is_coinbase
+                 FROM webhook_confirmations WHERE target_daa_score <= ?1",
+            )?;
+            let rows = stmt.query_map(params![virtual_daa_score as i64], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    UtxoChange {
+                        event: EVENT_UTXO_CONFIRMED,
+                        transaction_id: row.get(1)?,
+                        index: row.get(2)?,
+                        address: row.get(3)?,
+                        amount: row.get::<_, i64>(4)? as u64,
+                        block_daa_score: row.get::<_, i64>(5)? as u64,
+                        is_coinbase: row.get(6)?,
+                    },
+                ))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        for (webhook_id, change) in &reached {
+            let confirmations = virtual_daa_score.saturating_sub(change.block_daa_score);
+            enqueue(&tx, webhook_id, change.event, utxo_data(change, Some(confirmations)))?;
+        }
+        tx.execute("DELETE FROM webhook_confirmations WHERE target_daa_score <= ?1", params![virtual_daa_score as i64])?;
+        tx.commit()
+    })
+        .await
+}
+
+// Background task subscribing to UTXO changes of every watched address, resubscribing when the set changes
+pub async fn listen(db: Db, webhooks: Arc<Webhooks>) {
+    loop {
+        match watch(&db, &webhooks).await {
+            Ok(()) => {}
+            Err(err) => {
+                error!(error = ?err, "Webhook listener failed");
+                sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+// Runs one subscription until the watched addresses change or the node goes away
+async fn watch(db: &Db, webhooks: &Webhooks) -> anyhow::Result<()> {
+    let addresses = watched_addresses(db).await?;
+    // An empty scope would subscribe to every address of the network
+    if addresses.is_empty() {
+        webhooks.changed.notified().await;
+        return Ok(());
+    }
+
+    let count = addresses.len();
+    let subscription = Subscription::start(
+        "webhooks",
+        vec![
+            Scope::UtxosChanged(UtxosChangedScope::new(addresses)),
+            Scope::VirtualDaaScoreChanged(VirtualDaaScoreChangedScope {}),
+        ],
+    )
+        .await?;
+    info!(addresses = count, "Watching addresses for webhooks");
+
+    let result = loop {
+        tokio::select! {
+            _ = webhooks.changed.notified() => break Ok(()),
+            notification = subscription.recv() => {
+                let handled = match notification {
+                    Ok(Notification::UtxosChanged(notification)) => {
+                        let changes = notification
+                            .added
+                            .iter()
+                            .filter_map(|entry| UtxoChange::from_entry(EVENT_UTXO_ADDED, entry))
+                            .chain(notification.removed.iter().filter_map(|entry| UtxoChange::from_entry(EVENT_UTXO_SPENT, entry)))
+                            .collect();
+                        on_utxos_changed(db, changes).await
+                    }
+                    Ok(Notification::VirtualDaaScoreChanged(notification)) => {
+                        on_virtual_daa_score(db, notification.virtual_daa_score).await
+                    }
+                    Ok(_) => Ok(()),
+                    Err(err) => Err(err),
+                };
+                if let Err(err) = handled {
+                    break Err(err);
+                }
+            }
+        }
+    };
+
+    subscription.close().await;
+    result
+}
+
+// Background task posting queued events, retrying failures with exponential backoff
+pub async fn deliver(db: Db) {
+    loop {
+        if let Err(err) = deliver_due(&db, config::get().webhooks_allow_private).await {
+            error!(error = ?err, "Webhook delivery failed");
+        }
+        sleep(DELIVERY_INTERVAL).await;
+    }
+}
+
+async fn deliver_due(db: &Db, allow_private: bool) -> anyhow::Result<()> {
+    let due = db
+        .call(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT e.id, e.event, e.payload, e.attempts, w.url, w.secret
+                 FROM webhook_events e JOIN webhooks w ON w.id = e.webhook_id
+                 WHERE e.delivered_at IS NULL AND e.failed_at IS NULL AND e.next_attempt_at <= ?1
+                 ORDER BY e.next_attempt_at LIMIT ?2",
+            )?;
+            let rows = stmt.query_map(params![Utc::now().timestamp(), DELIVERY_BATCH as i64], |row| {
+                Ok(Outgoing {
+                    event_id: row.get(0)?,
+                    event: row.get(1)?,
+                    payload: row.get(2)?,
+                    attempts: row.get(3)?,
+                    url: row.get(4)?,
+                    secret: row.get(5)?,
+                })
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await?;
+    if due.is_empty() {
+        return Ok(());
+    }
+
+    let results = join_all(due.iter().map(|outgoing| attempt(outgoing, allow_private))).await;
+    db.call(move |conn| {
+        let tx = conn.transaction()?;
+        let now = Utc::now().timestamp();
+        for (outgoing, (status, error, duration_ms)) in due.iter().zip(results) {
+            let attempt = outgoing.attempts + 1;
+            tx.execute(
+                "INSERT INTO webhook_deliveries (event_id, attempt, timestamp, status, error, duration_ms)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![outgoing.event_id, attempt, now, status, error, duration_ms as i64],
+            )?;
+            if status.map_or(false, |status| (200..300).contains(&status)) {
+                tx.execute(
+                    "UPDATE webhook_events SET attempts = ?1, delivered_at = ?2 WHERE id = ?3",
+                    params![attempt, now, outgoing.event_id],
+                )?;
+            } else if attempt >= MAX_ATTEMPTS {
+                warn!(event_id = %outgoing.event_id, url = %outgoing.url, "Giving up on webhook event");
+                tx.execute(
+                    "UPDATE webhook_events SET attempts = ?1, failed_at = ?2 WHERE id = ?3",
+                    params![attempt, now, outgoing.event_id],
+                )?;
+            } else {
+                tx.execute(
+                    "UPDATE webhook_events SET attempts = ?1, next_attempt_at = ?2 WHERE id = ?3",
+                    params![attempt, now + retry_delay(attempt), outgoing.event_id],
+                )?;
+            }
+        }
+        tx.commit()
+    })
+        .await
+}
+
+// Checks the destination again before every attempt, since the host may resolve elsewhere than it
+// did at creation, and sends the event to exactly the addresses that were checked
+async fn attempt(outgoing: &Outgoing, allow_private: bool) -> (Option<u16>, Option<String>, u64) {
+    let url = match reqwest::Url::parse(&outgoing.url) {
+        Ok(url) => url,
+        Err(err) => return (None, Some(format!("Invalid URL: {}", err)), 0),
+    };
+    let addresses = match check_destination(&url, allow_private).await {
+        Ok(addresses) => addresses,
+        Err(err) => return (None, Some(err), 0),
+    };
+    match delivery_client(&url, &addresses) {
+        Ok(client) => post(&client, outgoing).await,
+        Err(err) => (None, Some(format!("Failed to create HTTP client: {}", err)), 0),
+    }
+}
+
+// Redirects are not followed and proxies are not used, either would send the delivery somewhere
+// other than the checked addresses
+fn delivery_client(url: &reqwest::Url, addresses: &[SocketAddr]) -> reqwest::Result<reqwest::Client> {
+    let builder = reqwest::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .no_proxy();
+    let builder = match url.domain() {
+        Some(domain) => builder.resolve_to_addrs(domain, addresses),
+        None => builder,
+    };
+    builder.build()
+}
+
+// Sends one event, returning the response status or the transport error and how long it took
+async fn post(client: &reqwest::Client, outgoing: &Outgoing) -> (Option<u16>, Option<String>, u64) {
+    let timestamp = Utc::now().timestamp();
+    let start = Instant::now();
+    let response = client
+        .post(&outgoing.url)
+        .header("content-type", "application/json")
+        .header("x-xenom-event", &outgoing.event)
+        .header("x-xenom-delivery", &outgoing.event_id)
+        .header("x-xenom-timestamp", timestamp.to_string())
+        .header("x-xenom-signature", format!("sha256={}", sign(&outgoing.secret, timestamp, &outgoing.payload)))
+        .body(outgoing.payload.clone())
+        .send()
+        .await;
+    let duration_ms = start.elapsed().as_millis() as u64;
+    match response {
+        Ok(response) if response.status().is_success() => (Some(response.status().as_u16()), None, duration_ms),
+        Ok(response) => (Some(response.status().as_u16()), Some(format!("Endpoint answered {}", response.status())), duration_ms),
+        Err(err) => (None, Some(err.to_string()), duration_ms),
+    }
+}
+
+// Loopback, link-local, private and other non-public addresses, which would let a webhook reach
+// services next to the API
+fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                // Shared address space of carrier-grade NAT, 100.64.0.0/10
+                || (first == 100 && second & 0xc0 == 64)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_internal(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                // Unique local fc00::/7 and link-local fe80::/10
+                ip.is_loopback() || ip.is_unspecified() || first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80
+            }
+        },
+    }
+}
+
+// Resolves the host of a webhook URL, refusing it when any of its addresses is internal
+async fn check_destination(url: &reqwest::Url, allow_private: bool) -> Result<Vec<SocketAddr>, String> {
+    let host = url.host_str().ok_or_else(|| format!("Invalid URL: {}", url))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addresses: Vec<SocketAddr> = match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|err| format!("Failed to resolve {}: {}", host, err))?
+            .collect(),
+    };
+    if addresses.is_empty() {
+        return Err(format!("Failed to resolve {}", host));
+    }
+    if !allow_private && addresses.iter().any(|address| is_internal(address.ip())) {
+        return Err(format!("{} resolves to a loopback, link-local or private address", host));
+    }
+    Ok(addresses)
+}
+
+fn owner(req: &HttpRequest) -> Result<String, HttpResponse> {
+    req.extensions()
+        .get::<ApiKey>()
+        .map(|key| key.id.clone())
+        .ok_or_else(|| HttpResponse::Unauthorized().json("An API key is required"))
+}
+
+async fn load(db: &Db, owner: String, id: Option<String>) -> anyhow::Result<Vec<Webhook>> {
+    db.call(move |conn| {
+        let mut stmt = conn.prepare(
+            "SELECT id, url, min_amount, confirmations, created_at FROM webhooks
+             WHERE owner = ?1 AND (?2 IS NULL OR id = ?2) ORDER BY created_at",
+        )?;
+        let rows = stmt.query_map(params![owner, id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)? as u64,
+                row.get::<_, i64>(3)? as u64,
+                row.get::<_, i64>(4)?,
+            ))
+        })?;
+        let webhooks = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut addresses = conn.prepare("SELECT address FROM webhook_addresses WHERE webhook_id = ?1 ORDER BY address")?;
+        webhooks
+            .into_iter()
+            .map(|(id, url, min_amount, confirmations, created_at)| {
+                let rows = addresses.query_map(params![id], |row| row.get(0))?;
+                Ok(Webhook {
+                    addresses: rows.collect::<rusqlite::Result<Vec<_>>>()?,
+                    id,
+                    url,
+                    min_amount: Amount::new(min_amount, Unit::Both),
+                    confirmations,
+                    created_at,
+                })
+            })
+            .collect()
+    })
+        .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks",
+    tag = "webhooks",
+    request_body = CreateWebhookRequest,
+    security(("api_key" = [])),
+    responses(
+        (status = 201, description = "The new webhook, including its signing secret", body = CreatedWebhook),
+        (status = 400, description = "Invalid or internal URL, address, amount or depth", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = ErrorBody),
+        (status = 403, description = "API key lacks the webhooks scope", body = ErrorBody),
+    )
+)]
+pub async fn create_webhook(
+    req: HttpRequest,
+    body: web::Json<CreateWebhookRequest>,
+    db: web::Data<Db>,
+    webhooks: web::Data<Webhooks>,
+) -> impl Responder {
+    let owner = match owner(&req) {
+        Ok(owner) => owner,
+        Err(err) => return err,
+    };
+    let body = body.into_inner();
+    let url = match reqwest::Url::parse(&body.url) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => url,
+        _ => return HttpResponse::BadRequest().json(format!("Invalid URL: {}", body.url)),
+    };
+    if let Err(err) = check_destination(&url, config::get().webhooks_allow_private).await {
+        return HttpResponse::BadRequest().json(err);
+    }
+    if body.addresses.is_empty() || body.addresses.len() > MAX_ADDRESSES {
+        return HttpResponse::BadRequest().json(format!("A webhook watches between 1 and {} addresses", MAX_ADDRESSES));
+    }
+    let mut addresses = Vec::with_capacity(body.addresses.len());
+    for address in &body.addresses {
+        match RpcAddress::try_from(address.as_str()) {
+            Ok(address) => addresses.push(address.to_string()),
+            Err(err) => return HttpResponse::BadRequest().json(format!("Invalid address {}: {}", address, err)),
+        }
+    }
+    addresses.sort();
+    addresses.dedup();
+    let min_amount = match body.min_amount.as_deref() {
+        Some(min_amount) => match parse_xen(min_amount) {
+            Some(min_amount) => min_amount,
+            None => return HttpResponse::BadRequest().json(format!("Invalid XEN amount: {}", min_amount)),
+        },
+        None => 0,
+    };
+    let confirmations = body.confirmations.unwrap_or(0);
+    if confirmations > MAX_CONFIRMATIONS {
+        return HttpResponse::BadRequest().json(format!("confirmations must not exceed {}", MAX_CONFIRMATIONS));
+    }
+
+    let secret = format!("whsec_{}", Uuid::new_v4().simple());
+    let webhook = Webhook {
+        id: Uuid::new_v4().to_string(),
+        url: body.url,
+        addresses,
+        min_amount: Amount::new(min_amount, Unit::Both),
+        confirmations,
+        created_at: Utc::now().timestamp(),
+    };
+
+    let (id, url, stored_secret, stored_addresses, created_at) =
+        (webhook.id.clone(), webhook.url.clone(), secret.clone(), webhook.addresses.clone(), webhook.created_at);
+    let inserted = db
+        .call(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "INSERT INTO webhooks (id, owner, url, secret, min_amount, confirmations, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![id, owner, url, stored_secret, min_amount as i64, confirmations as i64, created_at],
+            )?;
+            for address in &stored_addresses {
+                tx.execute("INSERT INTO webhook_addresses (webhook_id, address) VALUES (?1, ?2)", params![id, address])?;
+            }
+            tx.commit()
+        })
+        .await;
+    if let Err(err) = inserted {
+        return HttpResponse::InternalServerError().json(format!("Failed to store webhook: {:?}", err));
+    }
+    webhooks.changed();
+
+    info!(id = %webhook.id, addresses = webhook.addresses.len(), "Created webhook");
+    HttpResponse::Created().json(CreatedWebhook { secret, webhook })
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks",
+    tag = "webhooks",
+    security(("api_key" = [])),
+    responses(
+        (status = 200, description = "Webhooks of the API key", body = Vec<Webhook>),
+        (status = 401, description = "Missing or invalid API key", body = ErrorBody),
+        (status = 403, description = "API key lacks the webhooks scope", body = ErrorBody),
+    )
+)]
+pub async fn list_webhooks(req: HttpRequest, db: web::Data<Db>) -> impl Responder {
+    let owner = match owner(&req) {
+        Ok(owner) => owner,
+        Err(err) => return err,
+    };
+    match load(&db, owner, None).await {
+        Ok(webhooks) => HttpResponse::Ok().json(webhooks),
+        Err(err) => HttpResponse::InternalServerError().json(format!("Failed to list webhooks: {:?}", err)),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = String, Path, description = "Webhook id")),
+    security(("api_key" = [])),
+    responses(
+        (status = 200, description = "The webhook", body = Webhook),
+        (status = 404, description = "No webhook with this id for the API key", body = ErrorBody),
+    )
+)]
+pub async fn get_webhook(req: HttpRequest, path: web::Path<String>, db: web::Data<Db>) -> impl Responder {
+    let owner = match owner(&req) {
+        Ok(owner) => owner,
+        Err(err) => return err,
+    };
+    let id = path.into_inner();
+    match load(&db, owner, Some(id.clone())).await {
+        Ok(mut webhooks) => match webhooks.pop() {
+            Some(webhook) => HttpResponse::Ok().json(webhook),
+            None => HttpResponse::NotFound().json(format!("No webhook with id {}", id)),
+        },
+        Err(err) => HttpResponse::InternalServerError().json(format!("Failed to read webhook: {:?}", err)),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = String, Path, description = "Webhook id")),
+    security(("api_key" = [])),
+    responses(
+        (status = 204, description = "Webhook removed, undelivered events are dropped"),
+        (status = 404, description = "No webhook with this id for the API key", body = ErrorBody),
+    )
+)]
+pub async fn delete_webhook(
+    req: HttpRequest,
+    path: web::Path<String>,
+    db: web::Data<Db>,
+    webhooks: web::Data<Webhooks>,
+) -> impl Responder {
+    let owner = match owner(&req) {
+        Ok(owner) => owner,
+        Err(err) => return err,
+    };
+    let id = path.into_inner();
+    let deleted_id = id.clone();
+    let deleted = db
+        .call(move |conn| {
+            let tx = conn.transaction()?;
+            let deleted = tx.execute("DELETE FROM webhooks WHERE id = ?1 AND owner = ?2", params![deleted_id, owner])?;
+            if deleted > 0 {
+                tx.execute("DELETE FROM webhook_addresses WHERE webhook_id = ?1", params![deleted_id])?;
+                tx.execute("DELETE FROM webhook_confirmations WHERE webhook_id = ?1", params![deleted_id])?;
+                tx.execute(
+                    "DELETE FROM webhook_events WHERE webhook_id = ?1 AND delivered_at IS NULL AND failed_at IS NULL",
+                    params![deleted_id],
+                )?;
+            }
+            tx.commit()?;
+            Ok(deleted)
+        })
+        .await;
+
+    match deleted {
+        Ok(0) => HttpResponse::NotFound().json(format!("No webhook with id {}", id)),
+        Ok(_) => {
+            webhooks.changed();
+            info!(id = %id, "Deleted webhook");
+            HttpResponse::NoContent().finish()
+        }
+        Err(err) => HttpResponse::InternalServerError().json(format!("Failed to delete webhook: {:?}", err)),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks/{id}/ping",
+    tag = "webhooks",
+    params(("id" = String, Path, description = "Webhook id")),
+    security(("api_key" = [])),
+    responses(
+        (status = 202, description = "A ping event was queued for delivery", body = QueuedEvent),
+        (status = 404, description = "No webhook with this id for the API key", body = ErrorBody),
+    )
+)]
+pub async fn ping_webhook(req: HttpRequest, path: web::Path<String>, db: web::Data<Db>) -> impl Responder {
+    let owner = match owner(&req) {
+        Ok(owner) => owner,
+        Err(err) => return err,
+    };
+    let id = path.into_inner();
+    let webhook_id = id.clone();
+    let queued = db
+        .call(move |conn| {
+            let tx = conn.transaction()?;
+            let exists = tx
+                .query_row("SELECT 1 FROM webhooks WHERE id = ?1 AND owner = ?2", params![webhook_id, owner], |_| Ok(()))
+                .optional()?;
+            let event_id = match exists {
+                Some(()) => Some(enqueue(&tx, &webhook_id, EVENT_PING, json!({}))?),
+                None => None,
+            };
+            tx.commit()?;
+            Ok(event_id)
+        })
+        .await;
+
+    match queued {
+        Ok(Some(event_id)) => HttpResponse::Accepted().json(QueuedEvent { event_id }),
+        Ok(None) => HttpResponse::NotFound().json(format!("No webhook with id {}", id)),
+        Err(err) => HttpResponse::InternalServerError().json(format!("Failed to queue ping: {:?}", err)),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks/{id}/deliveries",
+    tag = "webhooks",
+    params(("id" = String, Path, description = "Webhook id"), DeliveryQuery),
+    security(("api_key" = [])),
+    responses(
+        (status = 200, description = "Delivery attempts, newest first", body = Vec<Delivery>),
+        (status = 404, description = "No webhook with this id for the API key", body = ErrorBody),
+    )
+)]
+pub async fn list_deliveries(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<DeliveryQuery>,
+    db: web::Data<Db>,
+) -> impl Responder {
+    let owner = match owner(&req) {
+        Ok(owner) => owner,
+        Err(err) => return err,
+    };
+    let id = path.into_inner();
+    let limit = query.limit.unwrap_or(DEFAULT_DELIVERY_LIMIT).clamp(1, MAX_DELIVERY_LIMIT);
+    let webhook_id = id.clone();
+    let deliveries = db
+        .call(move |conn| {
+            let exists = conn
+                .query_row("SELECT 1 FROM webhooks WHERE id = ?1 AND owner = ?2", params![webhook_id, owner], |_| Ok(()))
+                .optional()?;
+            if exists.is_none() {
+                return Ok(None);
+            }
+            let mut stmt = conn.prepare(
+                "SELECT d.event_id, e.event, d.attempt, d.timestamp, d.status, d.error, d.duration_ms,
+                     CASE WHEN e.delivered_at IS NOT NULL THEN 'delivered' WHEN e.failed_at IS NOT NULL THEN 'failed' ELSE 'retrying' END
+                 FROM webhook_deliveries d JOIN webhook_events e ON e.id = d.event_id
+                 WHERE e.webhook_id = ?1 ORDER BY d.timestamp DESC, d.attempt DESC LIMIT ?2",
+            )?;
+            let rows = stmt.query_map(params![webhook_id, limit as i64], |row| {
+                Ok(Delivery {
+                    event_id: row.get(0)?,
+                    event: row.get(1)?,
+                    attempt: row.get(2)?,
+                    timestamp: row.get(3)?,
+                    status: row.get(4)?,
+                    error: row.get(5)?,
+                    duration_ms: row.get::<_, i64>(6)? as u64,
+                    state: row.get(7)?,
+                })
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>().map(Some)
+        })
+        .await;
+
+    match deliveries {
+        Ok(Some(deliveries)) => HttpResponse::Ok().json(deliveries),
+        Ok(None) => HttpResponse::NotFound().json(format!("No webhook with id {}", id)),
+        Err(err) => HttpResponse::InternalServerError().json(format!("Failed to read deliveries: {:?}", err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc;
+    use super::*;
+
+    const SECRET: &str = "whsec_test";
+
+    // Headers (lowercase names) and body of one received POST
+    struct Received {
+        headers: HashMap<String, String>,
+        body: String,
+    }
+
+    async fn read_request(socket: &mut TcpStream) -> Received {
+        let mut buffer = Vec::new();
+        let mut chunk = [0; 4096];
+        loop {
+            if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+                let head = String::from_utf8_lossy(&buffer[..end]).to_string();
+                let headers: HashMap<String, String> = head
+                    .lines()
+                    .skip(1)
+                    .filter_map(|line| line.split_once(':'))
+                    .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+                    .collect();
+                let length = headers.get("content-length").map_or(0, |length| length.parse().unwrap());
+                while buffer.len() < end + 4 + length {
+                    let read = socket.read(&mut chunk).await.unwrap();
+                    buffer.extend_from_slice(&chunk[..read]);
+                }
+                let body = String::from_utf8(buffer[end + 4..end + 4 + length].to_vec()).unwrap();
+                return Received { headers, body };
+            }
+            let read = socket.read(&mut chunk).await.unwrap();
+            assert!(read > 0, "connection closed before the headers ended");
+            buffer.extend_from_slice(&chunk[..read]);
+        }
+    }
+
+    // Local endpoint answering every request with the status, passing the requests on
+    async fn endpoint(status: u16) -> (String, mpsc::UnboundedReceiver<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let received = read_request(&mut socket).await;
+                let _ = sender.send(received);
+                let response = format!("HTTP/1.1 {} Test\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status);
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        (url, receiver)
+    }
+
+    async fn queue_ping(db: &Db, url: &str, attempts: i64) -> String {
+        let url = url.to_string();
+        db.call(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "INSERT INTO webhooks (id, owner, url, secret, min_amount, confirmations, created_at)
+                 VALUES ('hook', 'owner', ?1, ?2, 0, 0, 0)",
+                params![url, SECRET],
+            )?;
+            let id = enqueue(&tx, "hook", EVENT_PING, json!({}))?;
+            tx.execute("UPDATE webhook_events SET attempts = ?1 WHERE id = ?2", params![attempts, id])?;
+            tx.commit()?;
+            Ok(id)
+        })
+            .await
+            .unwrap()
+    }
+
+    // Attempts, next attempt, delivery and failure time of an event
+    async fn event_state(db: &Db, id: &str) -> (i64, i64, Option<i64>, Option<i64>) {
+        let id = id.to_string();
+        db.call(move |conn| {
+            conn.query_row(
+                "SELECT attempts, next_attempt_at, delivered_at, failed_at FROM webhook_events WHERE id = ?1",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+        })
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn retries_back_off_up_to_an_hour() {
+        assert_eq!(retry_delay(1), 10);
+        assert_eq!(retry_delay(2), 20);
+        assert_eq!(retry_delay(3), 40);
+        assert_eq!(retry_delay(9), 2560);
+        assert_eq!(retry_delay(10), RETRY_MAX_SECS);
+        assert_eq!(retry_delay(100), RETRY_MAX_SECS);
+    }
+
+    #[tokio::test]
+    async fn signs_the_delivered_body() {
+        let db = Db::open(":memory:").unwrap();
+        let (url, mut received) = endpoint(200).await;
+        let id = queue_ping(&db, &url, 0).await;
+
+        deliver_due(&db, true).await.unwrap();
+
+        let request = received.recv().await.unwrap();
+        let timestamp: i64 = request.headers["x-xenom-timestamp"].parse().unwrap();
+        assert_eq!(request.headers["x-xenom-signature"], format!("sha256={}", sign(SECRET, timestamp, &request.body)));
+        assert_eq!(request.headers["x-xenom-event"], EVENT_PING);
+        assert_eq!(request.headers["x-xenom-delivery"], id);
+        let payload: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(payload["id"], id.as_str());
+    }
+
+    #[tokio::test]
+    async fn marks_successful_deliveries() {
+        let db = Db::open(":memory:").unwrap();
+        let (url, _received) = endpoint(204).await;
+        let id = queue_ping(&db, &url, 0).await;
+
+        deliver_due(&db, true).await.unwrap();
+
+        let (attempts, _, delivered_at, failed_at) = event_state(&db, &id).await;
+        assert_eq!(attempts, 1);
+        assert!(delivered_at.is_some());
+        assert_eq!(failed_at, None);
+    }
+
+    #[tokio::test]
+    async fn schedules_a_retry_after_an_error_status() {
+        let db = Db::open(":memory:").unwrap();
+        let (url, _received) = endpoint(500).await;
+        let id = queue_ping(&db, &url, 0).await;
+
+        let before = Utc::now().timestamp();
+        deliver_due(&db, true).await.unwrap();
+        let after = Utc::now().timestamp();
+
+        let (attempts, next_attempt_at, delivered_at, failed_at) = event_state(&db, &id).await;
+        assert_eq!(attempts, 1);
+        assert!(next_attempt_at >= before + retry_delay(1) && next_attempt_at <= after + retry_delay(1));
+        assert_eq!((delivered_at, failed_at), (None, None));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_attempt() {
+        let db = Db::open(":memory:").unwrap();
+        let (url, _received) = endpoint(500).await;
+        let id = queue_ping(&db, &url, MAX_ATTEMPTS - 1).await;
+
+        deliver_due(&db, true).await.unwrap();
+
+        let (attempts, _, delivered_at, failed_at) = event_state(&db, &id).await;
+        assert_eq!(attempts, MAX_ATTEMPTS);
+        assert_eq!(delivered_at, None);
+        assert!(failed_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn spent_utxos_are_not_confirmed() {
+        let db = Db::open(":memory:").unwrap();
+        db.call(|conn| {
+            conn.execute(
+                "INSERT INTO webhooks (id, owner, url, secret, min_amount, confirmations, created_at)
+                 VALUES ('hook', 'owner', 'http://example.com', ?1, 0, 10, 0)",
+                params![SECRET],
+            )?;
+            conn.execute("INSERT INTO webhook_addresses (webhook_id, address) VALUES ('hook', 'xenom:watched')", [])
+        })
+            .await
+            .unwrap();
+        let change = |event| UtxoChange {
+            event,
+            address: "xenom:watched".to_string(),
+            transaction_id: "tx".to_string(),
+            index: 0,
+            amount: 1_000,
+            block_daa_score: 100,
+            is_coinbase: false,
+        };
+        let pending = |db: Db| async move {
+            db.call(|conn| conn.query_row("SELECT COUNT(*) FROM webhook_confirmations", [], |row| row.get::<_, i64>(0)))
+                .await
+                .unwrap()
+        };
+
+        on_utxos_changed(&db, vec![change(EVENT_UTXO_ADDED)]).await.unwrap();
+        assert_eq!(pending(db.clone()).await, 1);
+        on_utxos_changed(&db, vec![change(EVENT_UTXO_SPENT)]).await.unwrap();
+        assert_eq!(pending(db.clone()).await, 0);
+
+        on_virtual_daa_score(&db, 200).await.unwrap();
+        let confirmed = db
+            .call(|conn| {
+                conn.query_row("SELECT COUNT(*) FROM webhook_events WHERE event = ?1", params![EVENT_UTXO_CONFIRMED], |row| {
+                    row.get::<_, i64>(0)
+                })
+            })
+            .await
+            .unwrap();
+        assert_eq!(confirmed, 0);
+    }
+
+    #[test]
+    fn classifies_internal_addresses() {
+        let internal = [
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ];
+        for ip in internal {
+            assert!(is_internal(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["1.1.1.1", "100.128.0.1", "2606:4700::1111", "::ffff:8.8.8.8"] {
+            assert!(!is_internal(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_internal_destinations_unless_allowed() {
+        for url in ["http://127.0.0.1:8080/hook", "http://[::1]/hook", "https://10.1.2.3/hook", "http://localhost/hook"] {
+            let url = reqwest::Url::parse(url).unwrap();
+            assert!(check_destination(&url, false).await.is_err(), "{}", url);
+            assert!(check_destination(&url, true).await.is_ok(), "{}", url);
+        }
+        let public = reqwest::Url::parse("https://1.1.1.1/hook").unwrap();
+        assert_eq!(check_destination(&public, false).await, Ok(vec!["1.1.1.1:443".parse().unwrap()]));
+    }
+
+    #[tokio::test]
+    async fn refuses_delivery_to_internal_destinations() {
+        let db = Db::open(":memory:").unwrap();
+        // Stored while the host still resolved to a public address
+        let (url, mut received) = endpoint(200).await;
+        let id = queue_ping(&db, &url.replace("127.0.0.1", "localhost"), 0).await;
+
+        deliver_due(&db, false).await.unwrap();
+
+        let (attempts, _, delivered_at, _) = event_state(&db, &id).await;
+        assert_eq!(attempts, 1);
+        assert_eq!(delivered_at, None);
+        let (status, error) = db
+            .call(move |conn| {
+                conn.query_row("SELECT status, error FROM webhook_deliveries WHERE event_id = ?1", params![id], |row| {
+                    Ok((row.get::<_, Option<i64>>(0)?, row.get::<_, String>(1)?))
+                })
+            })
+            .await
+            .unwrap();
+        assert_eq!(status, None);
+        assert!(error.contains("loopback"), "{}", error);
+        assert!(received.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn delivers_to_the_checked_addresses() {
+        let db = Db::open(":memory:").unwrap();
+        let (url, mut received) = endpoint(204).await;
+        let id = queue_ping(&db, &url.replace("127.0.0.1", "localhost"), 0).await;
+
+        deliver_due(&db, true).await.unwrap();
+
+        assert!(received.recv().await.is_some());
+        let (_, _, delivered_at, _) = event_state(&db, &id).await;
+        assert!(delivered_at.is_some());
+    }
+}
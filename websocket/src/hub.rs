@@ -0,0 +1,140 @@
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use anyhow::Context;
+use kaspa_notify::scope::{BlockAddedScope, Scope};
+use kaspa_rpc_core::notify::connection::{ChannelConnection, ChannelType};
+use kaspa_rpc_core::{Notification, RpcBlock};
+use kaspa_wrpc_client::prelude::RpcApi;
+use kaspa_wrpc_client::KaspaRpcClient;
+use serde_json::json;
+use tokio::sync::{broadcast, Mutex};
+use tracing::{error, info};
+use crate::{get_client, metrics};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+// Every client asking for the last blocks within this window gets the same answer
+const LAST_BLOCKS_TTL: Duration = Duration::from_secs(1);
+
+// What happens to a client that falls further behind than the broadcast buffer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SlowConsumer {
+    // Skip the missed messages and tell the client how many it lost
+    Drop,
+    // Close the connection with the reason
+    Disconnect,
+}
+
+impl SlowConsumer {
+    // From XENOM_WS_SLOW_CONSUMER, drop unless set to disconnect
+    pub fn from_env() -> SlowConsumer {
+        match std::env::var("XENOM_WS_SLOW_CONSUMER").as_deref() {
+            Ok("disconnect") => SlowConsumer::Disconnect,
+            _ => SlowConsumer::Drop,
+        }
+    }
+}
+
+// One upstream node connection shared by every websocket client: added blocks are fanned out through a
+// bounded broadcast channel, and "last-blocks" requests go over the same connection
+pub struct Hub {
+    sender: broadcast::Sender<Arc<str>>,
+    client: RwLock<Option<Arc<KaspaRpcClient>>>,
+    last_blocks: Mutex<Option<(Instant, Arc<str>)>>,
+}
+
+// Header fields an explorer feed needs, without the transactions
+fn compact_block(block: &RpcBlock) -> serde_json::Value {
+    json!({
+        "hash": block.header.hash,
+        "timestamp": block.header.timestamp,
+        "daaScore": block.header.daa_score,
+        "blueScore": block.header.blue_score,
+        "parents": block.header.parents_by_level.first().cloned().unwrap_or_default(),
+        "transactionCount": block.transactions.len(),
+    })
+}
+
+impl Hub {
+    pub fn new(capacity: usize) -> Arc<Hub> {
+        let (sender, _) = broadcast::channel(capacity);
+        Arc::new(Hub { sender, client: RwLock::new(None), last_blocks: Mutex::new(None) })
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<str>> {
+        self.sender.subscribe()
+    }
+
+    pub fn broadcast(&self, message: Arc<str>) {
+        // Sending only fails without clients, nobody misses the block then
+        let _ = self.sender.send(message);
+    }
+
+    // Keeps the upstream subscription alive, reconnecting whenever the node goes away
+    pub async fn run(self: Arc<Hub>) {
+        loop {
+            if let Err(err) = self.forward().await {
+                error!(error = ?err, "Upstream subscription failed");
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    async fn forward(&self) -> Result<(), anyhow::Error> {
+        let client = Arc::new(get_client().await?);
+        let (sender, receiver) = async_channel::unbounded();
+        let listener_id = client.register_new_listener(ChannelConnection::new("websocket-hub", sender, ChannelType::Closable));
+        if let Err(err) = client.start_notify(listener_id, Scope::BlockAdded(BlockAddedScope {})).await {
+            let _ = client.disconnect().await;
+            return Err(err).context("Failed to subscribe to added blocks");
+        }
+        *self.client.write().unwrap() = Some(client.clone());
+        metrics().upstream_connected.set(1);
+        info!("Subscribed to added blocks");
+
+        let closed = loop {
+            match receiver.recv().await {
+                Ok(Notification::BlockAdded(notification)) => {
+                    let message = json!({
+                        "status": "success",
+                        "block-added": compact_block(&notification.block),
+                    });
+                    self.broadcast(Arc::from(message.to_string()));
+                }
+                Ok(_) => {}
+                Err(_) => break anyhow::anyhow!("Node notification channel closed"),
+            }
+        };
+
+        *self.client.write().unwrap() = None;
+        metrics().upstream_connected.set(0);
+        let _ = client.unregister_listener(listener_id).await;
+        let _ = client.disconnect().await;
+        Err(closed)
+    }
+
+    // Blocks from the current tip, fetched once per window however many clients ask
+    pub async fn last_blocks(&self) -> Result<Arc<str>, anyhow::Error> {
+        let mut cached = self.last_blocks.lock().await;
+        if let Some((fetched_at, response)) = cached.as_ref() {
+            if fetched_at.elapsed() < LAST_BLOCKS_TTL {
+                metrics().last_blocks_hits.inc();
+                return Ok(response.clone());
+            }
+        }
+        metrics().last_blocks_misses.inc();
+
+        let client = self.client.read().unwrap().clone().context("Not connected to the node")?;
+        let block_dag_info = client.get_block_dag_info().await
+            .context("Failed to fetch block DAG info")?;
+        let latest_block_hash = *block_dag_info.tip_hashes.first().context("Node reported no tip hashes")?;
+        let blocks_info = client.get_blocks(Some(latest_block_hash), true, true).await
+            .context("Failed to fetch blocks")?;
+
+        let response: Arc<str> = Arc::from(json!({
+            "status": "success",
+            "last-blocks": blocks_info,
+        }).to_string());
+        *cached = Some((Instant::now(), response.clone()));
+        Ok(response)
+    }
+}
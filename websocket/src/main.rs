@@ -1,35 +1,181 @@
 use tokio::net::TcpListener;
-use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Message};
 use tokio_tungstenite::accept_async;
-use futures_util::{StreamExt, SinkExt};
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
 use serde_json::json;
 use anyhow::Context;
+use std::sync::Arc;
 use std::time::Duration;
 use kaspa_wrpc_client::{KaspaRpcClient, WrpcEncoding};
-use kaspa_wrpc_client::prelude::{ConnectOptions, RpcApi};
+use kaspa_wrpc_client::prelude::ConnectOptions;
 use tokio::net::TcpStream;
-use log::{error, info};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::timeout;
+use tracing::{debug, error, info, info_span, warn, Instrument};
+use tracing_subscriber::EnvFilter;
+use actix_web::{web, App, HttpResponse, HttpServer};
+use prometheus::{Encoder, IntCounter, IntGauge, Registry, TextEncoder};
+use std::sync::OnceLock;
+use crate::hub::{Hub, SlowConsumer};
+
+mod hub;
+
+// Messages a client may fall behind the block stream before the slow consumer policy applies
+const DEFAULT_BUFFER: usize = 256;
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_NODE_URL: &str = "ws://eu.losmuchachos.digital:19910";
+const DEFAULT_PORT: u16 = 18910;
+const DEFAULT_METRICS_PORT: u16 = 18911;
+
+struct Metrics {
+    registry: Registry,
+    clients: IntGauge,
+    connections: IntCounter,
+    messages_sent: IntCounter,
+    connection_errors: IntCounter,
+    dropped_messages: IntCounter,
+    slow_disconnects: IntCounter,
+    upstream_connected: IntGauge,
+    last_blocks_hits: IntCounter,
+    last_blocks_misses: IntCounter,
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(|| {
+        let registry = Registry::new_custom(Some("xenom_websocket".to_string()), None).unwrap();
+        let clients = IntGauge::new("clients", "Connected websocket clients").unwrap();
+        let connections = IntCounter::new("connections_total", "Websocket connections accepted").unwrap();
+        let messages_sent = IntCounter::new("messages_sent_total", "Messages sent to websocket clients").unwrap();
+        let connection_errors =
+            IntCounter::new("connection_errors_total", "Websocket connections that ended with an error").unwrap();
+        registry.register(Box::new(clients.clone())).unwrap();
+        registry.register(Box::new(connections.clone())).unwrap();
+        registry.register(Box::new(messages_sent.clone())).unwrap();
+        let dropped_messages =
+            IntCounter::new("dropped_messages_total", "Broadcast messages skipped for clients that fell behind").unwrap();
+        let slow_disconnects =
+            IntCounter::new("slow_disconnects_total", "Clients disconnected for not keeping up").unwrap();
+        let upstream_connected = IntGauge::new("upstream_connected", "Whether the shared node subscription is up").unwrap();
+        registry.register(Box::new(connection_errors.clone())).unwrap();
+        registry.register(Box::new(dropped_messages.clone())).unwrap();
+        registry.register(Box::new(slow_disconnects.clone())).unwrap();
+        let last_blocks_hits =
+            IntCounter::new("last_blocks_cache_hits_total", "last-blocks answers served from the cached snapshot").unwrap();
+        let last_blocks_misses =
+            IntCounter::new("last_blocks_cache_misses_total", "last-blocks answers fetched from the node").unwrap();
+        registry.register(Box::new(upstream_connected.clone())).unwrap();
+        registry.register(Box::new(last_blocks_hits.clone())).unwrap();
+        registry.register(Box::new(last_blocks_misses.clone())).unwrap();
+        Metrics {
+            registry,
+            clients,
+            connections,
+            messages_sent,
+            connection_errors,
+            dropped_messages,
+            slow_disconnects,
+            upstream_connected,
+            last_blocks_hits,
+            last_blocks_misses,
+        }
+    })
+}
+
+// Keeps the connected clients gauge accurate whichever way a connection ends
+struct ClientGuard;
+
+impl ClientGuard {
+    fn new() -> ClientGuard {
+        metrics().connections.inc();
+        metrics().clients.inc();
+        ClientGuard
+    }
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        metrics().clients.dec();
+    }
+}
+
+async fn export_metrics() -> HttpResponse {
+    let mut buffer = Vec::new();
+    if let Err(err) = TextEncoder::new().encode(&metrics().registry.gather(), &mut buffer) {
+        return HttpResponse::InternalServerError().body(format!("Failed to encode metrics: {}", err));
+    }
+    HttpResponse::Ok().content_type(prometheus::TEXT_FORMAT).body(buffer)
+}
+
+// Log level from RUST_LOG or XENOM_LOG, JSON lines when XENOM_LOG_FORMAT=json
+fn init_logging() {
+    let level = std::env::var("XENOM_LOG").unwrap_or_else(|_| "info".to_string());
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level));
+    if std::env::var("XENOM_LOG_FORMAT").map_or(false, |format| format == "json") {
+        tracing_subscriber::fmt().json().with_current_span(true).with_span_list(false).with_env_filter(filter).init();
+    } else {
+        tracing_subscriber::fmt().with_env_filter(filter).init();
+    }
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
 
 #[tokio::main]
 async fn main() {
-    let addr = "0.0.0.0:18910".to_string();
-    let listener = TcpListener::bind(&addr).await.unwrap();
-    println!("WebSocket server is running on ws://{}", addr);
-
-    while let Ok((stream, _)) = listener.accept().await {
-        tokio::spawn(async move {
-            if let Err(e) = handle_connection(stream).await {
-                error!("Error handling connection: {}", e);
+    init_logging();
+    let addr = ("0.0.0.0", env_or("XENOM_WS_PORT", DEFAULT_PORT));
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!(error = %err, port = addr.1, "Failed to bind the websocket port");
+            return;
+        }
+    };
+    info!("WebSocket server is running on ws://{}:{}", addr.0, addr.1);
+
+    // Prometheus metrics are served over plain HTTP next to the websocket port
+    let metrics_port = env_or("XENOM_WS_METRICS_PORT", DEFAULT_METRICS_PORT);
+    match HttpServer::new(|| App::new().route("/metrics", web::get().to(export_metrics))).bind(("0.0.0.0", metrics_port)) {
+        Ok(server) => {
+            tokio::spawn(server.run());
+        }
+        Err(err) => error!(error = %err, port = metrics_port, "Failed to bind the metrics port, serving without metrics"),
+    }
+
+    // All clients share one node subscription instead of opening a connection each
+    let buffer = env_or("XENOM_WS_BUFFER", DEFAULT_BUFFER);
+    let slow_consumer = SlowConsumer::from_env();
+    let hub = Hub::new(buffer);
+    tokio::spawn(hub.clone().run());
+
+    while let Ok((stream, peer)) = listener.accept().await {
+        let span = info_span!("connection", peer = %peer);
+        let hub = hub.clone();
+        tokio::spawn(
+            async move {
+                let _guard = ClientGuard::new();
+                if let Err(e) = handle_connection(stream, hub, slow_consumer).await {
+                    metrics().connection_errors.inc();
+                    error!(error = ?e, "Error handling connection");
+                }
             }
-        });
+                .instrument(span),
+        );
     }
 }
 
 async fn get_client() -> Result<KaspaRpcClient, anyhow::Error> {
+    // Same node as the API unless XENOM_NODE_URL says otherwise
+    let url = std::env::var("XENOM_NODE_URL").unwrap_or_else(|_| DEFAULT_NODE_URL.to_string());
     // Create a new KaspaRpcClient
     let kaspa_rpc = KaspaRpcClient::new(
         WrpcEncoding::SerdeJson,
-        Some("ws://eu.losmuchachos.digital:19910"),
+        Some(&url),
         None,
         None,
         None,
@@ -52,88 +198,176 @@ async fn get_client() -> Result<KaspaRpcClient, anyhow::Error> {
     Ok(kaspa_rpc)
 }
 
-async fn handle_connection(stream: TcpStream) -> Result<(), anyhow::Error> {
-    // Accept the websocket connection
-    let ws_stream = accept_async(stream)
-        .await
-        .context("WebSocket connection failed")?;
-
-    let (mut write, mut read) = ws_stream.split();
-
-    // Get client and handle error case
-    let client = get_client().await?;
-
-    // Fetch block DAG info and handle potential error
-    let block_dag_info = client.get_block_dag_info().await
-        .context("Failed to fetch block DAG info")?;
-
-    let latest_block_hash = block_dag_info.tip_hashes[0].clone();
+// Sends one message, giving up on a client that stops reading so it can't hold on to its task forever
+async fn send<W>(write: &mut W, text: String) -> Result<(), anyhow::Error>
+where
+    W: Sink<Message> + Unpin,
+    W::Error: std::error::Error + Send + Sync + 'static,
+{
+    match timeout(SEND_TIMEOUT, write.send(Message::Text(text))).await {
+        Ok(sent) => sent.context("Failed to send message")?,
+        Err(_) => {
+            metrics().slow_disconnects.inc();
+            return Err(anyhow::anyhow!("Client did not accept a message within {:?}", SEND_TIMEOUT));
+        }
+    }
+    metrics().messages_sent.inc();
+    Ok(())
+}
 
-    // Fetch the latest blocks and handle potential error
-    let blocks_info = match client.get_blocks(Some(latest_block_hash), true, true).await {
-        Ok(blocks) => blocks,
+// Answers the initial snapshot and "last-blocks" from the hub's shared node connection
+async fn send_last_blocks<W>(hub: &Hub, write: &mut W) -> Result<(), anyhow::Error>
+where
+    W: Sink<Message> + Unpin,
+    W::Error: std::error::Error + Send + Sync + 'static,
+{
+    match hub.last_blocks().await {
+        Ok(response) => send(write, response.to_string()).await,
         Err(err) => {
             let error_response = json!({
                 "status": "error",
-                "message": format!("Failed to fetch blocks: {}", err),
+                "message": format!("Failed to fetch blocks: {:#}", err),
             });
-            write.send(Message::Text(error_response.to_string())).await
-                .context("Failed to send error message")?;
-            return Err(anyhow::anyhow!("Failed to fetch blocks: {}", err));
+            send(write, error_response.to_string()).await
         }
-    };
+    }
+}
 
-    // If successful, serialize the response and send it over WebSocket
-    let response = json!({
-        "status": "success",
-        "last-blocks": blocks_info,
-    });
+async fn handle_connection(stream: TcpStream, hub: Arc<Hub>, slow_consumer: SlowConsumer) -> Result<(), anyhow::Error> {
+    // Accept the websocket connection
+    let ws_stream = accept_async(stream)
+        .await
+        .context("WebSocket connection failed")?;
 
-    write.send(Message::Text(response.to_string())).await
-        .context("Failed to send blocks response")?;
+    let (mut write, mut read) = ws_stream.split();
 
-    // Handle incoming WebSocket messages
-    while let Some(Ok(message)) = read.next().await {
-        if let Message::Text(text) = message {
-            println!("Received message: {}", text);
+    // Subscribe before the snapshot so no block falls between the two
+    let mut blocks = hub.subscribe();
+    send_last_blocks(&hub, &mut write).await?;
+    serve(&mut write, &mut read, &hub, &mut blocks, slow_consumer).await?;
 
-            if text == "join-room" {
-                // Example room join logic
-                write.send(Message::Text("Joined room".to_string())).await
-                    .context("Failed to send join-room message")?;
-            }
-            if text == "last-blocks" {
-                let block_dag_info = client.get_block_dag_info().await
-                    .context("Failed to fetch block DAG info")?;
+    info!("WebSocket connection closed");
+    Ok(())
+}
 
-                let latest_block_hash = block_dag_info.tip_hashes[0].clone();
+// Answers the client's requests and forwards the block stream until either side goes away
+async fn serve<W, R, E>(
+    write: &mut W,
+    read: &mut R,
+    hub: &Hub,
+    blocks: &mut broadcast::Receiver<Arc<str>>,
+    slow_consumer: SlowConsumer,
+) -> Result<(), anyhow::Error>
+where
+    W: Sink<Message> + Unpin,
+    W::Error: std::error::Error + Send + Sync + 'static,
+    R: Stream<Item = Result<Message, E>> + Unpin,
+{
+    loop {
+        tokio::select! {
+            message = read.next() => {
+                let Some(Ok(message)) = message else { break };
+                if let Message::Text(text) = message {
+                    debug!(message = %text, "Received message");
 
-                // Fetch the latest blocks and handle potential error
-                let blocks_info = match client.get_blocks(Some(latest_block_hash), true, true).await {
-                    Ok(blocks) => blocks,
-                    Err(err) => {
-                        let error_response = json!({
-                "status": "error",
-                "message": format!("Failed to fetch blocks: {}", err),
-            });
-                        write.send(Message::Text(error_response.to_string())).await
-                            .context("Failed to send error message")?;
-                        return Err(anyhow::anyhow!("Failed to fetch blocks: {}", err));
+                    if text == "join-room" {
+                        // Example room join logic
+                        send(write, "Joined room".to_string()).await?;
+                    }
+                    // Still answered for clients that poll
+                    if text == "last-blocks" {
+                        send_last_blocks(hub, write).await?;
                     }
-                };
+                }
+            }
+            block = blocks.recv() => {
+                match block {
+                    Ok(message) => send(write, message.to_string()).await?,
+                    Err(RecvError::Lagged(skipped)) => {
+                        metrics().dropped_messages.inc_by(skipped);
+                        warn!(skipped, "Client fell behind the block stream");
+                        match slow_consumer {
+                            SlowConsumer::Drop => {
+                                let notice = json!({
+                                    "status": "warning",
+                                    "message": format!("Client too slow, {} messages dropped", skipped),
+                                });
+                                send(write, notice.to_string()).await?;
+                            }
+                            SlowConsumer::Disconnect => {
+                                metrics().slow_disconnects.inc();
+                                let close = CloseFrame {
+                                    code: CloseCode::Policy,
+                                    reason: format!("Client too slow, {} messages dropped", skipped).into(),
+                                };
+                                let _ = timeout(SEND_TIMEOUT, write.send(Message::Close(Some(close)))).await;
+                                info!("Disconnected slow client");
+                                return Ok(());
+                            }
+                        }
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
+    }
+    Ok(())
+}
 
-                // If successful, serialize the response and send it over WebSocket
-                let response = json!({
-        "status": "success",
-        "last-blocks": blocks_info,
-    });
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::pin::pin;
+    use futures_util::{sink, stream};
+    use tokio_tungstenite::tungstenite;
+    use super::*;
 
-                write.send(Message::Text(response.to_string())).await
-                    .context("Failed to send blocks response")?;
-            }
+    // Runs the receive loop of a silent client `behind` messages behind a hub buffering two. The
+    // loop only returns on its own when it disconnects the client.
+    async fn lagging_client(slow_consumer: SlowConsumer, behind: usize) -> (Vec<Message>, bool) {
+        let hub = Hub::new(2);
+        let mut blocks = hub.subscribe();
+        for n in 0..behind {
+            hub.broadcast(Arc::from(format!("block {}", n)));
         }
+        let mut written = Vec::new();
+        let disconnected = {
+            let mut write = pin!(sink::unfold(&mut written, |written, message| async move {
+                written.push(message);
+                Ok::<_, Infallible>(written)
+            }));
+            let mut read = stream::pending::<Result<Message, tungstenite::Error>>();
+            let served = serve(&mut write, &mut read, &hub, &mut blocks, slow_consumer);
+            timeout(Duration::from_millis(100), served).await.is_ok()
+        };
+        (written, disconnected)
     }
 
-    info!("WebSocket connection closed");
-    Ok(())
+    #[tokio::test]
+    async fn drops_missed_blocks_and_keeps_the_client() {
+        let (messages, disconnected) = lagging_client(SlowConsumer::Drop, 5).await;
+        assert!(!disconnected);
+        let Message::Text(notice) = &messages[0] else { panic!("{:?}", messages) };
+        let notice: serde_json::Value = serde_json::from_str(notice).unwrap();
+        assert_eq!(notice["status"], "warning");
+        assert_eq!(notice["message"], "Client too slow, 3 messages dropped");
+        assert_eq!(messages[1..], [Message::Text("block 3".to_string()), Message::Text("block 4".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn disconnects_a_lagging_client_when_asked_to() {
+        let (messages, disconnected) = lagging_client(SlowConsumer::Disconnect, 5).await;
+        assert!(disconnected);
+        let close = CloseFrame { code: CloseCode::Policy, reason: "Client too slow, 3 messages dropped".into() };
+        assert_eq!(messages, vec![Message::Close(Some(close))]);
+    }
+
+    #[tokio::test]
+    async fn forwards_blocks_while_within_the_buffer() {
+        for slow_consumer in [SlowConsumer::Drop, SlowConsumer::Disconnect] {
+            let (messages, disconnected) = lagging_client(slow_consumer, 2).await;
+            assert!(!disconnected);
+            assert_eq!(messages, vec![Message::Text("block 0".to_string()), Message::Text("block 1".to_string())]);
+        }
+    }
 }